@@ -10,6 +10,9 @@ pub enum Error {
     #[error(transparent)]
     Read(#[from] ReadError),
 
+    #[error(transparent)]
+    Write(#[from] WriteError),
+
     #[error(transparent)]
     Io(#[from] io::Error),
 }
@@ -28,4 +31,53 @@ pub enum ReadError {
 
     #[error("missing nul")]
     MissingNul,
+
+    #[error("invalid patch")]
+    InvalidPatch,
+
+    #[error("the patch does not apply to this archive")]
+    PatchMismatch,
+
+    #[error("invalid layout")]
+    InvalidLayout,
+
+    #[error("invalid manifest: {0}")]
+    InvalidManifest(String),
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum WriteError {
+    #[error("invalid path: {0:?}")]
+    InvalidPath(String),
+
+    #[error("{0} has no name")]
+    MissingName(String),
+
+    #[error("{0:?} has the same hash as {1:?}")]
+    HashCollision(String, String),
+
+    #[error("the archive is too large for its format")]
+    TooLarge,
+
+    #[error("invalid texture: {0:?}")]
+    InvalidTexture(String),
+
+    #[error("{0:?} cannot be stored in this format of archive")]
+    FormatMismatch(String),
+
+    #[error("{0:?} is not in the archive")]
+    NotFound(String),
+
+    #[error("{0:?} is already in the archive")]
+    AlreadyExists(String),
+
+    #[error("{0:?} is larger than the archive size limit")]
+    ExceedsLimit(String),
+
+    #[error("{0:?} does not match its layout")]
+    LayoutMismatch(String),
+
+    #[error("{0:?} is stored more than once")]
+    DuplicatePath(String),
 }
@@ -1,12 +1,18 @@
 pub mod helpers;
+pub mod merge;
 pub mod path;
+pub mod pipeline;
+pub mod policy;
 pub mod str;
 pub mod string;
+pub mod verify;
+pub mod vfs;
+pub mod write;
 
 mod error;
 mod read;
 
-pub use error::{Error, ReadError, Result};
+pub use error::{Error, ReadError, Result, WriteError};
 pub use read::{Archive, Entries, Entry};
 
 pub mod detail {
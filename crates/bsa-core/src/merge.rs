@@ -0,0 +1,23 @@
+//! Types shared by the merge operations of every archive format.
+
+/// Which archive's file is kept when several of the archives being merged contain the
+/// same path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precedence {
+    /// The file from the first archive containing the path.
+    First,
+    /// The file from the last archive containing the path, the way the game resolves
+    /// archives later in the load order.
+    #[default]
+    Last,
+}
+
+/// What a merge did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// Files in the merged archive.
+    pub files: usize,
+    /// Files left out because another archive's file at the same path took
+    /// precedence.
+    pub overridden: usize,
+}
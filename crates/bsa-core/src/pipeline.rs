@@ -0,0 +1,124 @@
+//! Runs jobs on a thread pool, and hands back their results in the order the jobs
+//! were submitted.
+//!
+//! Writers use this to read and compress files on several threads, while writing the
+//! archive sequentially on one. See `performance.md` for the reasoning behind this
+//! strategy.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use threadpool::ThreadPool;
+
+/// A thread pool whose results are taken in submission order.
+///
+/// At most `capacity` jobs are in flight at once, counting jobs that have finished
+/// but whose results have not been taken. This bounds the memory held by results
+/// waiting on an earlier, slower job.
+pub struct Pipeline<T> {
+    pool: ThreadPool,
+    /// The index and result of every finished job, or [None] if it panicked.
+    sender: Sender<(usize, Option<T>)>,
+    receiver: Receiver<(usize, Option<T>)>,
+    capacity: usize,
+    submitted: usize,
+    taken: usize,
+    finished: BTreeMap<usize, T>,
+}
+
+impl<T: 'static + Send> Pipeline<T> {
+    pub fn new(threads: usize, capacity: usize) -> Pipeline<T> {
+        let (sender, receiver) = channel();
+        Pipeline {
+            pool: ThreadPool::new(threads.max(1)),
+            sender,
+            receiver,
+            capacity: capacity.max(1),
+            submitted: 0,
+            taken: 0,
+            finished: BTreeMap::new(),
+        }
+    }
+
+    /// Whether as many jobs are in flight as the capacity allows. The next result
+    /// must be taken before submitting another job.
+    pub fn is_full(&self) -> bool {
+        self.submitted - self.taken >= self.capacity
+    }
+
+    /// Whether every result has been taken.
+    pub fn is_empty(&self) -> bool {
+        self.submitted == self.taken
+    }
+
+    /// Run `job` on the pool.
+    ///
+    /// # Panics
+    /// If the pipeline is full.
+    pub fn submit<F>(&mut self, job: F)
+    where
+        F: 'static + Send + FnOnce() -> T,
+    {
+        assert!(!self.is_full(), "pipeline is full");
+        let index = self.submitted;
+        self.submitted += 1;
+        let sender = self.sender.clone();
+        self.pool.execute(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(job)).ok();
+            // The receiver is gone if the pipeline was dropped early, after an error.
+            let _ = sender.send((index, result));
+        });
+    }
+
+    /// The result of the oldest job whose result has not been taken, waiting for it
+    /// to finish. Returns `None` if there is no such job, and an error if any job
+    /// panicked.
+    pub fn next_result(&mut self) -> Option<io::Result<T>> {
+        if self.is_empty() {
+            return None;
+        }
+        while !self.finished.contains_key(&self.taken) {
+            let (index, result) = match self.receiver.recv() {
+                Ok((index, Some(result))) => (index, result),
+                Ok((_, None)) => return Some(Err(io::Error::other("a pipeline job panicked"))),
+                Err(e) => return Some(Err(io::Error::other(e))),
+            };
+            self.finished.insert(index, result);
+        }
+        let result = self.finished.remove(&self.taken)?;
+        self.taken += 1;
+        Some(Ok(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pipeline;
+
+    #[test]
+    fn test_order() {
+        let mut pipeline = Pipeline::new(4, 8);
+        for i in 0..8u64 {
+            pipeline.submit(move || {
+                std::thread::sleep(std::time::Duration::from_millis(8 - i));
+                i
+            });
+        }
+        let results: Vec<_> = (0..8)
+            .map(|_| pipeline.next_result().unwrap().unwrap())
+            .collect();
+        assert_eq!(results, [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert!(pipeline.next_result().is_none());
+    }
+
+    #[test]
+    fn test_panic() {
+        let mut pipeline = Pipeline::new(2, 2);
+        pipeline.submit(|| panic!("job failed"));
+        assert!(pipeline.next_result().unwrap().is_err());
+    }
+}
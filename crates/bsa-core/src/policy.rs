@@ -0,0 +1,105 @@
+//! Rules deciding which files of an archive are compressed, and how.
+
+use std::collections::HashMap;
+
+/// The zlib level used when none is set, the same as zlib's own default.
+pub const DEFAULT_ZLIB_LEVEL: u32 = 6;
+/// The LZ4 level used when none is set, which selects the fast encoder.
+pub const DEFAULT_LZ4_LEVEL: u32 = 0;
+
+/// Decides whether each file of an archive is compressed.
+///
+/// A file follows the rule for its extension if there is one, and the writer's
+/// default otherwise. A file that does not compress well enough is stored
+/// uncompressed instead, when a minimum saving is set.
+///
+/// Each codec has a level of its own. Version 105 archives are compressed with LZ4
+/// frames and use the LZ4 level, while other archives use the zlib level. Tools taking
+/// a level from their users should reject the one that does not apply to an archive
+/// rather than ignore it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionPolicy {
+    /// Whether to compress files, by lower case extension.
+    extensions: HashMap<String, bool>,
+    min_savings: Option<f64>,
+    zlib_level: u32,
+    lz4_level: u32,
+}
+
+impl CompressionPolicy {
+    /// A policy without rules, which compresses every file the writer compresses by
+    /// default.
+    pub fn new() -> CompressionPolicy {
+        CompressionPolicy {
+            extensions: HashMap::new(),
+            min_savings: None,
+            zlib_level: DEFAULT_ZLIB_LEVEL,
+            lz4_level: DEFAULT_LZ4_LEVEL,
+        }
+    }
+
+    /// Set whether files with an extension, with or without the leading period, are
+    /// compressed regardless of the writer's default.
+    pub fn set_extension(&mut self, extension: &str, compressed: bool) {
+        let extension = extension.trim_start_matches('.').to_lowercase();
+        self.extensions.insert(extension, compressed);
+    }
+
+    /// Whether the file at `path` is compressed, if there is a rule for its
+    /// extension.
+    pub fn extension_rule(&self, path: &str) -> Option<bool> {
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+        let (_, extension) = name.rsplit_once('.')?;
+        self.extensions.get(&extension.to_lowercase()).copied()
+    }
+
+    pub fn min_savings(&self) -> Option<f64> {
+        self.min_savings
+    }
+
+    /// Set the fraction of its size that compression must save for a file to be
+    /// stored compressed, such as `0.05` for 5%. Files that save less are stored
+    /// uncompressed. With no minimum, files are stored compressed whenever that saves
+    /// any space, and writers never store a file larger than its contents.
+    pub fn set_min_savings(&mut self, min_savings: Option<f64>) {
+        self.min_savings = min_savings;
+    }
+
+    /// Whether a file of `uncompressed` bytes is worth storing as `compressed` bytes.
+    pub fn keeps_compressed(&self, uncompressed: u64, compressed: u64) -> bool {
+        match self.min_savings {
+            Some(min_savings) => {
+                let saved = uncompressed as f64 - compressed as f64;
+                saved > 0.0 && saved >= uncompressed as f64 * min_savings
+            }
+            None => true,
+        }
+    }
+
+    pub fn zlib_level(&self) -> u32 {
+        self.zlib_level
+    }
+
+    /// Set the zlib level, from 0 for no compression to 9 for the smallest output.
+    /// Larger levels are treated as 9.
+    pub fn set_zlib_level(&mut self, level: u32) {
+        self.zlib_level = level.min(9);
+    }
+
+    pub fn lz4_level(&self) -> u32 {
+        self.lz4_level
+    }
+
+    /// Set the LZ4 level. Levels below 3 use the fast encoder, and levels from 3 to 12
+    /// the high compression encoder, with 12 for the smallest output. Larger levels
+    /// are treated as 12.
+    pub fn set_lz4_level(&mut self, level: u32) {
+        self.lz4_level = level.min(12);
+    }
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     borrow::Cow,
     fs::{self, File},
-    io::Write,
+    io::{self, Write},
     path::Path,
 };
 
@@ -82,8 +82,8 @@ pub struct Entry<'a, A: ?Sized + Archive> {
     index: A::Index,
 }
 
-impl<A: ?Sized + Archive> Entry<'_, A> {
-    pub fn new(imp: &dyn EntriesImpl<A>, index: A::Index) -> Entry<A> {
+impl<'a, A: ?Sized + Archive> Entry<'a, A> {
+    pub fn new(imp: &'a dyn EntriesImpl<A>, index: A::Index) -> Entry<'a, A> {
         Entry { imp, index }
     }
 
@@ -96,7 +96,7 @@ impl<A: ?Sized + Archive> Entry<'_, A> {
     ///
     /// # Notes
     /// The returned name is already normalized, so it is safe to use it directly.
-    pub fn name(&self) -> Cow<str> {
+    pub fn name(&self) -> Cow<'a, str> {
         self.imp.name(self.index)
     }
 
@@ -109,6 +109,11 @@ impl<A: ?Sized + Archive> Entry<'_, A> {
     pub fn extract_to<W: Write>(&self, out: &mut W) -> Result<()> {
         self.imp.extract_to(self.index, out)
     }
+
+    /// Get the size of the contents of this entry, as extracted.
+    pub fn size(&self) -> Result<u64> {
+        self.imp.size(self.index)
+    }
 }
 
 /// This is a helper trait for implementing the [Entries] type.
@@ -136,6 +141,30 @@ pub trait EntriesImpl<A: ?Sized + Archive> {
     }
 
     fn extract_to(&self, index: A::Index, writer: &mut dyn Write) -> Result<()>;
+
+    /// The size of the contents of the entry at `index`.
+    ///
+    /// By default, this extracts the entry. Archives that store the size should
+    /// return it directly.
+    fn size(&self, index: A::Index) -> Result<u64> {
+        let mut counter = Counter(0);
+        self.extract_to(index, &mut counter)?;
+        Ok(counter.0)
+    }
+}
+
+/// Counts the bytes written to it.
+pub(crate) struct Counter(pub u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 // /// This is a helper trait for implementing the [Entry] type.
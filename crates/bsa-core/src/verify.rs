@@ -0,0 +1,97 @@
+//! Types describing the result of verifying the integrity of an archive.
+
+use std::fmt::{self, Display};
+
+use thiserror::Error;
+
+/// The result of verifying an archive.
+///
+/// Verification does not stop at the first problem. Every entry is checked, and all
+/// problems found are collected into a report.
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    /// The number of entries that were checked.
+    pub entries: usize,
+
+    /// Every problem found, in the order they were found.
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Default::default()
+    }
+
+    /// Returns true if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Record a problem. `entry` is the name of the entry (or directory) the problem
+    /// was found in, or [None] if the problem concerns the archive as a whole.
+    pub fn push<S: Into<String>>(&mut self, entry: Option<S>, kind: IssueKind) {
+        self.issues.push(Issue {
+            entry: entry.map(Into::into),
+            kind,
+        });
+    }
+}
+
+/// A single problem found while verifying an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The name of the entry (or directory) this problem was found in, or [None] if
+    /// it concerns the archive as a whole.
+    pub entry: Option<String>,
+    pub kind: IssueKind,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{}: {}", entry, self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IssueKind {
+    #[error("failed to read data: {0}")]
+    Read(String),
+
+    #[error("failed to decompress data: {0}")]
+    Decompression(String),
+
+    #[error("expected {expected} bytes of decompressed data, found {actual}")]
+    LengthMismatch { expected: u64, actual: u64 },
+
+    #[error("name is not a valid archive path")]
+    InvalidName,
+
+    #[error("stored hash does not match the hash of the name")]
+    HashMismatch,
+
+    #[error("directories are not sorted by hash")]
+    UnsortedDirectories,
+
+    #[error("files are not sorted by hash")]
+    UnsortedFiles,
+
+    #[error("data at offset {offset} ({len} bytes) extends past the end of the archive ({archive_len} bytes)")]
+    OutOfBounds {
+        offset: u64,
+        len: u64,
+        archive_len: u64,
+    },
+
+    #[error("path is stored {count} times")]
+    DuplicatePath { count: usize },
+
+    #[error("embedded name {embedded:?} does not match {expected:?}")]
+    EmbeddedNameMismatch { embedded: String, expected: String },
+
+    #[error("chunk record sentinel is 0x{0:x} instead of 0xBAADF00D")]
+    InvalidChunkSentinel(u32),
+}
@@ -0,0 +1,445 @@
+//! A virtual filesystem overlaying a load order of archives and loose files.
+//!
+//! The game sees a single tree of files, assembled from every archive it loads and
+//! the loose files in its data directory. When several sources provide the same path,
+//! the [Vfs] resolves it the same way the engine does:
+//!
+//! 1. Loose files always win over files in archives.
+//! 2. Among archives, the one loaded last wins. The same goes for loose file
+//!    directories, for tools that stage mods in separate directories.
+//!
+//! Sources must therefore be added in load order.
+//!
+//! Any [Archive] can be added, which covers the BSAs of Oblivion and later games and
+//! the BA2s of Fallout 4. Morrowind's TES3 archives are not supported, as there is no
+//! reader for them yet.
+//!
+//! # Examples
+//! Find out which source provides a mesh.
+//! ```
+//! use bsa_core::{vfs::Vfs, Archive};
+//!
+//! fn provider<A: 'static + Archive>(archive: A) -> bsa_core::Result<()> {
+//!     let mut vfs = Vfs::new();
+//!     vfs.add_archive("Skyrim - Meshes0.bsa", archive);
+//!     vfs.add_loose_files("Data")?;
+//!
+//!     if let Some(file) = vfs.resolve("meshes/foo.nif") {
+//!         println!("{} is loaded from {}", file.name(), file.source_name());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+    ops::Bound,
+    path::{Path, PathBuf},
+};
+
+use crate::{read::Counter, Archive, Result};
+
+/// A source of files for a [Vfs].
+///
+/// Archives implementing [Archive] and directories of loose files are supported out
+/// of the box. Implement this to provide files from elsewhere.
+pub trait Source {
+    /// The number of files in this source.
+    fn len(&self) -> usize;
+
+    /// Returns true if this source contains no files.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The name of the file at `index`, as stored by the source.
+    fn name(&self, index: usize) -> Cow<'_, str>;
+
+    /// Write the contents of the file at `index` to `out`.
+    fn read_to(&self, index: usize, out: &mut dyn Write) -> Result<()>;
+
+    /// The size of the contents of the file at `index`.
+    ///
+    /// By default, this reads through the file. Sources that store the size should
+    /// return it directly.
+    fn size(&self, index: usize) -> Result<u64> {
+        let mut counter = Counter(0);
+        self.read_to(index, &mut counter)?;
+        Ok(counter.0)
+    }
+}
+
+/// Whether a source is an archive or a directory of loose files, which determines
+/// its priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SourceKind {
+    Archive,
+    Loose,
+}
+
+/// The entries of an [Archive], in the order they are stored.
+pub struct ArchiveSource<A: Archive> {
+    archive: A,
+    indices: Vec<A::Index>,
+}
+
+impl<A: Archive> ArchiveSource<A> {
+    pub fn new(archive: A) -> ArchiveSource<A> {
+        let indices = archive.entries().map(|entry| entry.index()).collect();
+        ArchiveSource { archive, indices }
+    }
+
+    pub fn archive(&self) -> &A {
+        &self.archive
+    }
+
+    /// The index in the archive of the file at `index` in this source.
+    pub fn index(&self, index: usize) -> A::Index {
+        self.indices[index]
+    }
+}
+
+impl<A: Archive> Source for ArchiveSource<A> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn name(&self, index: usize) -> Cow<'_, str> {
+        self.archive.by_index(self.indices[index]).name()
+    }
+
+    fn read_to(&self, index: usize, mut out: &mut dyn Write) -> Result<()> {
+        self.archive
+            .by_index(self.indices[index])
+            .extract_to(&mut out)
+    }
+
+    fn size(&self, index: usize) -> Result<u64> {
+        self.archive.by_index(self.indices[index]).size()
+    }
+}
+
+/// The loose files in a directory, such as the game's `Data` directory.
+pub struct LooseFiles {
+    root: PathBuf,
+    files: Vec<String>,
+}
+
+impl LooseFiles {
+    /// Collect every file under `root`, recursively. Files are sorted by path, so
+    /// that their order does not depend on the order the file system lists them in.
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<LooseFiles> {
+        let root = root.as_ref().to_owned();
+        let mut files = Vec::new();
+        collect_files(&root, "", &mut files)?;
+        files.sort();
+        Ok(LooseFiles { root, files })
+    }
+
+    /// Keep only the files whose path relative to the root satisfies `f`.
+    pub fn retain<F: FnMut(&str) -> bool>(&mut self, mut f: F) {
+        self.files.retain(|file| f(file));
+    }
+}
+
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) => format!("{}{}", prefix, name),
+            // The game cannot address this file either.
+            None => continue,
+        };
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", name), files)?;
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+impl Source for LooseFiles {
+    fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    fn name(&self, index: usize) -> Cow<'_, str> {
+        Cow::Borrowed(&self.files[index])
+    }
+
+    fn read_to(&self, index: usize, out: &mut dyn Write) -> Result<()> {
+        let mut f = File::open(self.root.join(&self.files[index]))?;
+        io::copy(&mut f, out)?;
+        Ok(())
+    }
+
+    fn size(&self, index: usize) -> Result<u64> {
+        Ok(fs::metadata(self.root.join(&self.files[index]))?.len())
+    }
+}
+
+struct SourceEntry {
+    name: String,
+    kind: SourceKind,
+    source: Box<dyn Source>,
+}
+
+/// A file in a particular source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Provider {
+    source: usize,
+    file: usize,
+}
+
+/// A virtual filesystem overlaying a load order of sources. See the [module
+/// documentation](self) for how paths are resolved.
+#[derive(Default)]
+pub struct Vfs {
+    sources: Vec<SourceEntry>,
+    /// Every provider of each normalized path, from highest priority to lowest.
+    files: BTreeMap<String, Vec<Provider>>,
+}
+
+impl Vfs {
+    pub fn new() -> Vfs {
+        Default::default()
+    }
+
+    /// Add an archive, loaded after every source added so far.
+    pub fn add_archive<A, S>(&mut self, name: S, archive: A)
+    where
+        A: 'static + Archive,
+        S: Into<String>,
+    {
+        let source = Box::new(ArchiveSource::new(archive));
+        self.add_source(name, SourceKind::Archive, source);
+    }
+
+    /// Add a directory of loose files. Loose files take priority over every archive,
+    /// and over loose files added before.
+    pub fn add_loose_files<P: AsRef<Path>>(&mut self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        let source = Box::new(LooseFiles::new(dir)?);
+        self.add_source(dir.display().to_string(), SourceKind::Loose, source);
+        Ok(())
+    }
+
+    /// Add any source of files.
+    pub fn add_source<S: Into<String>>(
+        &mut self,
+        name: S,
+        kind: SourceKind,
+        source: Box<dyn Source>,
+    ) {
+        let index = self.sources.len();
+        for file in 0..source.len() {
+            let path = normalize(&source.name(file));
+            let providers = self.files.entry(path).or_default();
+
+            // Sources are added in load order, so this source wins over every other
+            // source of the same kind so far.
+            let sources = &self.sources;
+            let position = providers
+                .iter()
+                .position(|provider| sources[provider.source].kind <= kind)
+                .unwrap_or(providers.len());
+            let provider = Provider {
+                source: index,
+                file,
+            };
+            providers.insert(position, provider);
+        }
+
+        self.sources.push(SourceEntry {
+            name: name.into(),
+            kind,
+            source,
+        });
+    }
+
+    /// Get the file the game loads for `path`, or [None] if no source provides it.
+    pub fn resolve(&self, path: &str) -> Option<VfsFile<'_>> {
+        self.providers(path).next()
+    }
+
+    /// Every file provided for `path`, from highest priority (the one the game loads)
+    /// to lowest.
+    pub fn providers(&self, path: &str) -> impl Iterator<Item = VfsFile<'_>> {
+        let providers = match self.files.get(&normalize(path)) {
+            Some(providers) => providers.as_slice(),
+            None => &[],
+        };
+        providers.iter().map(move |&provider| self.file(provider))
+    }
+
+    /// Every path in the merged tree, normalized, in sorted order.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// Every path under the directory `dir`, recursively.
+    pub fn paths_in(&self, dir: &str) -> impl Iterator<Item = &str> {
+        let mut prefix = normalize(dir);
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        self.files
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .map(|(path, _)| path.as_str())
+            .take_while(move |path| path.starts_with(&prefix))
+    }
+
+    /// Every path provided by more than one source, with the file that wins and the
+    /// files it shadows.
+    pub fn shadowed(&self) -> impl Iterator<Item = Shadowed<'_>> {
+        self.files
+            .iter()
+            .filter(|(_, providers)| providers.len() > 1)
+            .map(move |(path, providers)| Shadowed {
+                path,
+                winner: self.file(providers[0]),
+                shadowed: providers[1..].iter().map(|&p| self.file(p)).collect(),
+            })
+    }
+
+    fn file(&self, provider: Provider) -> VfsFile<'_> {
+        VfsFile {
+            source: &self.sources[provider.source],
+            index: provider.source,
+            file: provider.file,
+        }
+    }
+}
+
+/// A file in one of the sources of a [Vfs].
+#[derive(Clone, Copy)]
+pub struct VfsFile<'a> {
+    source: &'a SourceEntry,
+    index: usize,
+    file: usize,
+}
+
+impl<'a> VfsFile<'a> {
+    /// The name of the file, as stored by its source.
+    pub fn name(&self) -> Cow<'a, str> {
+        self.source.source.name(self.file)
+    }
+
+    /// The name the source was added with.
+    pub fn source_name(&self) -> &'a str {
+        &self.source.name
+    }
+
+    /// The position of the source in the load order.
+    pub fn source_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn source_kind(&self) -> SourceKind {
+        self.source.kind
+    }
+
+    /// Write the contents of the file to `out`.
+    pub fn read_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        self.source.source.read_to(self.file, out)
+    }
+
+    /// The size of the contents of the file.
+    pub fn size(&self) -> Result<u64> {
+        self.source.source.size(self.file)
+    }
+
+    /// Read the contents of the file.
+    pub fn read(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.read_to(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// A path provided by more than one source.
+pub struct Shadowed<'a> {
+    pub path: &'a str,
+    /// The file the game loads.
+    pub winner: VfsFile<'a>,
+    /// The files hidden by the winner, from highest priority to lowest.
+    pub shadowed: Vec<VfsFile<'a>>,
+}
+
+/// Normalize a path the way the game does: lower case, with `/` as the separator and
+/// no empty components.
+pub fn normalize(path: &str) -> String {
+    path.to_lowercase()
+        .split(['\\', '/'])
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, fs, io::Write};
+
+    use super::{Source, SourceKind, Vfs};
+    use crate::Result;
+
+    /// An archive held in memory.
+    struct Memory(&'static [(&'static str, &'static [u8])]);
+
+    impl Source for Memory {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn name(&self, index: usize) -> Cow<'_, str> {
+            Cow::Borrowed(self.0[index].0)
+        }
+
+        fn read_to(&self, index: usize, out: &mut dyn Write) -> Result<()> {
+            out.write_all(self.0[index].1)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_vfs_priority() {
+        let temp = tempfile::tempdir().unwrap();
+        let loose = temp.path();
+        fs::create_dir_all(loose.join("Meshes")).unwrap();
+        fs::write(loose.join("Meshes/B.nif"), b"loose").unwrap();
+
+        let first = Memory(&[("meshes\\a.nif", b"a1"), ("meshes\\b.nif", b"b1")]);
+        let second = Memory(&[("meshes\\a.nif", b"a2")]);
+
+        // Loose files win regardless of where they are in the load order.
+        let mut vfs = Vfs::new();
+        vfs.add_loose_files(loose).unwrap();
+        vfs.add_source("first.bsa", SourceKind::Archive, Box::new(first));
+        vfs.add_source("second.bsa", SourceKind::Archive, Box::new(second));
+
+        let a = vfs.resolve("Meshes\\A.nif").unwrap();
+        assert_eq!(a.source_name(), "second.bsa");
+        assert_eq!(a.read().unwrap(), b"a2");
+
+        let b = vfs.resolve("meshes/b.nif").unwrap();
+        assert_eq!(b.source_kind(), SourceKind::Loose);
+        assert_eq!(b.name(), "Meshes/B.nif");
+
+        let paths: Vec<_> = vfs.paths_in("meshes").collect();
+        assert_eq!(paths, ["meshes/a.nif", "meshes/b.nif"]);
+
+        let shadowed: Vec<_> = vfs
+            .shadowed()
+            .map(|s| (s.path, s.shadowed[0].source_name()))
+            .collect();
+        assert_eq!(
+            shadowed,
+            [("meshes/a.nif", "first.bsa"), ("meshes/b.nif", "first.bsa")]
+        );
+    }
+}
@@ -0,0 +1,13 @@
+//! Types shared by the writers of every archive format.
+
+/// What writing an archive did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteSummary {
+    /// Files in the archive.
+    pub files: usize,
+    /// Stored payloads that were not written, because they are identical to one
+    /// written before them. Their records point at the data of the first one.
+    pub deduplicated: usize,
+    /// Bytes of data left out by deduplication.
+    pub bytes_saved: u64,
+}
@@ -0,0 +1,160 @@
+//! Report paths provided by more than one mod.
+//!
+//! Every mod folder contributes the archives at its root and the loose files in its
+//! subfolders. Mods
+//! are given in load order, and paths are resolved as described in [bsa_core::vfs]:
+//! loose files win over archives, and later mods win over earlier ones.
+//!
+//! Conflicting contents are compared against the winning file, first by size, and then
+//! by a SHA-256 digest only if the sizes match.
+
+use std::{fs, path::Path};
+
+use crate::{digest::Fingerprint, open::add_named_archive_to_vfs};
+use bsa_core::{
+    vfs::{LooseFiles, SourceKind, Vfs, VfsFile},
+    Result,
+};
+
+/// A path provided by more than one source.
+pub struct Conflict<'a> {
+    pub path: &'a str,
+    /// The file the game loads.
+    pub winner: VfsFile<'a>,
+    /// The files hidden by the winner, from highest priority to lowest.
+    pub shadowed: Vec<Shadowed<'a>>,
+}
+
+impl Conflict<'_> {
+    /// Returns true if every shadowed file is identical to the winner, in which case
+    /// the conflict is harmless.
+    pub fn is_identical(&self) -> bool {
+        self.shadowed.iter().all(|shadowed| shadowed.identical)
+    }
+}
+
+/// A file hidden by the winner of a [Conflict].
+pub struct Shadowed<'a> {
+    pub file: VfsFile<'a>,
+    /// Whether the contents are byte-identical to the winner.
+    pub identical: bool,
+}
+
+/// Build a [Vfs] from mod folders, given in load order.
+///
+/// The archives at the root of each folder are added in name order, and every file in
+/// its subfolders is added as a loose file. Other files at the root, such as plugins,
+/// `meta.ini` and readmes, are not assets and are left out. Sources are named after
+/// the folder, followed by the archive name for archives.
+pub fn mod_folders_vfs<P: AsRef<Path>>(folders: &[P]) -> Result<Vfs> {
+    let mut vfs = Vfs::new();
+    for folder in folders {
+        let folder = folder.as_ref();
+        let folder_name = folder
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| folder.display().to_string());
+
+        let mut archives = Vec::new();
+        for entry in fs::read_dir(folder)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_file() && is_archive_name(&name) {
+                archives.push(name);
+            }
+        }
+        archives.sort();
+        for archive in &archives {
+            let name = format!("{}/{}", folder_name, archive);
+            add_named_archive_to_vfs(&mut vfs, name, &folder.join(archive))?;
+        }
+
+        let mut loose = LooseFiles::new(folder)?;
+        loose.retain(|path| path.contains('/'));
+        vfs.add_source(folder_name, SourceKind::Loose, Box::new(loose));
+    }
+    Ok(vfs)
+}
+
+fn is_archive_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".bsa") || name.ends_with(".ba2")
+}
+
+/// Every path in the [Vfs] provided by more than one source, in path order.
+pub fn conflicts(vfs: &Vfs) -> Result<Vec<Conflict<'_>>> {
+    let mut conflicts = Vec::new();
+    for shadowed in vfs.shadowed() {
+        let winner = shadowed.winner;
+        let winner_size = winner.size()?;
+        let mut winner_fingerprint = None;
+
+        let mut files = Vec::new();
+        for file in shadowed.shadowed {
+            let identical = if file.size()? != winner_size {
+                false
+            } else {
+                if winner_fingerprint.is_none() {
+                    winner_fingerprint = Some(fingerprint(&winner)?);
+                }
+                winner_fingerprint == Some(fingerprint(&file)?)
+            };
+            files.push(Shadowed { file, identical });
+        }
+
+        conflicts.push(Conflict {
+            path: shadowed.path,
+            winner,
+            shadowed: files,
+        });
+    }
+    Ok(conflicts)
+}
+
+fn fingerprint(file: &VfsFile) -> Result<Fingerprint> {
+    Fingerprint::of(|mut out| file.read_to(&mut out))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{conflicts, mod_folders_vfs};
+
+    #[test]
+    fn test_conflicts() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let files = [
+            ("First/meshes/a.nif", "first"),
+            ("First/textures/a.dds", "same"),
+            ("Second/Meshes/A.nif", "second"),
+            ("Second/textures/a.dds", "same"),
+            ("Second/textures/b.dds", "only"),
+            ("First/meta.ini", "[General]\nmodid=1"),
+            ("Second/meta.ini", "[General]\nmodid=2"),
+            ("First/Patch.esp", "first"),
+            ("Second/Patch.esp", "second"),
+        ];
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let vfs = mod_folders_vfs(&[dir.join("First"), dir.join("Second")]).unwrap();
+        let conflicts = conflicts(&vfs).unwrap();
+
+        let report: Vec<_> = conflicts
+            .iter()
+            .map(|c| (c.path, c.winner.source_name(), c.is_identical()))
+            .collect();
+        assert_eq!(
+            report,
+            [
+                ("meshes/a.nif", "Second", false),
+                ("textures/a.dds", "Second", true)
+            ]
+        );
+    }
+}
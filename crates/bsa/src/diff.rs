@@ -0,0 +1,418 @@
+//! Compare two archives, or an archive and a directory of loose files.
+//!
+//! Entries are matched by normalized path. Contents are compared by streaming the
+//! decompressed data of both sides through a digest, so neither side is extracted or
+//! held in memory. Besides contents, entries carry attributes describing how they are
+//! stored, such as their compression. A change to attributes alone is reported as a
+//! metadata change. Loose files have no attributes. The archive and file flags of a
+//! BSA are stored once for the whole archive, so a change to them is reported once, as
+//! a metadata change with an empty path.
+//!
+//! A path stored more than once on either side cannot be matched up, and is reported
+//! as a conflict instead.
+
+use std::{
+    collections::{btree_map, BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+};
+
+use bsa_core::{
+    vfs::{normalize, ArchiveSource, LooseFiles, Source},
+    Result,
+};
+use fo4_ba2::{Ba2, Entry as Ba2Entry};
+use tes4_bsa::{Bsa, BsaArchive, Compression, Sse, Tes4, Tes5};
+
+use crate::{digest::Fingerprint, ArchiveType};
+
+/// How an entry is stored, by attribute name.
+pub type Attributes = BTreeMap<&'static str, String>;
+
+/// One side of a diff: an archive or a directory of loose files.
+pub struct Side {
+    source: Box<dyn Source>,
+    /// The attributes of the archive as a whole.
+    archive_attributes: Attributes,
+    attributes: Vec<Attributes>,
+    /// The index of every file, by normalized path. If several files share a path, the
+    /// first is kept.
+    paths: BTreeMap<String, usize>,
+    /// The number of files at every path stored more than once.
+    duplicates: BTreeMap<String, usize>,
+}
+
+impl Side {
+    /// Open an archive of any supported format, or a directory of loose files.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Side> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let source = LooseFiles::new(path)?;
+            let attributes = vec![Attributes::new(); source.len()];
+            return Ok(Side::new(Box::new(source), attributes));
+        }
+
+        let r = BufReader::new(File::open(path)?);
+        match ArchiveType::detect_file(path)? {
+            ArchiveType::Tes4 => Side::bsa::<Tes4, _>(r),
+            ArchiveType::Tes5 => Side::bsa::<Tes5, _>(r),
+            ArchiveType::Sse => Side::bsa::<Sse, _>(r),
+            ArchiveType::Ba2 => Side::ba2(r),
+        }
+    }
+
+    fn bsa<A, R>(r: R) -> Result<Side>
+    where
+        A: 'static + Bsa,
+        R: 'static + Read + Seek,
+    {
+        let source = ArchiveSource::new(BsaArchive::<A, R>::new(r)?);
+        let archive = source.archive();
+        let mut archive_attributes = Attributes::new();
+        let archive_flags = format!("{:?}", archive.archive_flags());
+        archive_attributes.insert("archive_flags", archive_flags);
+        archive_attributes.insert("file_flags", format!("{:?}", archive.file_flags()));
+        let attributes = (0..source.len())
+            .map(|i| {
+                let compression = archive.compression(source.index(i));
+                let mut attributes = Attributes::new();
+                attributes.insert("compression", compression_name(compression).to_owned());
+                attributes
+            })
+            .collect();
+        let mut side = Side::new(Box::new(source), attributes);
+        side.archive_attributes = archive_attributes;
+        Ok(side)
+    }
+
+    fn ba2<R: 'static + Read + Seek>(r: R) -> Result<Side> {
+        let ba2 = Ba2::new(r)?;
+        let attributes = ba2.entries().map(|entry| ba2_attributes(&entry)).collect();
+        Ok(Side::new(Box::new(ArchiveSource::new(ba2)), attributes))
+    }
+
+    fn new(source: Box<dyn Source>, attributes: Vec<Attributes>) -> Side {
+        let mut paths = BTreeMap::new();
+        let mut duplicates = BTreeMap::new();
+        for i in 0..source.len() {
+            let path = normalize(&source.name(i));
+            match paths.entry(path) {
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(i);
+                }
+                btree_map::Entry::Occupied(entry) => {
+                    *duplicates.entry(entry.key().clone()).or_insert(1) += 1;
+                }
+            }
+        }
+        Side {
+            source,
+            archive_attributes: Attributes::new(),
+            attributes,
+            paths,
+            duplicates,
+        }
+    }
+
+    /// The number of files stored at a normalized path.
+    fn count(&self, path: &str) -> usize {
+        match self.duplicates.get(path) {
+            Some(&count) => count,
+            None => self.paths.contains_key(path) as usize,
+        }
+    }
+
+    /// The normalized path and index of every file, in path order.
+    pub(crate) fn paths(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.paths
+            .iter()
+            .map(|(path, &index)| (path.as_str(), index))
+    }
+
+    /// The index of the file at a normalized path.
+    pub(crate) fn index_of(&self, path: &str) -> Option<usize> {
+        self.paths.get(path).copied()
+    }
+
+    pub(crate) fn fingerprint(&self, index: usize) -> Result<Fingerprint> {
+        Fingerprint::of(|out| self.source.read_to(index, out))
+    }
+}
+
+fn compression_name(compression: Option<Compression>) -> &'static str {
+    match compression {
+        Some(Compression::Zlib) => "zlib",
+        Some(Compression::Lz4) => "lz4",
+        None => "none",
+    }
+}
+
+fn ba2_attributes(entry: &Ba2Entry) -> Attributes {
+    let mut attributes = Attributes::new();
+    let (chunks, compressed, tile_mode) = match entry {
+        Ba2Entry::General(entry) => {
+            let chunks: Vec<_> = entry.chunks().collect();
+            let compressed = chunks.iter().any(|c| c.compressed_size().is_some());
+            (chunks.len(), compressed, None)
+        }
+        Ba2Entry::DirectX(entry) => {
+            let chunks: Vec<_> = entry.chunks().collect();
+            let compressed = chunks.iter().any(|c| c.compressed_size().is_some());
+            (chunks.len(), compressed, Some(entry.tile_mode()))
+        }
+    };
+    let compression = if compressed {
+        Some(Compression::Zlib)
+    } else {
+        None
+    };
+    attributes.insert("compression", compression_name(compression).to_owned());
+    attributes.insert("chunks", chunks.to_string());
+    if let Some(tile_mode) = tile_mode {
+        attributes.insert("tile_mode", tile_mode.to_string());
+    }
+    attributes
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeChange {
+    pub name: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    /// The contents differ. Any attributes that changed as well are included.
+    Modified(Vec<AttributeChange>),
+    /// The contents are identical, but the attributes differ.
+    Metadata(Vec<AttributeChange>),
+    /// The path is stored more than once on at least one side, so the entries cannot
+    /// be compared. Holds the number of entries at the path on each side.
+    Conflict {
+        old: usize,
+        new: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    /// The normalized path of the entry, or an empty path for changes to the archive as
+    /// a whole.
+    pub path: String,
+    pub change: Change,
+}
+
+/// Every difference between `old` and `new`, in path order, after any change to the
+/// archive as a whole. Entries that are the same on both sides are left out.
+pub fn diff(old: &Side, new: &Side) -> Result<Vec<Difference>> {
+    let mut differences = Vec::new();
+
+    let attributes = attribute_changes(&old.archive_attributes, &new.archive_attributes);
+    if !attributes.is_empty() {
+        differences.push(Difference {
+            path: String::new(),
+            change: Change::Metadata(attributes),
+        });
+    }
+
+    let conflicts: BTreeSet<&String> = old.duplicates.keys().chain(new.duplicates.keys()).collect();
+    for &path in &conflicts {
+        differences.push(Difference {
+            path: path.clone(),
+            change: Change::Conflict {
+                old: old.count(path),
+                new: new.count(path),
+            },
+        });
+    }
+
+    for (path, &old_index) in &old.paths {
+        if conflicts.contains(path) {
+            continue;
+        }
+        let new_index = match new.paths.get(path) {
+            Some(&index) => index,
+            None => {
+                differences.push(Difference {
+                    path: path.clone(),
+                    change: Change::Removed,
+                });
+                continue;
+            }
+        };
+
+        let attributes = attribute_changes(&old.attributes[old_index], &new.attributes[new_index]);
+        let change = if old.fingerprint(old_index)? != new.fingerprint(new_index)? {
+            Change::Modified(attributes)
+        } else if !attributes.is_empty() {
+            Change::Metadata(attributes)
+        } else {
+            continue;
+        };
+        differences.push(Difference {
+            path: path.clone(),
+            change,
+        });
+    }
+
+    for path in new.paths.keys() {
+        if !old.paths.contains_key(path) && !conflicts.contains(path) {
+            differences.push(Difference {
+                path: path.clone(),
+                change: Change::Added,
+            });
+        }
+    }
+
+    differences.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(differences)
+}
+
+/// Open both sides and compare them. See [diff].
+pub fn diff_paths<P: AsRef<Path>, Q: AsRef<Path>>(old: P, new: Q) -> Result<Vec<Difference>> {
+    diff(&Side::open(old)?, &Side::open(new)?)
+}
+
+/// Changes to the attributes present on both sides.
+fn attribute_changes(old: &Attributes, new: &Attributes) -> Vec<AttributeChange> {
+    old.iter()
+        .filter_map(|(&name, old)| {
+            let new = new.get(name)?;
+            if old == new {
+                None
+            } else {
+                Some(AttributeChange {
+                    name,
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::BufWriter, path::Path};
+
+    use tes4_bsa::Tes5Writer;
+
+    use super::{diff_paths, Change};
+
+    fn write_bsa(path: &Path, compressed: bool, files: &[(&str, &str)]) {
+        let mut writer = Tes5Writer::new();
+        writer.set_compressed(compressed);
+        for (name, contents) in files {
+            writer.add(name, contents.repeat(10).into_bytes()).unwrap();
+        }
+        let out = BufWriter::new(fs::File::create(path).unwrap());
+        writer.write_to(out).unwrap();
+    }
+
+    #[test]
+    fn test_diff_directories() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let files = [
+            ("old/meshes/same.nif", "same"),
+            ("old/meshes/changed.nif", "before"),
+            ("old/meshes/removed.nif", "removed"),
+            ("new/Meshes/Same.nif", "same"),
+            ("new/meshes/changed.nif", "after!"),
+            ("new/meshes/added.nif", "added"),
+        ];
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let differences = diff_paths(dir.join("old"), dir.join("new")).unwrap();
+
+        let differences: Vec<_> = differences
+            .iter()
+            .map(|d| (d.path.as_str(), &d.change))
+            .collect();
+        assert_eq!(
+            differences,
+            [
+                ("meshes/added.nif", &Change::Added),
+                ("meshes/changed.nif", &Change::Modified(Vec::new())),
+                ("meshes/removed.nif", &Change::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_archives() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let (old, new) = (dir.join("old.bsa"), dir.join("new.bsa"));
+        write_bsa(
+            &old,
+            false,
+            &[("meshes/same.nif", "same"), ("meshes/a.nif", "before")],
+        );
+        write_bsa(
+            &new,
+            true,
+            &[("meshes/same.nif", "same"), ("meshes/a.nif", "after!")],
+        );
+
+        let differences = diff_paths(&old, &new).unwrap();
+
+        let differences: Vec<_> = differences
+            .iter()
+            .map(|d| {
+                let (kind, attributes) = match &d.change {
+                    Change::Modified(attributes) => ("modified", attributes),
+                    Change::Metadata(attributes) => ("metadata", attributes),
+                    change => panic!("unexpected change {:?}", change),
+                };
+                let names: Vec<_> = attributes.iter().map(|a| a.name).collect();
+                (d.path.as_str(), kind, names)
+            })
+            .collect();
+        // The archive flags changed along with the compression, and are reported
+        // once for the whole archive.
+        assert_eq!(
+            differences,
+            [
+                ("", "metadata", vec!["archive_flags"]),
+                ("meshes/a.nif", "modified", vec!["compression"]),
+                ("meshes/same.nif", "metadata", vec!["compression"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_archive_and_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("loose/meshes")).unwrap();
+        let archive = dir.join("old.bsa");
+        write_bsa(
+            &archive,
+            true,
+            &[("meshes/a.nif", "a"), ("meshes/b.nif", "b")],
+        );
+        fs::write(dir.join("loose/meshes/a.nif"), "a".repeat(10)).unwrap();
+        fs::write(dir.join("loose/meshes/b.nif"), "b".repeat(10)).unwrap();
+        fs::write(dir.join("loose/meshes/B.nif"), "c".repeat(10)).unwrap();
+
+        let differences = diff_paths(&archive, dir.join("loose")).unwrap();
+
+        // Attributes only present on one side are not compared, and the two loose
+        // files at meshes/b.nif cannot be told apart.
+        let differences: Vec<_> = differences
+            .iter()
+            .map(|d| (d.path.as_str(), &d.change))
+            .collect();
+        let conflict = Change::Conflict { old: 1, new: 2 };
+        assert_eq!(differences, [("meshes/b.nif", &conflict)]);
+    }
+}
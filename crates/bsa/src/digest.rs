@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+
+use bsa_core::Result;
+use sha2::{Digest, Sha256};
+
+/// The length and SHA-256 digest of the contents of a file, used to tell whether two
+/// files are identical without holding both in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fingerprint {
+    pub len: u64,
+    pub digest: [u8; 32],
+}
+
+impl Fingerprint {
+    /// Compute the fingerprint of whatever `read` writes.
+    pub fn of<F>(read: F) -> Result<Fingerprint>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<()>,
+    {
+        let mut writer = FingerprintWriter {
+            len: 0,
+            hasher: Sha256::new(),
+        };
+        read(&mut writer)?;
+        Ok(Fingerprint {
+            len: writer.len,
+            digest: writer.hasher.finalize().into(),
+        })
+    }
+}
+
+struct FingerprintWriter {
+    len: u64,
+    hasher: Sha256,
+}
+
+impl Write for FingerprintWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.len += buf.len() as u64;
+        self.hasher.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
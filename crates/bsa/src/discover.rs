@@ -0,0 +1,353 @@
+//! Discover which archives a game loads, and in which order.
+//!
+//! Archives are loaded from two places. First, every archive listed in the
+//! `[Archive]` section of the game's INI files, in the order listed. Then, for each
+//! active plugin in load order, the archives named after that plugin. Oblivion and
+//! the Fallout 3 engine instead order both plugins and archives by their modification
+//! time, oldest first.
+//!
+//! The result is ordered lowest priority first, the order sources are added to a
+//! [Vfs](bsa_core::vfs::Vfs).
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bsa_core::{vfs::Vfs, Result};
+
+use crate::{add_archive_to_vfs, Game};
+
+/// Why an archive is loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveOrigin {
+    /// The archive is listed in an INI file.
+    Ini,
+    /// The archive is named after a plugin.
+    Plugin(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredArchive {
+    pub path: PathBuf,
+    pub origin: ArchiveOrigin,
+}
+
+/// The files that determine which archives a game loads.
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    pub game: Game,
+
+    /// The game's `Data` directory.
+    pub data_dir: PathBuf,
+
+    /// INI files, in the order the game reads them, such as `Skyrim.ini` followed by
+    /// `SkyrimCustom.ini`. Values in later files override earlier ones.
+    pub ini_files: Vec<PathBuf>,
+
+    /// `plugins.txt`, listing the active plugins.
+    pub plugins_file: Option<PathBuf>,
+
+    /// `loadorder.txt`, used by Skyrim to order plugins.
+    pub load_order_file: Option<PathBuf>,
+}
+
+impl Discovery {
+    pub fn new<P: Into<PathBuf>>(game: Game, data_dir: P) -> Discovery {
+        Discovery {
+            game,
+            data_dir: data_dir.into(),
+            ini_files: Vec::new(),
+            plugins_file: None,
+            load_order_file: None,
+        }
+    }
+
+    /// Every archive the game loads, lowest priority first.
+    ///
+    /// Archives that are listed but not present in the data directory are skipped, as
+    /// the game does.
+    pub fn archives(&self) -> Result<Vec<DiscoveredArchive>> {
+        let data = DataDir::new(&self.data_dir)?;
+        let mut seen = HashSet::new();
+        let mut archives = Vec::new();
+
+        let ini = self.read_ini_archive_lists()?;
+        for &key in self.game.ini_archive_keys() {
+            let list = match ini.get(&key.to_lowercase()) {
+                Some(list) => list,
+                None => continue,
+            };
+            for name in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                if let Some(name) = data.get(name) {
+                    if seen.insert(name.to_lowercase()) {
+                        archives.push(DiscoveredArchive {
+                            path: self.data_dir.join(name),
+                            origin: ArchiveOrigin::Ini,
+                        });
+                    }
+                }
+            }
+        }
+
+        for plugin in self.plugins_in(&data)? {
+            for name in plugin_archives(self.game, &plugin, &data) {
+                if seen.insert(name.to_lowercase()) {
+                    archives.push(DiscoveredArchive {
+                        path: self.data_dir.join(name),
+                        origin: ArchiveOrigin::Plugin(plugin.clone()),
+                    });
+                }
+            }
+        }
+
+        if self.game.orders_by_timestamp() {
+            archives.sort_by_cached_key(|archive| modified(&archive.path));
+        }
+
+        Ok(archives)
+    }
+
+    /// Build a [Vfs] of every archive the game loads, followed by the loose files in
+    /// the data directory.
+    pub fn vfs(&self) -> Result<Vfs> {
+        let mut vfs = Vfs::new();
+        for archive in self.archives()? {
+            add_archive_to_vfs(&mut vfs, &archive.path)?;
+        }
+        vfs.add_loose_files(&self.data_dir)?;
+        Ok(vfs)
+    }
+
+    /// Every active plugin present in the data directory, in load order.
+    pub fn plugins(&self) -> Result<Vec<String>> {
+        self.plugins_in(&DataDir::new(&self.data_dir)?)
+    }
+
+    fn plugins_in(&self, data: &DataDir) -> Result<Vec<String>> {
+        let mut active = Vec::new();
+        if let Some(path) = &self.plugins_file {
+            for line in read_lines(path)? {
+                // Since Skyrim Special Edition, plugins.txt lists every plugin and marks
+                // the active ones with an asterisk.
+                match line.strip_prefix('*') {
+                    Some(plugin) => active.push(plugin.to_owned()),
+                    None if !matches!(self.game, Game::SkyrimSE | Game::Fallout4) => {
+                        active.push(line)
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        if let Some(path) = &self.load_order_file {
+            let position: HashMap<String, usize> = read_lines(path)?
+                .into_iter()
+                .enumerate()
+                .map(|(i, plugin)| (plugin.to_lowercase(), i))
+                .collect();
+            active.sort_by_key(|plugin| {
+                position
+                    .get(&plugin.to_lowercase())
+                    .copied()
+                    .unwrap_or(usize::MAX)
+            });
+        }
+
+        let mut seen = HashSet::new();
+        let mut plugins: Vec<String> = self
+            .game
+            .implicit_plugins()
+            .iter()
+            .copied()
+            .chain(active.iter().map(String::as_str))
+            .filter_map(|plugin| data.get(plugin))
+            .filter(|plugin| seen.insert(plugin.to_lowercase()))
+            .map(str::to_owned)
+            .collect();
+
+        if self.game.orders_by_timestamp() {
+            plugins.sort_by_cached_key(|plugin| modified(&self.data_dir.join(plugin)));
+            // Masters always load before other plugins.
+            plugins.sort_by_key(|plugin| !plugin.to_lowercase().ends_with(".esm"));
+        }
+
+        Ok(plugins)
+    }
+
+    /// The values of the archive list keys, by lower case key. Later INI files
+    /// override earlier ones.
+    fn read_ini_archive_lists(&self) -> Result<HashMap<String, String>> {
+        let mut values = HashMap::new();
+        for path in &self.ini_files {
+            let text = fs::read(path)?;
+            let text = String::from_utf8_lossy(&text);
+            let mut in_archive_section = false;
+            for line in text.lines() {
+                let line = line.trim();
+                if line.starts_with('[') {
+                    in_archive_section = line.eq_ignore_ascii_case("[archive]");
+                } else if in_archive_section && !line.starts_with(';') {
+                    if let Some((key, value)) = line.split_once('=') {
+                        values.insert(key.trim().to_lowercase(), value.trim().to_owned());
+                    }
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// The names of the archives a plugin loads, in the order they are loaded.
+fn plugin_archives(game: Game, plugin: &str, data: &DataDir) -> Vec<String> {
+    let stem = match plugin.rfind('.') {
+        Some(i) => &plugin[..i],
+        None => plugin,
+    };
+
+    match game {
+        // These load every archive whose name begins with the name of the plugin.
+        Game::Oblivion | Game::Fallout3 | Game::FalloutNV => {
+            let prefix = stem.to_lowercase();
+            data.names
+                .iter()
+                .filter(|name| {
+                    let lower = name.to_lowercase();
+                    lower.starts_with(&prefix) && lower.ends_with(".bsa")
+                })
+                .cloned()
+                .collect()
+        }
+        Game::Skyrim => data
+            .get(&format!("{}.bsa", stem))
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+        Game::SkyrimSE => [format!("{}.bsa", stem), format!("{} - Textures.bsa", stem)]
+            .iter()
+            .filter_map(|name| data.get(name))
+            .map(str::to_owned)
+            .collect(),
+        Game::Fallout4 => [
+            format!("{} - Main.ba2", stem),
+            format!("{} - Textures.ba2", stem),
+        ]
+        .iter()
+        .filter_map(|name| data.get(name))
+        .map(str::to_owned)
+        .collect(),
+    }
+}
+
+/// The files directly inside the data directory, looked up case insensitively as the
+/// game does.
+struct DataDir {
+    /// Every file name, sorted.
+    names: Vec<String>,
+    by_lower: HashMap<String, usize>,
+}
+
+impl DataDir {
+    fn new(path: &Path) -> Result<DataDir> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.push(name.to_owned());
+                }
+            }
+        }
+        names.sort();
+        let by_lower = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.to_lowercase(), i))
+            .collect();
+        Ok(DataDir { names, by_lower })
+    }
+
+    /// The actual name of a file, if present.
+    fn get(&self, name: &str) -> Option<&str> {
+        let i = *self.by_lower.get(&name.to_lowercase())?;
+        Some(&self.names[i])
+    }
+}
+
+fn read_lines(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read(path)?;
+    let lines = String::from_utf8_lossy(&text)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect();
+    Ok(lines)
+}
+
+/// The modification time of a file, for sorting. Files without one sort first.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{ArchiveOrigin, Discovery};
+    use crate::Game;
+
+    #[test]
+    fn test_discover_skyrim_se() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let data = dir.join("Data");
+        fs::create_dir_all(&data).unwrap();
+        for name in [
+            "Skyrim - Meshes0.bsa",
+            "Skyrim.esm",
+            "Update.esm",
+            "Update.bsa",
+            "Mod.esp",
+            "mod.bsa",
+            "Mod - Textures.bsa",
+            "Inactive.esp",
+            "Inactive.bsa",
+        ] {
+            fs::write(data.join(name), b"").unwrap();
+        }
+        fs::write(
+            dir.join("Skyrim.ini"),
+            "[Archive]\nsResourceArchiveList=Skyrim - Meshes0.bsa, Missing.bsa\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("plugins.txt"),
+            "# comment\n*Mod.esp\nInactive.esp\n",
+        )
+        .unwrap();
+
+        let mut discovery = Discovery::new(Game::SkyrimSE, &data);
+        discovery.ini_files.push(dir.join("Skyrim.ini"));
+        discovery.plugins_file = Some(dir.join("plugins.txt"));
+        let archives = discovery.archives().unwrap();
+
+        let names: Vec<_> = archives
+            .iter()
+            .map(|a| a.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Skyrim - Meshes0.bsa",
+                "Update.bsa",
+                "mod.bsa",
+                "Mod - Textures.bsa"
+            ]
+        );
+        assert_eq!(archives[0].origin, ArchiveOrigin::Ini);
+        assert_eq!(archives[2].origin, ArchiveOrigin::Plugin("Mod.esp".into()));
+    }
+}
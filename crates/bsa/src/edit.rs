@@ -0,0 +1,100 @@
+//! Edit an archive of any supported format.
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, Write},
+    path::Path,
+};
+
+use bsa_core::Result;
+use fo4_ba2::Ba2Editor;
+use tes4_bsa::edit::BsaEditor;
+
+use crate::ArchiveType;
+
+/// Stages changes to an existing BSA or BA2, and writes the result as a new archive
+/// of the same format. See [BsaEditor] and [Ba2Editor].
+pub enum Editor {
+    Bsa(BsaEditor),
+    Ba2(Ba2Editor),
+}
+
+impl Editor {
+    /// Open the archive at `path` for editing, whatever its format.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Editor> {
+        let path = path.as_ref();
+        let r = BufReader::new(File::open(path)?);
+        Editor::new(ArchiveType::detect_file(path)?, r)
+    }
+
+    /// Open an archive of a known format for editing.
+    pub fn new<R: 'static + Read + Seek>(archive_type: ArchiveType, r: R) -> Result<Editor> {
+        match archive_type {
+            ArchiveType::Tes4 | ArchiveType::Tes5 | ArchiveType::Sse => {
+                Ok(Editor::Bsa(BsaEditor::open(r)?))
+            }
+            ArchiveType::Ba2 => Ok(Editor::Ba2(Ba2Editor::open(r)?)),
+        }
+    }
+
+    /// Whether the archive contains a file at `path`, including staged changes.
+    pub fn contains(&self, path: &str) -> bool {
+        match self {
+            Editor::Bsa(editor) => editor.contains(path),
+            Editor::Ba2(editor) => editor.contains(path),
+        }
+    }
+
+    /// Add a new file, read from `r` when the archive is committed.
+    pub fn add<R: 'static + Read>(&mut self, path: &str, r: R) -> Result<()> {
+        match self {
+            Editor::Bsa(editor) => editor.add(path, r),
+            Editor::Ba2(editor) => Ok(editor.add(path, r)?),
+        }
+    }
+
+    /// Replace the contents of an existing file with data read from `r`.
+    pub fn replace<R: 'static + Read>(&mut self, path: &str, r: R) -> Result<()> {
+        match self {
+            Editor::Bsa(editor) => editor.replace(path, r),
+            Editor::Ba2(editor) => Ok(editor.replace(path, r)?),
+        }
+    }
+
+    /// Remove the file at `path`.
+    pub fn remove(&mut self, path: &str) -> Result<()> {
+        match self {
+            Editor::Bsa(editor) => editor.remove(path),
+            Editor::Ba2(editor) => Ok(editor.remove(path)?),
+        }
+    }
+
+    /// Rename the file at `from` to `to`, which may be in a different folder.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        match self {
+            Editor::Bsa(editor) => editor.rename(from, to),
+            Editor::Ba2(editor) => Ok(editor.rename(from, to)?),
+        }
+    }
+
+    /// The number of files in the archive, including staged changes.
+    pub fn len(&self) -> usize {
+        match self {
+            Editor::Bsa(editor) => editor.len(),
+            Editor::Ba2(editor) => editor.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the archive with every staged change applied. It must not be written
+    /// over the archive being edited.
+    pub fn commit<W: Write + Seek>(self, w: W) -> Result<()> {
+        match self {
+            Editor::Bsa(editor) => editor.commit(w),
+            Editor::Ba2(editor) => Ok(editor.commit(w)?),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use bsa_core::policy::CompressionPolicy;
+use tes4_bsa::{ArchiveFlags, FileFlags};
+
+use crate::ArchiveType;
+
+/// A game using BSA or BA2 archives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Game {
+    Oblivion,
+    Fallout3,
+    FalloutNV,
+    Skyrim,
+    SkyrimSE,
+    Fallout4,
+}
+
+impl Game {
+    /// The game with a name such as `skyrimse`, ignoring case: one of `oblivion`,
+    /// `fallout3`, `falloutnv`, `skyrim`, `skyrimse` and `fallout4`.
+    pub fn from_name(name: &str) -> Option<Game> {
+        match name.to_lowercase().as_str() {
+            "oblivion" => Some(Game::Oblivion),
+            "fallout3" => Some(Game::Fallout3),
+            "falloutnv" => Some(Game::FalloutNV),
+            "skyrim" => Some(Game::Skyrim),
+            "skyrimse" => Some(Game::SkyrimSE),
+            "fallout4" => Some(Game::Fallout4),
+            _ => None,
+        }
+    }
+
+    /// The extension of the game's archives, without the leading period.
+    pub fn archive_extension(self) -> &'static str {
+        match self {
+            Game::Fallout4 => "ba2",
+            _ => "bsa",
+        }
+    }
+
+    /// The format of the game's archives.
+    pub fn archive_type(self) -> ArchiveType {
+        match self {
+            Game::Oblivion => ArchiveType::Tes4,
+            Game::Fallout3 | Game::FalloutNV | Game::Skyrim => ArchiveType::Tes5,
+            Game::SkyrimSE => ArchiveType::Sse,
+            Game::Fallout4 => ArchiveType::Ba2,
+        }
+    }
+
+    /// The compression policy of the game's own archives, which leave sounds
+    /// uncompressed so that they can be streamed from the archive.
+    pub fn compression_policy(self) -> CompressionPolicy {
+        let sounds: &[&str] = match self {
+            Game::Oblivion | Game::Fallout3 | Game::FalloutNV => &["wav", "mp3", "ogg"],
+            Game::Skyrim | Game::SkyrimSE | Game::Fallout4 => &["wav", "xwm", "fuz"],
+        };
+        let mut policy = CompressionPolicy::new();
+        for extension in sounds {
+            policy.set_extension(extension, false);
+        }
+        policy
+    }
+
+    /// The file flags of BSAs the game expects to be uncompressed as a whole, which
+    /// are those holding sounds or voices.
+    pub fn uncompressed_file_flags(self) -> FileFlags {
+        match self {
+            Game::Fallout4 => FileFlags::empty(),
+            _ => FileFlags::SOUNDS | FileFlags::VOICES,
+        }
+    }
+
+    /// The flags of a BSA for the game holding files described by `file_flags`,
+    /// besides whether it is compressed.
+    ///
+    /// Names of directories and files are always included, since the games look files
+    /// up by name. Fallout 3 and New Vegas need the names of textures embedded in
+    /// their data, as in the games' own texture archives. The flags retaining names
+    /// and strings only matter on consoles, and are left unset.
+    pub fn archive_flags(self, file_flags: FileFlags) -> ArchiveFlags {
+        let mut flags = ArchiveFlags::INCLUDE_DIRNAMES | ArchiveFlags::INCLUDE_FILENAMES;
+        if matches!(self, Game::Fallout3 | Game::FalloutNV)
+            && file_flags.contains(FileFlags::TEXTURES)
+        {
+            flags |= ArchiveFlags::EMBED_FILENAMES;
+        }
+        flags
+    }
+
+    /// The file name of one of several archives split from the same contents, such as
+    /// `Skyrim - Textures0.bsa`, following the numbering of the game's own archives.
+    ///
+    /// Skyrim numbers every archive from 0, and Fallout 4 from 1. The older games
+    /// leave the first archive unnumbered and number the rest from 2. A single archive
+    /// is never numbered.
+    pub fn split_archive_name(self, base: &str, index: usize, count: usize) -> String {
+        let extension = self.archive_extension();
+        if count <= 1 {
+            return format!("{}.{}", base, extension);
+        }
+        match self {
+            Game::Skyrim | Game::SkyrimSE => format!("{}{}.{}", base, index, extension),
+            Game::Fallout4 => format!("{}{}.{}", base, index + 1, extension),
+            _ if index == 0 => format!("{}.{}", base, extension),
+            _ => format!("{}{}.{}", base, index + 1, extension),
+        }
+    }
+
+    /// The keys in the `[Archive]` section of the game's INI files that list the
+    /// archives loaded at startup, in the order they are loaded.
+    pub fn ini_archive_keys(self) -> &'static [&'static str] {
+        match self {
+            Game::Oblivion | Game::Fallout3 | Game::FalloutNV => &["SArchiveList"],
+            Game::Skyrim | Game::SkyrimSE => &["sResourceArchiveList", "sResourceArchiveList2"],
+            Game::Fallout4 => &[
+                "sResourceStartUpArchiveList",
+                "sResourceIndexFileList",
+                "sResourceArchiveList",
+                "sResourceArchiveList2",
+            ],
+        }
+    }
+
+    /// Plugins the game loads whether or not they are listed in plugins.txt.
+    pub fn implicit_plugins(self) -> &'static [&'static str] {
+        match self {
+            Game::Oblivion | Game::Fallout3 | Game::FalloutNV => &[],
+            Game::Skyrim => &["Skyrim.esm", "Update.esm"],
+            Game::SkyrimSE => &[
+                "Skyrim.esm",
+                "Update.esm",
+                "Dawnguard.esm",
+                "HearthFires.esm",
+                "Dragonborn.esm",
+            ],
+            Game::Fallout4 => &[
+                "Fallout4.esm",
+                "DLCRobot.esm",
+                "DLCworkshop01.esm",
+                "DLCCoast.esm",
+                "DLCworkshop02.esm",
+                "DLCworkshop03.esm",
+                "DLCNukaWorld.esm",
+            ],
+        }
+    }
+
+    /// Returns true if the game orders plugins and archives by file modification
+    /// time, rather than by the order of plugins.txt or loadorder.txt.
+    pub fn orders_by_timestamp(self) -> bool {
+        matches!(self, Game::Oblivion | Game::Fallout3 | Game::FalloutNV)
+    }
+}
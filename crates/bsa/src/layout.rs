@@ -0,0 +1,70 @@
+//! Unpack an archive of any format along with its layout, and pack it back the same
+//! way.
+//!
+//! The layout captures what the unpacked files alone do not: the flags of the
+//! archive, the compression of every file, the order of the data, and how BA2
+//! entries are split into chunks. See [tes4_bsa::layout] and [fo4_ba2::layout] for
+//! what is recorded for each format, and when the result matches the original byte
+//! for byte.
+
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+};
+
+use bsa_core::{write::WriteSummary, Result};
+
+pub use fo4_ba2::layout::Ba2Layout;
+pub use tes4_bsa::layout::BsaLayout;
+
+use crate::ArchiveType;
+
+/// The layout of an archive of either format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Layout {
+    Bsa(BsaLayout),
+    Ba2(Ba2Layout),
+}
+
+impl Layout {
+    /// Load a layout saved by [Layout::save], of either format.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Layout> {
+        let path = path.as_ref();
+        if fs::read_to_string(path)?.starts_with("ba2-layout") {
+            Ok(Layout::Ba2(Ba2Layout::load(path)?))
+        } else {
+            Ok(Layout::Bsa(BsaLayout::load(path)?))
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match self {
+            Layout::Bsa(layout) => layout.save(path),
+            Layout::Ba2(layout) => Ok(layout.save(path)?),
+        }
+    }
+}
+
+/// Extract every file of the archive at `archive` into `dir`, returning the layout
+/// of the archive.
+pub fn unpack<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, dir: Q) -> Result<Layout> {
+    let archive = archive.as_ref();
+    match ArchiveType::detect_file(archive)? {
+        ArchiveType::Ba2 => Ok(Layout::Ba2(fo4_ba2::layout::unpack(archive, dir)?)),
+        _ => Ok(Layout::Bsa(tes4_bsa::layout::unpack(archive, dir)?)),
+    }
+}
+
+/// Write the archive `archive` from the files in `dir`, as described by `layout`.
+pub fn pack<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    layout: &Layout,
+    archive: Q,
+) -> Result<WriteSummary> {
+    let out = BufWriter::new(File::create(archive)?);
+    match layout {
+        Layout::Bsa(layout) => tes4_bsa::layout::pack(dir, layout, out),
+        Layout::Ba2(layout) => Ok(fo4_ba2::layout::pack(dir, layout, out)?),
+    }
+}
@@ -1,5 +1,23 @@
+pub mod conflicts;
+pub mod diff;
+pub mod discover;
+pub mod edit;
+pub mod layout;
+pub mod lint;
+pub mod manifest;
+pub mod merge;
+pub mod pack;
 pub mod read;
+pub mod redundant;
+pub mod write;
 
-pub use bsa_core::{Error, ReadError, Result};
+mod digest;
+mod game;
+mod open;
 
+pub use bsa_core::{policy, verify, vfs, Error, ReadError, Result, WriteError};
+pub use tes4_bsa::{patch, repack};
+
+pub use game::Game;
+pub use open::{add_archive_to_vfs, ArchiveType};
 pub use read::*;
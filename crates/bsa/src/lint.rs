@@ -0,0 +1,469 @@
+//! Check an archive against the limits of the game loading it.
+//!
+//! An archive that reads without errors may still be one the game cannot use. The
+//! game may not read its version. Paths may be too long for the game, or their hashes
+//! may collide, so that lookups find the wrong file. Data may be past the offsets the
+//! game can seek to. Sounds may be compressed, or stored along with compressed files,
+//! which keeps the game from streaming them. Some archive flags are ignored by the
+//! game on PC, while others keep it from loading the archive at all. Fallout 4 only
+//! loads textures of some formats.
+//!
+//! Every problem is reported as a [Lint]. Errors keep the game from loading the
+//! archive or some of its files, while warnings point out settings the game ignores
+//! or does not expect.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{BufReader, Read, Seek},
+    path::Path,
+};
+
+use bsa_core::{vfs::normalize, Archive, Result};
+use dds::DxgiFormat;
+use fo4_ba2::{Ba2, Entry as Ba2Entry, Version as Ba2Version};
+use tes4_bsa::{ArchiveFlags, Bsa, BsaArchive, FileFlags, Sse, Tes4, Tes5};
+use thiserror::Error;
+
+use crate::{pack::MAX_BSA_LEN, ArchiveType, Game};
+
+/// The longest path the games load. They build the paths of files below `data\` in
+/// buffers of 260 bytes, including the terminating null.
+const MAX_PATH_LEN: usize = 260 - "data\\".len() - 1;
+
+/// The tile mode of textures in archives for PC.
+const PC_TILE_MODE: u8 = 8;
+
+/// The formats of textures Fallout 4 loads from DX10 archives.
+const FALLOUT4_TEXTURE_FORMATS: &[DxgiFormat] = &[
+    DxgiFormat::Bc1Unorm,
+    DxgiFormat::Bc1UnormSrgb,
+    DxgiFormat::Bc2Unorm,
+    DxgiFormat::Bc2UnormSrgb,
+    DxgiFormat::Bc3Unorm,
+    DxgiFormat::Bc3UnormSrgb,
+    DxgiFormat::Bc4Unorm,
+    DxgiFormat::Bc4Snorm,
+    DxgiFormat::Bc5Unorm,
+    DxgiFormat::Bc5Snorm,
+    DxgiFormat::Bc6hUf16,
+    DxgiFormat::Bc6hSf16,
+    DxgiFormat::Bc7Unorm,
+    DxgiFormat::Bc7UnormSrgb,
+    DxgiFormat::R8G8B8A8Unorm,
+    DxgiFormat::R8G8B8A8UnormSrgb,
+    DxgiFormat::B8G8R8A8Unorm,
+    DxgiFormat::B8G8R8A8UnormSrgb,
+    DxgiFormat::B8G8R8X8Unorm,
+    DxgiFormat::B5G6R5Unorm,
+    DxgiFormat::B5G5R5A1Unorm,
+    DxgiFormat::R8G8Unorm,
+    DxgiFormat::R8Unorm,
+    DxgiFormat::A8Unorm,
+];
+
+/// How serious a [Lint] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The game ignores a setting, or may not treat a file as intended.
+    Warning,
+    /// The game fails to load the archive, or some of its files.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem with an archive, for the game loading it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// The path of the entry (or directory) concerned, or [None] if the problem
+    /// concerns the archive as a whole.
+    pub entry: Option<String>,
+    pub kind: LintKind,
+}
+
+impl Lint {
+    fn archive(kind: LintKind) -> Lint {
+        Lint { entry: None, kind }
+    }
+
+    fn entry(entry: &str, kind: LintKind) -> Lint {
+        Lint {
+            entry: Some(entry.to_owned()),
+            kind,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{}: {}: {}", self.severity(), entry, self.kind),
+            None => write!(f, "{}: {}", self.severity(), self.kind),
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LintKind {
+    #[error("archive is a {found}, while the game loads a {expected}")]
+    WrongArchiveType {
+        expected: ArchiveType,
+        found: ArchiveType,
+    },
+
+    /// Fallout 4 only reads version 1. Versions 2 and 3 are written for Starfield.
+    #[error("BA2 version {0} is not one the game loads, which only reads version 1")]
+    UnsupportedBa2Version(u32),
+
+    #[error("archive is {len} bytes, more than the game's limit of {max}")]
+    ArchiveTooLarge { len: u64, max: u64 },
+
+    #[error("archive flags {0:?} are missing")]
+    MissingArchiveFlags(ArchiveFlags),
+
+    #[error("archive flags {0:?} are only used on consoles, and break loading on PC")]
+    ConsoleArchiveFlags(ArchiveFlags),
+
+    #[error("archive flags {0:?} are ignored by the game")]
+    IgnoredArchiveFlags(ArchiveFlags),
+
+    #[error("file flags {0:?} are missing, while the archive holds such files")]
+    MissingFileFlags(FileFlags),
+
+    #[error("path is {len} characters long, more than the game's limit of {max}")]
+    PathTooLong { len: usize, max: usize },
+
+    #[error("hash is the same as that of {other:?}")]
+    HashCollision { other: String },
+
+    #[error("data ends at offset {end}, past the game's limit of {max}")]
+    DataPastLimit { end: u64, max: u64 },
+
+    #[error("file is compressed, while the game only loads it uncompressed")]
+    ForbiddenCompression,
+
+    /// The game expects archives holding such files to be uncompressed as a whole,
+    /// as described in [Game::uncompressed_file_flags].
+    #[error("archive holding {0:?} files is compressed, while the game loads it uncompressed")]
+    ForbiddenArchiveCompression(FileFlags),
+
+    #[error("texture is in a general archive, while the game loads textures from DX10 archives")]
+    TextureInGeneralArchive,
+
+    #[error("texture format {0} is not one the game loads")]
+    UnsupportedTextureFormat(u8),
+
+    #[error("tile mode {0} is only used on consoles")]
+    ConsoleTileMode(u8),
+}
+
+impl LintKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            LintKind::MissingArchiveFlags(flags) => {
+                let names = ArchiveFlags::INCLUDE_DIRNAMES | ArchiveFlags::INCLUDE_FILENAMES;
+                if flags.intersects(names) {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                }
+            }
+            LintKind::IgnoredArchiveFlags(_)
+            | LintKind::MissingFileFlags(_)
+            | LintKind::TextureInGeneralArchive
+            | LintKind::ConsoleTileMode(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// Check the archive at `archive` against the limits of `game`. Returns the problems
+/// found with the archive as a whole first, then those found with each entry.
+pub fn lint<P: AsRef<Path>>(archive: P, game: Game) -> Result<Vec<Lint>> {
+    let path = archive.as_ref();
+    let mut lints = Vec::new();
+
+    let archive_type = ArchiveType::detect_file(path)?;
+    let expected = game.archive_type();
+    if archive_type != expected {
+        lints.push(Lint::archive(LintKind::WrongArchiveType {
+            expected,
+            found: archive_type,
+        }));
+    }
+    let len = fs::metadata(path)?.len();
+    if archive_type != ArchiveType::Ba2 && len > MAX_BSA_LEN {
+        lints.push(Lint::archive(LintKind::ArchiveTooLarge {
+            len,
+            max: MAX_BSA_LEN,
+        }));
+    }
+
+    let mut r = BufReader::new(File::open(path)?);
+    if archive_type == ArchiveType::Ba2 {
+        let mut header = [0; 8];
+        r.read_exact(&mut header)?;
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        // The rest of the archive cannot be read without knowing its version.
+        if version != Ba2Version::V1 as u32 {
+            lints.push(Lint::archive(LintKind::UnsupportedBa2Version(version)));
+            return Ok(lints);
+        }
+        r.rewind()?;
+    }
+    match archive_type {
+        ArchiveType::Tes4 => lint_bsa(&BsaArchive::<Tes4, _>::new(r)?, game, &mut lints),
+        ArchiveType::Tes5 => lint_bsa(&BsaArchive::<Tes5, _>::new(r)?, game, &mut lints),
+        ArchiveType::Sse => lint_bsa(&BsaArchive::<Sse, _>::new(r)?, game, &mut lints),
+        ArchiveType::Ba2 => lint_ba2(&Ba2::new(r)?, game, &mut lints),
+    }
+    Ok(lints)
+}
+
+fn lint_bsa<A: Bsa, R: Read + Seek>(bsa: &BsaArchive<A, R>, game: Game, lints: &mut Vec<Lint>) {
+    let entries: Vec<_> = bsa
+        .entries()
+        .map(|entry| (entry.index(), entry.name().into_owned()))
+        .collect();
+    let contents = entries.iter().fold(FileFlags::empty(), |flags, (_, name)| {
+        flags | FileFlags::for_path(name, A::VERSION)
+    });
+
+    let flags = bsa.archive_flags();
+    let missing = game.archive_flags(contents) - flags;
+    if !missing.is_empty() {
+        lints.push(Lint::archive(LintKind::MissingArchiveFlags(missing)));
+    }
+    let console = flags & (ArchiveFlags::XBOX360 | ArchiveFlags::XMEM);
+    if !console.is_empty() {
+        lints.push(Lint::archive(LintKind::ConsoleArchiveFlags(console)));
+    }
+    let mut ignored = flags
+        & (ArchiveFlags::RETAIN_DIRNAMES
+            | ArchiveFlags::RETAIN_FILENAMES
+            | ArchiveFlags::RETAIN_FILENAME_OFFSETS
+            | ArchiveFlags::RETAIN_STRINGS);
+    // Oblivion's archives have no embedded names.
+    if game == Game::Oblivion {
+        ignored |= flags & ArchiveFlags::EMBED_FILENAMES;
+    }
+    if !ignored.is_empty() {
+        lints.push(Lint::archive(LintKind::IgnoredArchiveFlags(ignored)));
+    }
+    let missing = contents - bsa.file_flags();
+    if !missing.is_empty() {
+        lints.push(Lint::archive(LintKind::MissingFileFlags(missing)));
+    }
+    let uncompressed = (contents | bsa.file_flags()) & game.uncompressed_file_flags();
+    let compressed = entries
+        .iter()
+        .any(|(index, _)| bsa.compression(*index).is_some());
+    if !uncompressed.is_empty() && compressed {
+        let kind = LintKind::ForbiddenArchiveCompression(uncompressed);
+        lints.push(Lint::archive(kind));
+    }
+
+    let policy = game.compression_policy();
+    let mut dirs = Hashes::default();
+    let mut colliding_dirs = BTreeSet::new();
+    let mut files = Hashes::default();
+    for (index, name) in &entries {
+        lint_path(name, lints);
+
+        let (dir_hash, file_hash) = bsa.hashes(*index);
+        let dir = name.rsplit_once('/').map_or("", |(dir, _)| dir);
+        if let Some(other) = dirs.collision(dir_hash.to_u64(), dir) {
+            if colliding_dirs.insert(dir.to_owned()) {
+                lints.push(Lint::entry(dir, LintKind::HashCollision { other }));
+            }
+        }
+        if let Some(other) = files.collision((dir_hash.to_u64(), file_hash.to_u64()), name) {
+            lints.push(Lint::entry(name, LintKind::HashCollision { other }));
+        }
+
+        let range = bsa.block_range(*index);
+        if range.end > MAX_BSA_LEN {
+            let kind = LintKind::DataPastLimit {
+                end: range.end,
+                max: MAX_BSA_LEN,
+            };
+            lints.push(Lint::entry(name, kind));
+        }
+
+        let compressed = bsa.compression(*index).is_some();
+        if compressed && policy.extension_rule(name) == Some(false) {
+            lints.push(Lint::entry(name, LintKind::ForbiddenCompression));
+        }
+    }
+}
+
+fn lint_ba2<R: Read + Seek>(ba2: &Ba2<R>, game: Game, lints: &mut Vec<Lint>) {
+    let policy = game.compression_policy();
+    let mut hashes = Hashes::default();
+    for entry in ba2.entries() {
+        let name = entry.display_name();
+        lint_path(&name, lints);
+
+        let hash = entry.hash();
+        let key = (hash.directory(), hash.file(), hash.extension());
+        if let Some(other) = hashes.collision(key, &name) {
+            lints.push(Lint::entry(&name, LintKind::HashCollision { other }));
+        }
+
+        let compressed = entry
+            .chunks()
+            .any(|chunk| chunk.compressed_size().is_some());
+        if compressed && policy.extension_rule(&name) == Some(false) {
+            lints.push(Lint::entry(&name, LintKind::ForbiddenCompression));
+        }
+
+        match &entry {
+            Ba2Entry::General(_) => {
+                if normalize(&name).ends_with(".dds") {
+                    lints.push(Lint::entry(&name, LintKind::TextureInGeneralArchive));
+                }
+            }
+            Ba2Entry::DirectX(e) => {
+                let texture = e.texture();
+                let format = DxgiFormat::try_from(u32::from(texture.format));
+                if !matches!(format, Ok(format) if FALLOUT4_TEXTURE_FORMATS.contains(&format)) {
+                    let kind = LintKind::UnsupportedTextureFormat(texture.format);
+                    lints.push(Lint::entry(&name, kind));
+                }
+                if texture.tile_mode != PC_TILE_MODE {
+                    let kind = LintKind::ConsoleTileMode(texture.tile_mode);
+                    lints.push(Lint::entry(&name, kind));
+                }
+            }
+        }
+    }
+}
+
+fn lint_path(name: &str, lints: &mut Vec<Lint>) {
+    let len = name.chars().count();
+    if len > MAX_PATH_LEN {
+        let kind = LintKind::PathTooLong {
+            len,
+            max: MAX_PATH_LEN,
+        };
+        lints.push(Lint::entry(name, kind));
+    }
+}
+
+/// The first name found with every hash, to find distinct names with the same hash.
+struct Hashes<K>(BTreeMap<K, String>);
+
+impl<K> Default for Hashes<K> {
+    fn default() -> Hashes<K> {
+        Hashes(BTreeMap::new())
+    }
+}
+
+impl<K: Ord> Hashes<K> {
+    /// The first name found with `hash`, if it is not `name`.
+    fn collision(&mut self, hash: K, name: &str) -> Option<String> {
+        let first = self.0.entry(hash).or_insert_with(|| name.to_owned());
+        if normalize(first) == normalize(name) {
+            None
+        } else {
+            Some(first.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use fo4_ba2::{Ba2Writer, Format};
+    use tes4_bsa::{ArchiveFlags, FileFlags, SseWriter};
+
+    use super::{lint, LintKind, Severity};
+    use crate::{ArchiveType, Game};
+
+    #[test]
+    fn test_lint() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let long_path = format!("meshes/{}/{}.nif", "a".repeat(200), "b".repeat(60));
+
+        let mut writer = SseWriter::new();
+        writer.set_archive_flags(
+            ArchiveFlags::INCLUDE_DIRNAMES
+                | ArchiveFlags::INCLUDE_FILENAMES
+                | ArchiveFlags::RETAIN_STRINGS,
+        );
+        writer.set_file_flags(FileFlags::MESHES);
+        writer.set_compressed(true);
+        writer.add("sound/fx/a.xwm", vec![0; 100]).unwrap();
+        writer.add(&long_path, vec![0; 100]).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        writer.write_to(&mut out).unwrap();
+        let archive = dir.join("Mod.bsa");
+        fs::write(&archive, out.into_inner()).unwrap();
+
+        let lints = lint(&archive, Game::SkyrimSE).unwrap();
+        let kinds: Vec<_> = lints.iter().map(|lint| lint.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            [
+                LintKind::IgnoredArchiveFlags(ArchiveFlags::RETAIN_STRINGS),
+                LintKind::MissingFileFlags(FileFlags::SOUNDS),
+                LintKind::ForbiddenArchiveCompression(FileFlags::SOUNDS),
+                LintKind::PathTooLong { len: 272, max: 254 },
+                LintKind::ForbiddenCompression,
+            ]
+        );
+        assert_eq!(lints[2].severity(), Severity::Error);
+        assert_eq!(lints[4].entry.as_deref(), Some("sound/fx/a.xwm"));
+        assert_eq!(lints[0].severity(), Severity::Warning);
+
+        let lints = lint(&archive, Game::Skyrim).unwrap();
+        assert_eq!(
+            lints[0].kind,
+            LintKind::WrongArchiveType {
+                expected: ArchiveType::Tes5,
+                found: ArchiveType::Sse
+            }
+        );
+        assert_eq!(lints[0].severity(), Severity::Error);
+    }
+
+    #[test]
+    fn test_lint_ba2_version() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        let mut writer = Ba2Writer::new(Format::General);
+        writer.add("meshes/a.nif", vec![0; 100]).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        writer.write_to(&mut out).unwrap();
+        let mut bytes = out.into_inner();
+        let archive = dir.join("Mod - Main.ba2");
+        fs::write(&archive, &bytes).unwrap();
+        assert_eq!(lint(&archive, Game::Fallout4).unwrap(), []);
+
+        // A Starfield archive.
+        bytes[4..8].copy_from_slice(&2u32.to_le_bytes());
+        fs::write(&archive, &bytes).unwrap();
+        let lints = lint(&archive, Game::Fallout4).unwrap();
+        let kinds: Vec<_> = lints.iter().map(|lint| lint.kind.clone()).collect();
+        assert_eq!(kinds, [LintKind::UnsupportedBa2Version(2)]);
+        assert_eq!(lints[0].severity(), Severity::Error);
+    }
+}
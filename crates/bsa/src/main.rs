@@ -0,0 +1,302 @@
+use std::{
+    env,
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    process,
+};
+
+use bsa::{
+    conflicts::{self, mod_folders_vfs},
+    diff::{diff_paths, AttributeChange, Change},
+    edit::Editor,
+    layout::{self, Layout},
+    lint::{lint, Severity},
+    manifest::build_manifest,
+    merge::{merge_paths, Precedence},
+    patch::{apply_patch, create_patch},
+    redundant::{clean, find_redundant},
+    repack,
+    verify::Report,
+    ArchiveType, Ba2, Game, SseArchive, Tes4Archive, Tes5Archive,
+};
+
+const USAGE: &str = "usage:
+    bsa verify <archive>...
+    bsa lint <game> <archive>...
+    bsa conflicts <mod folder>...
+    bsa diff <old> <new>
+    bsa patch <old> <new> <patch>
+    bsa apply <old> <patch> <new>
+    bsa update <archive> <dir>
+    bsa remove <archive> <new> <path>...
+    bsa merge <new> <archive>...
+    bsa redundant <archive> <master>...
+    bsa clean <archive> <new> <master>...
+    bsa unpack <archive> <dir> [<layout>]
+    bsa repack <dir> <layout> <new>
+    bsa build <manifest>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match args.split_first() {
+        Some((command, paths)) if !paths.is_empty() => match command.as_str() {
+            "verify" => verify(paths),
+            "lint" if paths.len() >= 2 => match Game::from_name(&paths[0]) {
+                Some(game) => lint_archives(game, &paths[1..]),
+                None => usage(),
+            },
+            "conflicts" => report_errors(conflicts(paths)),
+            "diff" if paths.len() == 2 => report_errors(diff(&paths[0], &paths[1])),
+            "patch" if paths.len() == 3 => report_errors(patch(&paths[0], &paths[1], &paths[2])),
+            "apply" if paths.len() == 3 => report_errors(apply(&paths[0], &paths[1], &paths[2])),
+            "update" if paths.len() == 2 => report_errors(update(&paths[0], &paths[1])),
+            "merge" if paths.len() >= 2 => report_errors(merge(&paths[0], &paths[1..])),
+            "remove" if paths.len() >= 3 => {
+                report_errors(remove(&paths[0], &paths[1], &paths[2..]))
+            }
+            "redundant" if paths.len() >= 2 => report_errors(redundant(&paths[0], &paths[1..])),
+            "clean" if paths.len() >= 3 => {
+                report_errors(clean_archive(&paths[0], &paths[1], &paths[2..]))
+            }
+            "unpack" if matches!(paths.len(), 2 | 3) => {
+                report_errors(unpack(&paths[0], &paths[1], paths.get(2)))
+            }
+            "build" if paths.len() == 1 => report_errors(build(&paths[0])),
+            "repack" if paths.len() == 3 => report_errors(repack(&paths[0], &paths[1], &paths[2])),
+            _ => usage(),
+        },
+        _ => usage(),
+    };
+    process::exit(code);
+}
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+fn report_errors(result: Result<(), Box<dyn Error>>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+/// Rebuilds a BSA from a directory, compressing only the files that changed.
+fn update(archive: &str, dir: &str) -> Result<(), Box<dyn Error>> {
+    let summary = repack::update(archive, dir)?;
+    println!(
+        "{} reused, {} compressed, {} removed",
+        summary.reused, summary.compressed, summary.removed
+    );
+    Ok(())
+}
+
+/// Merges archives into one. Files of later archives take precedence, as they do in
+/// the game's load order.
+fn merge(new: &str, archives: &[String]) -> Result<(), Box<dyn Error>> {
+    let summary = merge_paths(archives, new, None, Precedence::Last)?;
+    println!("{} files, {} overridden", summary.files, summary.overridden);
+    Ok(())
+}
+
+/// Writes a copy of an archive without some of its files.
+fn remove(archive: &str, new: &str, paths: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut editor = Editor::open(archive)?;
+    for path in paths {
+        editor.remove(path)?;
+    }
+    editor.commit(BufWriter::new(File::create(new)?))?;
+    Ok(())
+}
+
+/// Lists the files of an archive that are identical to those of the master archives.
+fn redundant(archive: &str, masters: &[String]) -> Result<(), Box<dyn Error>> {
+    for file in find_redundant(archive, masters)? {
+        println!(
+            "{} ({} bytes, from {})",
+            file.path, file.size, masters[file.master]
+        );
+    }
+    Ok(())
+}
+
+/// Writes a copy of an archive without the files identical to those of the master
+/// archives.
+fn clean_archive(archive: &str, new: &str, masters: &[String]) -> Result<(), Box<dyn Error>> {
+    let removed = clean(archive, new, masters)?;
+    let size: u64 = removed.iter().map(|file| file.size).sum();
+    println!("{} files removed, {} bytes", removed.len(), size);
+    Ok(())
+}
+
+/// Extracts every file of an archive, and saves its layout if requested.
+fn unpack(archive: &str, dir: &str, layout: Option<&String>) -> Result<(), Box<dyn Error>> {
+    let unpacked = layout::unpack(archive, dir)?;
+    if let Some(path) = layout {
+        unpacked.save(path)?;
+    }
+    Ok(())
+}
+
+/// Builds an archive from unpacked files and the layout saved when unpacking them.
+fn repack(dir: &str, layout: &str, new: &str) -> Result<(), Box<dyn Error>> {
+    let summary = layout::pack(dir, &Layout::load(layout)?, new)?;
+    println!("{} files", summary.files);
+    Ok(())
+}
+
+/// Builds the archives described by a TOML manifest.
+fn build(manifest: &str) -> Result<(), Box<dyn Error>> {
+    for path in build_manifest(manifest)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// Writes a patch from one version of a BSA to another.
+fn patch(old: &str, new: &str, patch: &str) -> Result<(), Box<dyn Error>> {
+    let old = BufReader::new(File::open(old)?);
+    let new = BufReader::new(File::open(new)?);
+    let out = BufWriter::new(File::create(patch)?);
+    let summary = create_patch(old, new, out)?;
+    println!(
+        "{} added, {} removed, {} modified, {} unchanged",
+        summary.added, summary.removed, summary.modified, summary.unchanged
+    );
+    Ok(())
+}
+
+/// Rebuilds the new version of a BSA from the old version and a patch.
+fn apply(old: &str, patch: &str, new: &str) -> Result<(), Box<dyn Error>> {
+    let old = BufReader::new(File::open(old)?);
+    let patch = BufReader::new(File::open(patch)?);
+    let out = BufWriter::new(File::create(new)?);
+    apply_patch(old, patch, out)?;
+    Ok(())
+}
+
+/// Prints every difference between two archives or directories.
+fn diff(old: &str, new: &str) -> Result<(), Box<dyn Error>> {
+    for difference in diff_paths(old, new)? {
+        let (status, attributes) = match &difference.change {
+            Change::Added => ("A", &[][..]),
+            Change::Removed => ("D", &[][..]),
+            Change::Modified(attributes) => ("M", &attributes[..]),
+            Change::Metadata(attributes) => ("m", &attributes[..]),
+            Change::Conflict { old, new } => {
+                println!("C {} ({} old, {} new entries)", difference.path, old, new);
+                continue;
+            }
+        };
+        if difference.path.is_empty() {
+            println!("{} (archive)", status);
+        } else {
+            println!("{} {}", status, difference.path);
+        }
+        for AttributeChange { name, old, new } in attributes {
+            println!("    {}: {} -> {}", name, old, new);
+        }
+    }
+    Ok(())
+}
+
+/// Prints every path provided by more than one mod, with the mod that wins.
+fn conflicts(folders: &[String]) -> Result<(), Box<dyn Error>> {
+    let vfs = mod_folders_vfs(folders)?;
+    for conflict in conflicts::conflicts(&vfs)? {
+        println!("{} (from {})", conflict.path, conflict.winner.source_name());
+        for shadowed in &conflict.shadowed {
+            let contents = if shadowed.identical {
+                "identical"
+            } else {
+                "differs"
+            };
+            println!("    {} ({})", shadowed.file.source_name(), contents);
+        }
+    }
+    Ok(())
+}
+
+/// Verifies every archive, printing any problems found. Returns the exit code.
+fn verify(paths: &[String]) -> i32 {
+    let mut code = 0;
+    for path in paths {
+        match verify_archive(Path::new(path)) {
+            Ok(report) => {
+                for issue in &report.issues {
+                    println!("{}: {}", path, issue);
+                }
+                if report.is_ok() {
+                    println!("{}: ok ({} entries)", path, report.entries);
+                } else {
+                    println!(
+                        "{}: {} problem(s) in {} entries",
+                        path,
+                        report.issues.len(),
+                        report.entries
+                    );
+                    code = 1;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+/// Checks every archive against the limits of the game, printing any problems found.
+/// Returns the exit code, which is 1 if any errors were found.
+fn lint_archives(game: Game, paths: &[String]) -> i32 {
+    let mut code = 0;
+    for path in paths {
+        match lint(path, game) {
+            Ok(lints) => {
+                for lint in &lints {
+                    println!("{}: {}", path, lint);
+                }
+                let errors = lints
+                    .iter()
+                    .filter(|lint| lint.severity() == Severity::Error)
+                    .count();
+                if lints.is_empty() {
+                    println!("{}: ok", path);
+                } else {
+                    println!(
+                        "{}: {} error(s), {} warning(s)",
+                        path,
+                        errors,
+                        lints.len() - errors
+                    );
+                }
+                if errors > 0 {
+                    code = 1;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+fn verify_archive(path: &Path) -> Result<Report, Box<dyn Error>> {
+    let r = BufReader::new(File::open(path)?);
+    let report = match ArchiveType::detect_file(path)? {
+        ArchiveType::Tes4 => Tes4Archive::new(r)?.verify()?,
+        ArchiveType::Tes5 => Tes5Archive::new(r)?.verify()?,
+        ArchiveType::Sse => SseArchive::new(r)?.verify()?,
+        ArchiveType::Ba2 => Ba2::new_lenient(r)?.verify()?,
+    };
+    Ok(report)
+}
@@ -0,0 +1,425 @@
+//! Build archives from a TOML manifest describing their contents.
+//!
+//! A manifest names the game, and lists the archives to build. Each archive is
+//! packed from one or more source directories, filtered by include and exclude
+//! globs, and split to stay under a size limit as described in [crate::pack]. Paths
+//! in the manifest are relative to the directory it is in.
+//!
+//! ```toml
+//! game = "skyrimse"
+//! output = "build"
+//!
+//! [[archive]]
+//! name = "My Mod - Textures"
+//! sources = ["data", "patches"]
+//! include = ["textures/**/*.dds"]
+//! exclude = ["**/*_test.dds"]
+//! limit = 1_000_000_000
+//!
+//! [[archive]]
+//! name = "My Mod"
+//! sources = ["data"]
+//! exclude = ["textures/**"]
+//! file_flags = ["meshes", "sounds"]
+//!
+//! [archive.compression]
+//! zlib_level = 9
+//! min_savings = 0.05
+//! extensions = { ogg = false }
+//! ```
+//!
+//! Globs match paths relative to their source directory, ignoring case, with `/` as
+//! the separator. `*` does not match across directories, while `**` does. When
+//! several sources of an archive hold the same path, the last of them is packed.
+//!
+//! Every setting but `name` and `sources` is optional:
+//!
+//! - `include` and `exclude`: globs of the files to pack, and of the files to leave
+//!   out. Without `include`, every file is packed.
+//! - `limit`: the largest an archive may be, in bytes.
+//! - `compressed`: whether files are compressed by default.
+//! - `deduplicate`: whether identical files share their data.
+//! - `threads`: the number of threads compressing files.
+//! - `compression`: `zlib_level`, `lz4_level`, `min_savings`, rules by `extensions`,
+//!   and for BSAs the `uncompressed_file_flags` of archives that are left uncompressed
+//!   as a whole. Extension rules are added to the game's own, described in
+//!   [Game::compression_policy]. Skyrim Special Edition compresses with LZ4, so it
+//!   takes `lz4_level` and refuses `zlib_level`, while every other game refuses
+//!   `lz4_level`.
+//! - For BSAs: `archive_flags` and `file_flags`, as lists of flag names in lower
+//!   case such as `include_dirnames` and `meshes`, `embed_names`, and `data_order`,
+//!   either `hash` or `added`. Flags that are not given are inferred from the game
+//!   and the files of each archive, as described in [Game::archive_flags] and
+//!   [FileFlags::for_path].
+//! - For BA2s: `format`, either `general` or `dx10`, and `include_names`.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bsa_core::{
+    vfs::{normalize, LooseFiles, Source},
+    ReadError, Result,
+};
+use fo4_ba2::Format;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use tes4_bsa::{ArchiveFlags, DataOrder, FileFlags};
+
+use crate::{
+    pack::{pack_files, PackFile, PackOptions},
+    ArchiveType, Game,
+};
+
+const ARCHIVE_FLAGS: &[(&str, ArchiveFlags)] = &[
+    ("include_dirnames", ArchiveFlags::INCLUDE_DIRNAMES),
+    ("include_filenames", ArchiveFlags::INCLUDE_FILENAMES),
+    ("compressed", ArchiveFlags::COMPRESSED),
+    ("retain_dirnames", ArchiveFlags::RETAIN_DIRNAMES),
+    ("retain_filenames", ArchiveFlags::RETAIN_FILENAMES),
+    (
+        "retain_filename_offsets",
+        ArchiveFlags::RETAIN_FILENAME_OFFSETS,
+    ),
+    ("xbox360", ArchiveFlags::XBOX360),
+    ("retain_strings", ArchiveFlags::RETAIN_STRINGS),
+    ("embed_filenames", ArchiveFlags::EMBED_FILENAMES),
+    ("xmem", ArchiveFlags::XMEM),
+];
+
+const FILE_FLAGS: &[(&str, FileFlags)] = &[
+    ("meshes", FileFlags::MESHES),
+    ("textures", FileFlags::TEXTURES),
+    ("menus", FileFlags::MENUS),
+    ("sounds", FileFlags::SOUNDS),
+    ("voices", FileFlags::VOICES),
+    ("shaders", FileFlags::SHADERS),
+    ("trees", FileFlags::TREES),
+    ("fonts", FileFlags::FONTS),
+    ("misc", FileFlags::MISC),
+];
+
+/// A manifest, as parsed from TOML.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The name of the game, as accepted by [Game::from_name].
+    pub game: String,
+    /// The directory archives are written to.
+    pub output: Option<PathBuf>,
+    #[serde(rename = "archive", default)]
+    pub archives: Vec<ArchiveManifest>,
+}
+
+/// The description of an archive, split into several if it is too large.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveManifest {
+    /// The name of the archive, without extension. Split archives are numbered as
+    /// described in [Game::split_archive_name].
+    pub name: String,
+    pub sources: Vec<PathBuf>,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub limit: Option<u64>,
+    pub compressed: Option<bool>,
+    pub deduplicate: Option<bool>,
+    pub threads: Option<usize>,
+    #[serde(default)]
+    pub compression: CompressionManifest,
+    pub archive_flags: Option<Vec<String>>,
+    pub file_flags: Option<Vec<String>>,
+    pub embed_names: Option<bool>,
+    pub data_order: Option<String>,
+    pub format: Option<String>,
+    pub include_names: Option<bool>,
+}
+
+/// Which files of an archive are compressed, and how.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompressionManifest {
+    pub zlib_level: Option<u32>,
+    pub lz4_level: Option<u32>,
+    pub min_savings: Option<f64>,
+    /// Whether files with an extension, without the leading period, are compressed.
+    #[serde(default)]
+    pub extensions: BTreeMap<String, bool>,
+    pub uncompressed_file_flags: Option<Vec<String>>,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Manifest> {
+        toml::from_str(text).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Manifest> {
+        Manifest::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Build every archive, resolving relative paths against `base`. Returns the
+    /// paths of the archives written.
+    ///
+    /// # Errors
+    /// If any setting is invalid or an archive matches no files, in which case no
+    /// archive is written, or if packing fails as described in
+    /// [crate::pack::pack_split].
+    pub fn build<P: AsRef<Path>>(&self, base: P) -> Result<Vec<PathBuf>> {
+        let base = base.as_ref();
+        let game = Game::from_name(&self.game)
+            .ok_or_else(|| invalid(format!("unknown game {:?}", self.game)))?;
+        let output = base.join(self.output.as_deref().unwrap_or_else(|| Path::new("")));
+
+        let archives = self
+            .archives
+            .iter()
+            .map(|archive| {
+                let options = archive
+                    .options(game)
+                    .map_err(|e| invalid(format!("archive {:?}: {}", archive.name, e)))?;
+                let files = archive.files(base)?;
+                if files.is_empty() {
+                    let message = format!("archive {:?}: no files match", archive.name);
+                    return Err(invalid(message));
+                }
+                Ok((archive, options, files))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        fs::create_dir_all(&output)?;
+        let mut paths = Vec::new();
+        for (archive, options, files) in archives {
+            paths.extend(pack_files(files, &output, &archive.name, &options)?);
+        }
+        Ok(paths)
+    }
+}
+
+/// Load the manifest at `path`, and build every archive it describes. Returns the
+/// paths of the archives written.
+pub fn build_manifest<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    Manifest::load(path)?.build(base)
+}
+
+impl ArchiveManifest {
+    fn options(&self, game: Game) -> std::result::Result<PackOptions, String> {
+        let mut options = PackOptions::new(game);
+        let ba2 = game.archive_type().bsa_version().is_none();
+        let only = |set: bool, name: &str, bsa: bool| {
+            if set && bsa == ba2 {
+                let format = if bsa { "BSAs" } else { "BA2s" };
+                return Err(format!("{} only applies to {}", name, format));
+            }
+            Ok(())
+        };
+        only(self.archive_flags.is_some(), "archive_flags", true)?;
+        only(self.file_flags.is_some(), "file_flags", true)?;
+        only(self.embed_names.is_some(), "embed_names", true)?;
+        only(self.data_order.is_some(), "data_order", true)?;
+        let uncompressed = &self.compression.uncompressed_file_flags;
+        only(uncompressed.is_some(), "uncompressed_file_flags", true)?;
+        only(self.format.is_some(), "format", false)?;
+        only(self.include_names.is_some(), "include_names", false)?;
+
+        if let Some(limit) = self.limit {
+            options.limit = limit;
+        }
+        if let Some(compressed) = self.compressed {
+            options.compressed = compressed;
+        }
+        if let Some(deduplicate) = self.deduplicate {
+            options.deduplicate = deduplicate;
+        }
+        options.threads = self.threads;
+
+        let compression = &self.compression;
+        if compression.zlib_level.is_some() && game.archive_type() == ArchiveType::Sse {
+            return Err("zlib_level does not apply to LZ4 compressed archives".to_owned());
+        }
+        if compression.lz4_level.is_some() && game.archive_type() != ArchiveType::Sse {
+            return Err("lz4_level only applies to LZ4 compressed archives".to_owned());
+        }
+        if let Some(level) = compression.zlib_level {
+            options.policy.set_zlib_level(level);
+        }
+        if let Some(level) = compression.lz4_level {
+            options.policy.set_lz4_level(level);
+        }
+        if compression.min_savings.is_some() {
+            options.policy.set_min_savings(compression.min_savings);
+        }
+        for (extension, &compressed) in &compression.extensions {
+            options.policy.set_extension(extension, compressed);
+        }
+        if let Some(names) = uncompressed {
+            options.uncompressed_file_flags = flags(FILE_FLAGS, FileFlags::empty(), names)?;
+        }
+
+        if let Some(names) = &self.archive_flags {
+            options.archive_flags = Some(flags(ARCHIVE_FLAGS, ArchiveFlags::empty(), names)?);
+        }
+        if let Some(embed) = self.embed_names {
+            let mut archive_flags = options
+                .archive_flags
+                .unwrap_or(ArchiveFlags::INCLUDE_DIRNAMES | ArchiveFlags::INCLUDE_FILENAMES);
+            archive_flags.set(ArchiveFlags::EMBED_FILENAMES, embed);
+            options.archive_flags = Some(archive_flags);
+        }
+        if let Some(names) = &self.file_flags {
+            options.file_flags = Some(flags(FILE_FLAGS, FileFlags::empty(), names)?);
+        }
+        if let Some(order) = &self.data_order {
+            options.data_order = match order.as_str() {
+                "hash" => DataOrder::Hash,
+                "added" => DataOrder::Added,
+                _ => return Err(format!("unknown data order {:?}", order)),
+            };
+        }
+
+        if let Some(format) = &self.format {
+            options.format = match format.as_str() {
+                "general" => Some(Format::General),
+                "dx10" => Some(Format::DirectX),
+                _ => return Err(format!("unknown format {:?}", format)),
+            };
+        }
+        if let Some(include_names) = self.include_names {
+            options.include_names = include_names;
+        }
+
+        glob_set(&self.include)?;
+        glob_set(&self.exclude)?;
+        Ok(options)
+    }
+
+    /// The files to pack, from every source directory.
+    fn files(&self, base: &Path) -> Result<Vec<PackFile>> {
+        let include = glob_set(&self.include).map_err(invalid)?;
+        let exclude = glob_set(&self.exclude).map_err(invalid)?;
+
+        let mut files = BTreeMap::new();
+        for source in &self.sources {
+            let dir = base.join(source);
+            let mut sources = LooseFiles::new(&dir)?;
+            sources.retain(|name| {
+                (self.include.is_empty() || include.is_match(name)) && !exclude.is_match(name)
+            });
+            for i in 0..sources.len() {
+                let name = sources.name(i).into_owned();
+                let file = PackFile {
+                    path: dir.join(&name),
+                    size: sources.size(i)?,
+                    name,
+                };
+                files.insert(normalize(&file.name), file);
+            }
+        }
+        Ok(files.into_values().collect())
+    }
+}
+
+fn glob_set(globs: &[String]) -> std::result::Result<GlobSet, String> {
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = GlobBuilder::new(glob)
+            .case_insensitive(true)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| e.to_string())?;
+        set.add(glob);
+    }
+    set.build().map_err(|e| e.to_string())
+}
+
+/// The flags named in `names`, looked up in `table`, added to `empty`.
+fn flags<F>(table: &[(&str, F)], empty: F, names: &[String]) -> std::result::Result<F, String>
+where
+    F: Copy + std::ops::BitOr<Output = F>,
+{
+    names.iter().try_fold(empty, |flags, name| {
+        let (_, flag) = table
+            .iter()
+            .find(|(flag_name, _)| flag_name == name)
+            .ok_or_else(|| format!("unknown flag {:?}", name))?;
+        Ok(flags | *flag)
+    })
+}
+
+fn invalid(message: String) -> bsa_core::Error {
+    ReadError::InvalidManifest(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use bsa_core::Archive;
+    use tes4_bsa::SseArchive;
+
+    use super::{build_manifest, Manifest};
+
+    #[test]
+    fn test_build_manifest() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for (path, contents) in [
+            ("data/meshes/a.nif", "a"),
+            ("data/meshes/b.nif", "old"),
+            ("data/meshes/notes.txt", "notes"),
+            ("patch/Meshes/B.nif", "new"),
+        ] {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let manifest = r#"
+            game = "SkyrimSE"
+            output = "out"
+
+            [[archive]]
+            name = "Mod"
+            sources = ["data", "patch"]
+            include = ["meshes/**"]
+            exclude = ["**/*.txt"]
+            file_flags = ["meshes"]
+
+            [archive.compression]
+            extensions = { nif = false }
+        "#;
+        fs::write(dir.join("mod.toml"), manifest).unwrap();
+
+        let paths = build_manifest(dir.join("mod.toml")).unwrap();
+        assert_eq!(paths, [dir.join("out").join("Mod.bsa")]);
+        let bsa = SseArchive::new(fs::File::open(&paths[0]).unwrap()).unwrap();
+        assert_eq!(bsa.entries().count(), 2);
+        let mut data = Vec::new();
+        bsa.by_name("meshes/b.nif")
+            .unwrap()
+            .extract_to(&mut data)
+            .unwrap();
+        assert_eq!(data, b"new");
+
+        let invalid = manifest.replace(r#"file_flags = ["meshes"]"#, r#"format = "dx10""#);
+        let error = Manifest::parse(&invalid).unwrap().build(dir).unwrap_err();
+        assert!(error.to_string().contains("format only applies to BA2s"));
+
+        let invalid = manifest.replace("extensions = { nif = false }", "zlib_level = 9");
+        let error = Manifest::parse(&invalid).unwrap().build(dir).unwrap_err();
+        assert!(error.to_string().contains("zlib_level does not apply"));
+
+        let valid = manifest.replace("extensions = { nif = false }", "lz4_level = 12");
+        Manifest::parse(&valid).unwrap().build(dir).unwrap();
+
+        let invalid = manifest.replace(r#"include = ["meshes/**"]"#, r#"include = ["*.esp"]"#);
+        let error = Manifest::parse(&invalid).unwrap().build(dir).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains(r#"archive "Mod": no files match"#));
+    }
+}
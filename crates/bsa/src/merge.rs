@@ -0,0 +1,155 @@
+//! Merge archives of any supported format into one.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bsa_core::{Result, WriteError};
+use fo4_ba2::Ba2;
+
+use crate::ArchiveType;
+
+pub use bsa_core::merge::{MergeSummary, Precedence};
+
+/// Merge the archives at `inputs` into a new archive at `output`.
+///
+/// BSAs of any version can be merged, into an archive of `target`, or of the version
+/// of the first archive if it is [None]. BA2s can only be merged with BA2s of the
+/// same format. See [tes4_bsa::merge::merge] and [fo4_ba2::merge].
+///
+/// The formats of all inputs are checked before anything is written. The merged
+/// archive is written next to `output`, and only replaces it once complete, so
+/// `output` is left alone if merging fails. `output` may not be one of the inputs.
+pub fn merge_paths<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    output: Q,
+    target: Option<ArchiveType>,
+    precedence: Precedence,
+) -> Result<MergeSummary> {
+    let output = output.as_ref();
+    if let Ok(output) = fs::canonicalize(output) {
+        for input in inputs {
+            if fs::canonicalize(input)? == output {
+                let message = format!("{} is one of the archives to merge", output.display());
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message).into());
+            }
+        }
+    }
+
+    let mut types = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        types.push(ArchiveType::detect_file(input)?);
+    }
+    let ba2 = match types.first() {
+        Some(&archive_type) => archive_type == ArchiveType::Ba2,
+        None => {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, "no archives to merge");
+            return Err(e.into());
+        }
+    };
+    if let Some(i) = types.iter().position(|&t| (t == ArchiveType::Ba2) != ba2) {
+        let path = inputs[i].as_ref().display().to_string();
+        return Err(WriteError::FormatMismatch(path).into());
+    }
+    if let Some(target) = target {
+        if (target == ArchiveType::Ba2) != ba2 {
+            let path = output.display().to_string();
+            return Err(WriteError::FormatMismatch(path).into());
+        }
+    }
+    if ba2 {
+        check_ba2_formats(inputs)?;
+    }
+
+    let readers = inputs
+        .iter()
+        .map(|input| Ok(BufReader::new(File::open(input)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let temp_path = with_suffix(output, ".tmp");
+    let mut out = BufWriter::new(File::create(&temp_path)?);
+    let result = if ba2 {
+        fo4_ba2::merge(readers, precedence, &mut out).map_err(Into::into)
+    } else {
+        let version = target.and_then(ArchiveType::bsa_version);
+        tes4_bsa::merge::merge(readers, version, precedence, &mut out)
+    };
+    let result = result.and_then(|summary| {
+        out.flush()?;
+        Ok(summary)
+    });
+    drop(out);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    } else {
+        fs::rename(&temp_path, output)?;
+    }
+    result
+}
+
+/// Check that every BA2 is of the same format as the first.
+fn check_ba2_formats<P: AsRef<Path>>(inputs: &[P]) -> Result<()> {
+    let mut format = None;
+    for input in inputs {
+        let ba2 = Ba2::new(BufReader::new(File::open(input)?))?;
+        if *format.get_or_insert(ba2.format()) != ba2.format() {
+            let path = input.as_ref().display().to_string();
+            return Err(WriteError::FormatMismatch(path).into());
+        }
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::BufWriter, path::Path};
+
+    use fo4_ba2::{Ba2Writer, Format};
+
+    use super::{merge_paths, Precedence};
+
+    fn write_ba2(path: &Path, format: Format) {
+        let mut writer = Ba2Writer::new(format);
+        if format == Format::General {
+            writer.add("meshes/a.nif", b"a".to_vec()).unwrap();
+        }
+        writer
+            .write_to(BufWriter::new(fs::File::create(path).unwrap()))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_merge_checks_inputs() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let (general, textures) = (dir.join("general.ba2"), dir.join("textures.ba2"));
+        write_ba2(&general, Format::General);
+        write_ba2(&textures, Format::DirectX);
+        let original = fs::read(&general).unwrap();
+
+        // Merging into one of the inputs is refused before anything is written.
+        let inputs = [&general, &general];
+        assert!(merge_paths(&inputs, &general, None, Precedence::Last).is_err());
+        assert_eq!(fs::read(&general).unwrap(), original);
+
+        // So is merging archives of different formats.
+        let output = dir.join("merged.ba2");
+        let inputs = [&general, &textures];
+        assert!(merge_paths(&inputs, &output, None, Precedence::Last).is_err());
+        assert!(!output.exists());
+
+        let inputs = [&general, &general];
+        let summary = merge_paths(&inputs, &output, None, Precedence::Last).unwrap();
+        assert_eq!(summary.files, 1);
+        assert!(output.exists());
+    }
+}
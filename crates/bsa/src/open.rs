@@ -0,0 +1,90 @@
+use std::{
+    fmt::{self, Display},
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use bsa_core::{vfs::Vfs, ReadError, Result};
+use fo4_ba2::Ba2;
+use tes4_bsa::{SseArchive, Tes4Archive, Tes5Archive, Version};
+
+/// The format of an archive, as determined from its header. Morrowind's TES3
+/// archives are not supported, and are reported as having an invalid header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArchiveType {
+    /// A version 103 BSA, used by Oblivion.
+    Tes4,
+    /// A version 104 BSA, used by Fallout 3, New Vegas and Skyrim.
+    Tes5,
+    /// A version 105 BSA, used by Skyrim Special Edition.
+    Sse,
+    /// A BA2, used by Fallout 4.
+    Ba2,
+}
+
+impl ArchiveType {
+    /// Determine the format of an archive from the first bytes of its header.
+    pub fn detect<R: Read>(mut r: R) -> Result<ArchiveType> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        let version = u32::from_le_bytes([magic[4], magic[5], magic[6], magic[7]]);
+        match (&magic[..4], version) {
+            (b"BSA\0", 103) => Ok(ArchiveType::Tes4),
+            (b"BSA\0", 104) => Ok(ArchiveType::Tes5),
+            (b"BSA\0", 105) => Ok(ArchiveType::Sse),
+            (b"BTDX", _) => Ok(ArchiveType::Ba2),
+            _ => Err(ReadError::InvalidHeader.into()),
+        }
+    }
+
+    /// Determine the format of the archive at `path`.
+    pub fn detect_file<P: AsRef<Path>>(path: P) -> Result<ArchiveType> {
+        ArchiveType::detect(File::open(path)?)
+    }
+
+    /// The version of a BSA, or [None] for a BA2.
+    pub fn bsa_version(self) -> Option<Version> {
+        match self {
+            ArchiveType::Tes4 => Some(Version::V103),
+            ArchiveType::Tes5 => Some(Version::V104),
+            ArchiveType::Sse => Some(Version::V105),
+            ArchiveType::Ba2 => None,
+        }
+    }
+}
+
+impl Display for ArchiveType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ArchiveType::Tes4 => "version 103 BSA",
+            ArchiveType::Tes5 => "version 104 BSA",
+            ArchiveType::Sse => "version 105 BSA",
+            ArchiveType::Ba2 => "BA2",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Open the archive at `path`, whatever its format, and add it to a [Vfs] as the
+/// last archive in the load order. The source is named after the file name.
+pub fn add_archive_to_vfs<P: AsRef<Path>>(vfs: &mut Vfs, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    add_named_archive_to_vfs(vfs, name, path)
+}
+
+pub(crate) fn add_named_archive_to_vfs(vfs: &mut Vfs, name: String, path: &Path) -> Result<()> {
+    let r = || -> io::Result<_> { Ok(BufReader::new(File::open(path)?)) };
+
+    match ArchiveType::detect_file(path)? {
+        ArchiveType::Tes4 => vfs.add_archive(name, Tes4Archive::new(r()?)?),
+        ArchiveType::Tes5 => vfs.add_archive(name, Tes5Archive::new(r()?)?),
+        ArchiveType::Sse => vfs.add_archive(name, SseArchive::new(r()?)?),
+        ArchiveType::Ba2 => vfs.add_archive(name, Ba2::new(r()?)?),
+    }
+    Ok(())
+}
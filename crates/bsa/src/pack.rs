@@ -0,0 +1,373 @@
+//! Pack a directory into archives, split to stay under a size limit.
+//!
+//! The size of each archive is estimated from the uncompressed size of its files,
+//! along with the records and names stored for them. Files are never stored larger
+//! than their contents, so the estimate is an upper bound, and archives of files that
+//! compress end up smaller than the limit. The written archive is checked against the
+//! limit as well.
+//!
+//! Files are packed in path order, and every folder is kept in a single archive
+//! unless it is larger than the limit by itself.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use bsa_core::{
+    policy::CompressionPolicy,
+    vfs::{normalize, LooseFiles, Source},
+    Result, WriteError,
+};
+use fo4_ba2::{Ba2Writer, Format};
+use tes4_bsa::{ArchiveFlags, DataOrder, FileFlags, RawWriter};
+
+use crate::Game;
+
+/// The longest BSA the games load. Offsets are read as signed 32-bit integers, so
+/// data past 2 GiB cannot be reached.
+pub const MAX_BSA_LEN: u64 = 1 << 31;
+
+/// The limit used when none is given, which keeps BSAs loadable.
+pub const DEFAULT_LIMIT: u64 = MAX_BSA_LEN;
+
+/// The size of an archive's header, rounded up.
+const HEADER_OVERHEAD: u64 = 64;
+/// The size of a folder's records, besides its name.
+const FOLDER_OVERHEAD: u64 = 32;
+/// The size of a file's records, besides its name and data, along with the length
+/// prefix of compressed data and of an embedded name. Names are counted twice, as
+/// they may be embedded before the data as well.
+const FILE_OVERHEAD: u64 = 64;
+
+/// How to pack a directory.
+#[derive(Debug, Clone)]
+pub struct PackOptions {
+    pub game: Game,
+    /// The largest an archive may be, in bytes.
+    pub limit: u64,
+    pub compressed: bool,
+    /// Which files are compressed. Files without a rule follow `compressed`.
+    pub policy: CompressionPolicy,
+    /// Whether identical files share their data.
+    pub deduplicate: bool,
+    /// The number of threads compressing files, or [None] for the number of CPUs.
+    pub threads: Option<usize>,
+
+    /// The flags of BSAs, or [None] for those chosen by [Game::archive_flags]. The
+    /// compressed flag follows `compressed` regardless.
+    pub archive_flags: Option<ArchiveFlags>,
+    /// The file flags of BSAs, or [None] to infer them from the files of each
+    /// archive, as described in [FileFlags::for_path].
+    pub file_flags: Option<FileFlags>,
+    /// BSAs with any of these file flags are not compressed, unless a rule of
+    /// `policy` says otherwise.
+    pub uncompressed_file_flags: FileFlags,
+    /// The order of the data of BSAs. Files are added in path order.
+    pub data_order: DataOrder,
+
+    /// The format of BA2s, or [None] for DX10 archives if every file is a DDS
+    /// texture, and general archives otherwise.
+    pub format: Option<Format>,
+    /// Whether BA2s include the names of their files.
+    pub include_names: bool,
+}
+
+impl PackOptions {
+    /// Options following the game's compression policy, described in
+    /// [Game::compression_policy].
+    pub fn new(game: Game) -> PackOptions {
+        PackOptions {
+            game,
+            limit: DEFAULT_LIMIT,
+            compressed: true,
+            policy: game.compression_policy(),
+            deduplicate: true,
+            threads: None,
+            archive_flags: None,
+            file_flags: None,
+            uncompressed_file_flags: game.uncompressed_file_flags(),
+            data_order: DataOrder::Hash,
+            format: None,
+            include_names: true,
+        }
+    }
+}
+
+/// Pack every file in `dir` into archives for the game in `out_dir`, named after
+/// `base` as described in [Game::split_archive_name]. Returns the paths of the
+/// archives written.
+///
+/// # Errors
+/// If a file is larger than the limit by itself, or if an archive would be too large
+/// for its format.
+pub fn pack_split<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    out_dir: Q,
+    base: &str,
+    options: &PackOptions,
+) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let sources = LooseFiles::new(dir)?;
+    let mut files = Vec::with_capacity(sources.len());
+    for i in 0..sources.len() {
+        let name = sources.name(i).into_owned();
+        files.push(PackFile {
+            path: dir.join(&name),
+            size: sources.size(i)?,
+            name,
+        });
+    }
+    pack_files(files, out_dir.as_ref(), base, options)
+}
+
+/// A file to pack.
+pub(crate) struct PackFile {
+    /// The path of the file in the archive.
+    pub name: String,
+    /// The path of the file on disk.
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Pack `files` into archives, as described in [pack_split].
+pub(crate) fn pack_files(
+    mut files: Vec<PackFile>,
+    out_dir: &Path,
+    base: &str,
+    options: &PackOptions,
+) -> Result<Vec<PathBuf>> {
+    files.sort_by_cached_key(|file| normalize(&file.name));
+
+    let archives = plan(&files, options.limit)?;
+    let mut paths = Vec::with_capacity(archives.len());
+    for (i, archive) in archives.iter().enumerate() {
+        let name = options.game.split_archive_name(base, i, archives.len());
+        let path = out_dir.join(name);
+        let archive_files = archive.iter().map(|&file| &files[file]);
+        write_archive(archive_files, &path, options)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Split files, sorted by path, into archives. Returns the indices of the files in
+/// each archive.
+fn plan(files: &[PackFile], limit: u64) -> Result<Vec<Vec<usize>>> {
+    let mut archives = Vec::new();
+    let mut current = Vec::new();
+    let mut used = HEADER_OVERHEAD;
+
+    let mut start = 0;
+    while start < files.len() {
+        let folder = parent(&files[start].name);
+        let len = files[start..]
+            .iter()
+            .take_while(|file| parent(&file.name) == folder)
+            .count();
+        let folder_files = start..start + len;
+        start += len;
+
+        let file_cost = |i: usize| {
+            let file = &files[i];
+            file.size + 2 * file.name.len() as u64 + FILE_OVERHEAD
+        };
+        let folder_overhead = folder.len() as u64 + FOLDER_OVERHEAD;
+        let folder_cost = folder_overhead + folder_files.clone().map(file_cost).sum::<u64>();
+
+        if used + folder_cost > limit && !current.is_empty() {
+            archives.push(std::mem::take(&mut current));
+            used = HEADER_OVERHEAD;
+        }
+        if used + folder_cost <= limit {
+            current.extend(folder_files);
+            used += folder_cost;
+            continue;
+        }
+
+        // The folder does not fit in an archive of its own, so split it.
+        used += folder_overhead;
+        for i in folder_files {
+            let cost = file_cost(i);
+            if HEADER_OVERHEAD + folder_overhead + cost > limit {
+                return Err(WriteError::ExceedsLimit(files[i].name.clone()).into());
+            }
+            if used + cost > limit {
+                archives.push(std::mem::take(&mut current));
+                used = HEADER_OVERHEAD + folder_overhead;
+            }
+            current.push(i);
+            used += cost;
+        }
+    }
+    if !current.is_empty() {
+        archives.push(current);
+    }
+    Ok(archives)
+}
+
+/// The folder of a path, normalized.
+fn parent(path: &str) -> String {
+    let path = normalize(path);
+    match path.rfind('/') {
+        Some(i) => path[..i].to_owned(),
+        None => String::new(),
+    }
+}
+
+fn write_archive<'a, I>(files: I, path: &Path, options: &PackOptions) -> Result<()>
+where
+    I: Iterator<Item = &'a PackFile> + Clone,
+{
+    let mut out = BufWriter::new(File::create(path)?);
+    match options.game.archive_type().bsa_version() {
+        Some(version) => {
+            let mut writer = RawWriter::new(version);
+            for file in files {
+                writer.add_send(&file.name, SourceFile(file.path.clone()))?;
+            }
+            if let Some(flags) = options.file_flags {
+                writer.set_file_flags(flags);
+            }
+            let archive_flags = options
+                .archive_flags
+                .unwrap_or_else(|| options.game.archive_flags(writer.file_flags()));
+            writer.set_archive_flags(archive_flags);
+            writer.set_compressed(options.compressed);
+            writer.set_policy(options.policy.clone());
+            writer.add_file_flags_rule(options.uncompressed_file_flags, false);
+            writer.set_deduplicate(options.deduplicate);
+            writer.set_data_order(options.data_order);
+            writer.set_max_len(Some(options.limit));
+            if let Some(threads) = options.threads {
+                writer.set_threads(threads);
+            }
+            writer.write_to(out)?;
+        }
+        None => {
+            let format = options.format.unwrap_or_else(|| {
+                let textures = files
+                    .clone()
+                    .all(|file| file.name.to_lowercase().ends_with(".dds"));
+                if textures {
+                    Format::DirectX
+                } else {
+                    Format::General
+                }
+            });
+            let mut writer = Ba2Writer::new(format);
+            writer.set_compressed(options.compressed);
+            writer.set_policy(options.policy.clone());
+            writer.set_deduplicate(options.deduplicate);
+            writer.set_include_names(options.include_names);
+            if let Some(threads) = options.threads {
+                writer.set_threads(threads);
+            }
+            for file in files {
+                writer.add_send(&file.name, SourceFile(file.path.clone()))?;
+            }
+            writer.write_to(&mut out)?;
+            // The writer has no limit of its own, so the whole archive is checked.
+            if out.stream_position()? > options.limit {
+                return Err(WriteError::ExceedsLimit(path.display().to_string()).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A source file, opened only when the archive is written.
+struct SourceFile(PathBuf);
+
+impl tes4_bsa::FileData for SourceFile {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        Ok(io::copy(&mut File::open(&self.0)?, w)?)
+    }
+}
+
+impl fo4_ba2::FileData for SourceFile {
+    fn write_to(&mut self, w: &mut dyn Write) -> fo4_ba2::Result<u64> {
+        Ok(io::copy(&mut File::open(&self.0)?, w)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use bsa_core::Archive;
+    use tes4_bsa::{FileFlags, SseArchive};
+
+    use super::{pack_split, PackOptions};
+    use crate::Game;
+
+    #[test]
+    fn test_pack_split() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let source = dir.join("source");
+        let files = [
+            "textures/a/1.dds",
+            "textures/a/2.dds",
+            "textures/b/1.dds",
+            "textures/c/1.dds",
+        ];
+        for path in files {
+            let path = source.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, [0; 1000]).unwrap();
+        }
+
+        // Each folder fits in an archive, but no two of them do.
+        let mut options = PackOptions::new(Game::SkyrimSE);
+        options.limit = 2300;
+        let paths = pack_split(&source, dir, "Mod - Textures", &options).unwrap();
+        let names: Vec<_> = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Mod - Textures0.bsa",
+                "Mod - Textures1.bsa",
+                "Mod - Textures2.bsa"
+            ]
+        );
+        let first = SseArchive::new(fs::File::open(&paths[0]).unwrap()).unwrap();
+        assert_eq!(first.entries().count(), 2);
+
+        options.limit = 1000;
+        assert!(pack_split(&source, dir, "Mod - Textures", &options).is_err());
+    }
+
+    #[test]
+    fn test_pack_uncompressed_file_flags() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let source = dir.join("source");
+        for path in ["meshes/a.nif", "sound/fx/a.wav"] {
+            let path = source.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, [0; 1000]).unwrap();
+        }
+
+        // The rule for sounds applies to the whole archive, so the meshes packed along
+        // with them are left uncompressed too.
+        let options = PackOptions::new(Game::SkyrimSE);
+        let paths = pack_split(&source, dir, "Mod", &options).unwrap();
+        let bsa = SseArchive::new(fs::File::open(&paths[0]).unwrap()).unwrap();
+        assert_eq!(bsa.file_flags(), FileFlags::MESHES | FileFlags::SOUNDS);
+        assert!(bsa
+            .entries()
+            .all(|entry| bsa.compression(entry.index()).is_none()));
+
+        fs::remove_file(source.join("sound/fx/a.wav")).unwrap();
+        let paths = pack_split(&source, dir, "Mod", &options).unwrap();
+        let bsa = SseArchive::new(fs::File::open(&paths[0]).unwrap()).unwrap();
+        assert!(bsa
+            .entries()
+            .all(|entry| bsa.compression(entry.index()).is_some()));
+    }
+}
@@ -1,2 +1,3 @@
 pub use bsa_core::{Archive, Entries, Entry};
+pub use fo4_ba2::Ba2;
 pub use tes4_bsa::{FnvArchive, Fo3Archive, SseArchive, Tes4Archive, Tes5Archive};
@@ -0,0 +1,135 @@
+//! Find the files of a mod's archive that are identical to the base game's.
+//!
+//! Mods often ship files unchanged from the game's own archives. They take up space,
+//! and show up as conflicts with the game and with every mod that changes them.
+//!
+//! Files are matched by normalized path against the master archives, and compared by
+//! a digest of their decompressed contents. When several masters contain a path, the
+//! file is compared with the last of them, since that is the one the game loads.
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use bsa_core::Result;
+
+use crate::{diff::Side, edit::Editor};
+
+/// A file identical to the one a master archive provides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redundant {
+    /// The normalized path of the file.
+    pub path: String,
+    /// The index of the master archive providing the same file.
+    pub master: usize,
+    /// The decompressed size of the file.
+    pub size: u64,
+}
+
+/// Every file of `archive` that is identical to the file at the same path in
+/// `masters`, in path order.
+///
+/// `archive` and `masters` may be archives of any supported format, or directories of
+/// loose files.
+pub fn find_redundant<P: AsRef<Path>, Q: AsRef<Path>>(
+    archive: P,
+    masters: &[Q],
+) -> Result<Vec<Redundant>> {
+    let archive = Side::open(archive)?;
+    let masters = masters.iter().map(Side::open).collect::<Result<Vec<_>>>()?;
+
+    let mut redundant = Vec::new();
+    for (path, index) in archive.paths() {
+        let found = masters
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, master)| Some((i, master, master.index_of(path)?)));
+        let (master, side, master_index) = match found {
+            Some(found) => found,
+            None => continue,
+        };
+        let fingerprint = archive.fingerprint(index)?;
+        if fingerprint == side.fingerprint(master_index)? {
+            redundant.push(Redundant {
+                path: path.to_owned(),
+                master,
+                size: fingerprint.len,
+            });
+        }
+    }
+    Ok(redundant)
+}
+
+/// Write a copy of `archive` to `output` without its redundant files, as found by
+/// [find_redundant]. Other files are copied as stored. Returns the files removed.
+pub fn clean<P: AsRef<Path>, Q: AsRef<Path>, M: AsRef<Path>>(
+    archive: P,
+    output: Q,
+    masters: &[M],
+) -> Result<Vec<Redundant>> {
+    let redundant = find_redundant(&archive, masters)?;
+    let mut editor = Editor::open(archive)?;
+    for file in &redundant {
+        editor.remove(&file.path)?;
+    }
+    editor.commit(BufWriter::new(File::create(output)?))?;
+    Ok(redundant)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor, path::Path};
+
+    use bsa_core::Archive;
+    use tes4_bsa::{SseArchive, SseWriter};
+
+    use super::{clean, find_redundant};
+
+    fn write_archive(path: &Path, files: &[(&str, &[u8])]) {
+        let mut writer = SseWriter::new();
+        for &(name, data) in files {
+            writer.add(name, data.to_vec()).unwrap();
+        }
+        let mut out = Cursor::new(Vec::new());
+        writer.write_to(&mut out).unwrap();
+        fs::write(path, out.into_inner()).unwrap();
+    }
+
+    #[test]
+    fn test_clean() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let masters = [dir.join("Skyrim - Meshes0.bsa"), dir.join("Update.bsa")];
+        write_archive(
+            &masters[0],
+            &[
+                ("meshes/a.nif", b"vanilla a"),
+                ("meshes/b.nif", b"vanilla b"),
+            ],
+        );
+        write_archive(&masters[1], &[("meshes/b.nif", b"updated b")]);
+        let archive = dir.join("Mod.bsa");
+        write_archive(
+            &archive,
+            &[
+                ("Meshes\\A.nif", b"vanilla a"),
+                ("meshes/b.nif", b"vanilla b"),
+                ("meshes/c.nif", b"new c"),
+            ],
+        );
+
+        // The mod's b.nif differs from the update's, which wins over the base game.
+        let redundant = find_redundant(&archive, &masters).unwrap();
+        let paths: Vec<_> = redundant
+            .iter()
+            .map(|r| (r.path.as_str(), r.master))
+            .collect();
+        assert_eq!(paths, [("meshes/a.nif", 0)]);
+
+        let cleaned = dir.join("Mod.clean.bsa");
+        clean(&archive, &cleaned, &masters).unwrap();
+        let bsa = SseArchive::new(fs::File::open(&cleaned).unwrap()).unwrap();
+        let mut names: Vec<_> = bsa.entries().map(|e| e.name().into_owned()).collect();
+        names.sort();
+        assert_eq!(names, ["meshes/b.nif", "meshes/c.nif"]);
+    }
+}
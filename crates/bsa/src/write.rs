@@ -0,0 +1,6 @@
+pub use bsa_core::write::WriteSummary;
+pub use fo4_ba2::{Ba2Writer, Format as Ba2Format, RawChunk, RawEntry};
+pub use tes4_bsa::{
+    ArchiveFlags, BsaWriter, FileData, FileFlags, FnvWriter, Fo3Writer, RawBlock, RawWriter,
+    SseWriter, Tes4Writer, Tes5Writer,
+};
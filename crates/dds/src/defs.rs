@@ -1,6 +1,9 @@
 use bitflags::bitflags;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
+/// The magic number at the start of every DDS file.
+pub const MAGIC: [u8; 4] = *b"DDS ";
+
 macro_rules! read_u32 {
     ($bytes:expr, $index:expr) => {
         u32::from_le_bytes($bytes[$index * 4..($index + 1) * 4].try_into().unwrap())
@@ -62,7 +65,17 @@ pub enum AlphaMode {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct FourCc([u8; 4]);
+pub struct FourCc(pub [u8; 4]);
+
+impl FourCc {
+    pub const NONE: FourCc = FourCc([0; 4]);
+    pub const DXT1: FourCc = FourCc(*b"DXT1");
+    pub const DXT3: FourCc = FourCc(*b"DXT3");
+    pub const DXT5: FourCc = FourCc(*b"DXT5");
+    pub const ATI1: FourCc = FourCc(*b"ATI1");
+    pub const ATI2: FourCc = FourCc(*b"ATI2");
+    pub const DX10: FourCc = FourCc(*b"DX10");
+}
 
 bitflags! {
     pub struct PixelFormatFlags: u32 {
@@ -105,6 +118,12 @@ bitflags! {
         const CUBEMAP_NEGATIVE_Y = 0x2000;
         const CUBEMAP_POSITIVE_Z = 0x4000;
         const CUBEMAP_NEGATIVE_Z = 0x8000;
+        const CUBEMAP_ALL_FACES = Self::CUBEMAP_POSITIVE_X.bits
+            | Self::CUBEMAP_NEGATIVE_X.bits
+            | Self::CUBEMAP_POSITIVE_Y.bits
+            | Self::CUBEMAP_NEGATIVE_Y.bits
+            | Self::CUBEMAP_POSITIVE_Z.bits
+            | Self::CUBEMAP_NEGATIVE_Z.bits;
         const VOLUME = 0x200000;
     }
 }
@@ -118,7 +137,204 @@ bitflags! {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
 #[repr(u32)]
 pub enum DxgiFormat {
-    Bc7,
+    Unknown = 0,
+    R32G32B32A32Typeless = 1,
+    R32G32B32A32Float = 2,
+    R32G32B32A32Uint = 3,
+    R32G32B32A32Sint = 4,
+    R32G32B32Typeless = 5,
+    R32G32B32Float = 6,
+    R32G32B32Uint = 7,
+    R32G32B32Sint = 8,
+    R16G16B16A16Typeless = 9,
+    R16G16B16A16Float = 10,
+    R16G16B16A16Unorm = 11,
+    R16G16B16A16Uint = 12,
+    R16G16B16A16Snorm = 13,
+    R16G16B16A16Sint = 14,
+    R32G32Typeless = 15,
+    R32G32Float = 16,
+    R32G32Uint = 17,
+    R32G32Sint = 18,
+    R32G8X24Typeless = 19,
+    D32FloatS8X24Uint = 20,
+    R32FloatX8X24Typeless = 21,
+    X32TypelessG8X24Uint = 22,
+    R10G10B10A2Typeless = 23,
+    R10G10B10A2Unorm = 24,
+    R10G10B10A2Uint = 25,
+    R11G11B10Float = 26,
+    R8G8B8A8Typeless = 27,
+    R8G8B8A8Unorm = 28,
+    R8G8B8A8UnormSrgb = 29,
+    R8G8B8A8Uint = 30,
+    R8G8B8A8Snorm = 31,
+    R8G8B8A8Sint = 32,
+    R16G16Typeless = 33,
+    R16G16Float = 34,
+    R16G16Unorm = 35,
+    R16G16Uint = 36,
+    R16G16Snorm = 37,
+    R16G16Sint = 38,
+    R32Typeless = 39,
+    D32Float = 40,
+    R32Float = 41,
+    R32Uint = 42,
+    R32Sint = 43,
+    R24G8Typeless = 44,
+    D24UnormS8Uint = 45,
+    R24UnormX8Typeless = 46,
+    X24TypelessG8Uint = 47,
+    R8G8Typeless = 48,
+    R8G8Unorm = 49,
+    R8G8Uint = 50,
+    R8G8Snorm = 51,
+    R8G8Sint = 52,
+    R16Typeless = 53,
+    R16Float = 54,
+    D16Unorm = 55,
+    R16Unorm = 56,
+    R16Uint = 57,
+    R16Snorm = 58,
+    R16Sint = 59,
+    R8Typeless = 60,
+    R8Unorm = 61,
+    R8Uint = 62,
+    R8Snorm = 63,
+    R8Sint = 64,
+    A8Unorm = 65,
+    R1Unorm = 66,
+    R9G9B9E5Sharedexp = 67,
+    R8G8B8G8Unorm = 68,
+    G8R8G8B8Unorm = 69,
+    Bc1Typeless = 70,
+    Bc1Unorm = 71,
+    Bc1UnormSrgb = 72,
+    Bc2Typeless = 73,
+    Bc2Unorm = 74,
+    Bc2UnormSrgb = 75,
+    Bc3Typeless = 76,
+    Bc3Unorm = 77,
+    Bc3UnormSrgb = 78,
+    Bc4Typeless = 79,
+    Bc4Unorm = 80,
+    Bc4Snorm = 81,
+    Bc5Typeless = 82,
+    Bc5Unorm = 83,
+    Bc5Snorm = 84,
+    B5G6R5Unorm = 85,
+    B5G5R5A1Unorm = 86,
+    B8G8R8A8Unorm = 87,
+    B8G8R8X8Unorm = 88,
+    R10G10B10XrBiasA2Unorm = 89,
+    B8G8R8A8Typeless = 90,
+    B8G8R8A8UnormSrgb = 91,
+    B8G8R8X8Typeless = 92,
+    B8G8R8X8UnormSrgb = 93,
+    Bc6hTypeless = 94,
+    Bc6hUf16 = 95,
+    Bc6hSf16 = 96,
+    Bc7Typeless = 97,
+    Bc7Unorm = 98,
+    Bc7UnormSrgb = 99,
+}
+
+impl DxgiFormat {
+    /// Returns the size in bytes of a single 4x4 block, or [None] if the format is
+    /// not block compressed.
+    pub fn block_size(self) -> Option<u32> {
+        use DxgiFormat::*;
+
+        match self {
+            Bc1Typeless | Bc1Unorm | Bc1UnormSrgb | Bc4Typeless | Bc4Unorm | Bc4Snorm => Some(8),
+            Bc2Typeless | Bc2Unorm | Bc2UnormSrgb | Bc3Typeless | Bc3Unorm | Bc3UnormSrgb
+            | Bc5Typeless | Bc5Unorm | Bc5Snorm | Bc6hTypeless | Bc6hUf16 | Bc6hSf16
+            | Bc7Typeless | Bc7Unorm | Bc7UnormSrgb => Some(16),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bits used by a single pixel, or [None] if the format is
+    /// block compressed or unknown.
+    pub fn bits_per_pixel(self) -> Option<u32> {
+        use DxgiFormat::*;
+
+        let bits = match self {
+            R32G32B32A32Typeless | R32G32B32A32Float | R32G32B32A32Uint | R32G32B32A32Sint => 128,
+            R32G32B32Typeless | R32G32B32Float | R32G32B32Uint | R32G32B32Sint => 96,
+            R16G16B16A16Typeless
+            | R16G16B16A16Float
+            | R16G16B16A16Unorm
+            | R16G16B16A16Uint
+            | R16G16B16A16Snorm
+            | R16G16B16A16Sint
+            | R32G32Typeless
+            | R32G32Float
+            | R32G32Uint
+            | R32G32Sint
+            | R32G8X24Typeless
+            | D32FloatS8X24Uint
+            | R32FloatX8X24Typeless
+            | X32TypelessG8X24Uint => 64,
+            R10G10B10A2Typeless
+            | R10G10B10A2Unorm
+            | R10G10B10A2Uint
+            | R11G11B10Float
+            | R8G8B8A8Typeless
+            | R8G8B8A8Unorm
+            | R8G8B8A8UnormSrgb
+            | R8G8B8A8Uint
+            | R8G8B8A8Snorm
+            | R8G8B8A8Sint
+            | R16G16Typeless
+            | R16G16Float
+            | R16G16Unorm
+            | R16G16Uint
+            | R16G16Snorm
+            | R16G16Sint
+            | R32Typeless
+            | D32Float
+            | R32Float
+            | R32Uint
+            | R32Sint
+            | R24G8Typeless
+            | D24UnormS8Uint
+            | R24UnormX8Typeless
+            | X24TypelessG8Uint
+            | R9G9B9E5Sharedexp
+            | R8G8B8G8Unorm
+            | G8R8G8B8Unorm
+            | B8G8R8A8Unorm
+            | B8G8R8X8Unorm
+            | R10G10B10XrBiasA2Unorm
+            | B8G8R8A8Typeless
+            | B8G8R8A8UnormSrgb
+            | B8G8R8X8Typeless
+            | B8G8R8X8UnormSrgb => 32,
+            R8G8Typeless | R8G8Unorm | R8G8Uint | R8G8Snorm | R8G8Sint | R16Typeless | R16Float
+            | D16Unorm | R16Unorm | R16Uint | R16Snorm | R16Sint | B5G6R5Unorm | B5G5R5A1Unorm => {
+                16
+            }
+            R8Typeless | R8Unorm | R8Uint | R8Snorm | R8Sint | A8Unorm => 8,
+            R1Unorm => 1,
+            _ => return None,
+        };
+
+        Some(bits)
+    }
+
+    /// Computes the pitch (for uncompressed formats) or the linear size of the top
+    /// level surface (for block compressed formats) of a `width` by `height` image.
+    pub fn pitch_or_linear_size(self, width: u32, height: u32) -> Option<u32> {
+        if let Some(block_size) = self.block_size() {
+            let blocks_wide = width.div_ceil(4).max(1);
+            let blocks_high = height.div_ceil(4).max(1);
+            Some(blocks_wide * blocks_high * block_size)
+        } else {
+            let bits = self.bits_per_pixel()?;
+            Some((width * bits).div_ceil(8))
+        }
+    }
 }
 
 impl Header {
@@ -153,6 +369,60 @@ impl Header {
             caps2,
         })
     }
+
+    pub fn to_bytes(&self) -> [u8; 124] {
+        let mut bytes = [0; 124];
+        let mut put_u32 = |index: usize, value: u32| {
+            bytes[index * 4..(index + 1) * 4].copy_from_slice(&value.to_le_bytes());
+        };
+
+        put_u32(0, 124);
+        put_u32(1, self.flags.bits());
+        put_u32(2, self.height);
+        put_u32(3, self.width);
+        put_u32(4, self.pitch_or_linear_size);
+        put_u32(5, self.depth);
+        put_u32(6, self.mipmap_count);
+        put_u32(26, self.caps.bits());
+        put_u32(27, self.caps2.bits());
+        bytes[18 * 4..18 * 4 + 32].copy_from_slice(&self.pixel_format.to_bytes());
+
+        bytes
+    }
+}
+
+impl HeaderDx10 {
+    pub fn from_bytes(bytes: [u8; 20]) -> Option<HeaderDx10> {
+        let format = DxgiFormat::try_from(read_u32!(bytes, 0)).ok()?;
+        let dimension = Dimension::try_from(read_u32!(bytes, 1)).ok()?;
+        let misc_flags = MiscFlags::from_bits(read_u32!(bytes, 2))?;
+        let array_size = read_u32!(bytes, 3);
+        let alpha_mode = AlphaMode::try_from(read_u32!(bytes, 4) & 0x7).ok()?;
+
+        Some(HeaderDx10 {
+            format,
+            dimension,
+            misc_flags,
+            array_size,
+            alpha_mode,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let fields = [
+            self.format.into(),
+            self.dimension.into(),
+            self.misc_flags.bits(),
+            self.array_size,
+            self.alpha_mode.into(),
+        ];
+
+        let mut bytes = [0; 20];
+        for (chunk, value) in bytes.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&u32::to_le_bytes(value));
+        }
+        bytes
+    }
 }
 
 impl PixelFormat {
@@ -187,4 +457,23 @@ impl PixelFormat {
 
         Some(pixel_format)
     }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let fields = [
+            32,
+            self.flags.bits(),
+            u32::from_le_bytes(self.fourcc.0),
+            self.rgb_bit_count,
+            self.red_bit_mask,
+            self.green_bit_mask,
+            self.blue_bit_mask,
+            self.alpha_bit_mask,
+        ];
+
+        let mut bytes = [0; 32];
+        for (chunk, value) in bytes.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&u32::to_le_bytes(value));
+        }
+        bytes
+    }
 }
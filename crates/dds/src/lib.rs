@@ -1,2 +1,4 @@
 mod compression;
 mod defs;
+
+pub use defs::*;
@@ -0,0 +1,90 @@
+use std::{
+    borrow::Cow,
+    io::{Read, Seek, Write},
+};
+
+use bsa_core::{detail::EntriesImpl, Archive, Entries, Entry};
+
+use crate::{
+    extract::hash_name,
+    hash::hash_file_path,
+    read::{Ba2Inner, ReadSeek},
+    Ba2,
+};
+
+/// Generic access to a BA2 archive, for use alongside other archive formats.
+///
+/// Entries are indexed in the order they are stored. Entries without a name are named
+/// after their hash, as when extracting. DX10 entries are read with a DDS header.
+impl<R> Archive for Ba2<R>
+where
+    R: Read + Seek,
+{
+    type Index = usize;
+
+    fn by_index(&self, index: Self::Index) -> Entry<'_, Self> {
+        if index >= self.inner.names.len() {
+            panic!("index out of range");
+        }
+        Entry::new(&self.inner, index)
+    }
+
+    fn by_name<S: AsRef<str>>(&self, name: S) -> Option<Entry<'_, Self>> {
+        let hash = hash_file_path(name.as_ref())?;
+        let index = *self.inner.index.get(&hash)?;
+        Some(Entry::new(&self.inner, index))
+    }
+
+    fn entries(&self) -> Entries<'_, Self> {
+        let start = if self.inner.names.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        Entries::new(&self.inner, start)
+    }
+}
+
+impl<R> EntriesImpl<Ba2<R>> for Ba2Inner<R>
+where
+    R: Read + Seek,
+{
+    fn next(&self, index: usize) -> Option<usize> {
+        let next = index + 1;
+        if next < self.names.len() {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    fn name(&self, index: usize) -> Cow<'_, str> {
+        match &self.names[index] {
+            Some(name) => Cow::Borrowed(name),
+            None => {
+                let ba2: &Ba2Inner<dyn ReadSeek> = self;
+                Cow::Owned(hash_name(ba2.entry(index).hash()))
+            }
+        }
+    }
+
+    fn extract_to(&self, index: usize, mut writer: &mut dyn Write) -> bsa_core::Result<()> {
+        let ba2: &Ba2Inner<dyn ReadSeek> = self;
+        ba2.entry(index).extract_to(&mut writer)?;
+        Ok(())
+    }
+
+    fn size(&self, index: usize) -> bsa_core::Result<u64> {
+        let ba2: &Ba2Inner<dyn ReadSeek> = self;
+        let entry = ba2.entry(index);
+        let header_len = match &entry {
+            crate::Entry::General(_) => 0,
+            crate::Entry::DirectX(e) => e.dds_header()?.len() as u64,
+        };
+        let data_len: u64 = entry
+            .chunks()
+            .map(|chunk| chunk.decompressed_size() as u64)
+            .sum();
+        Ok(header_len + data_len)
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    io::{Read, Seek, Write},
+    rc::Rc,
+};
+
+use bsa_core::WriteError;
+
+use crate::{
+    extract::hash_name,
+    write::{Ba2Writer, ReaderData, StoredEntry},
+    Ba2, Result,
+};
+
+/// Stages changes to an existing archive, and writes the result as a new archive.
+///
+/// Every entry of the archive is staged unchanged when it is opened. Entries that are
+/// not replaced are copied to the new archive as stored, without decompressing them.
+/// Entries keep their position in the archive, and new entries are added at the end.
+///
+/// # Examples
+/// Remove a single file from an archive.
+/// ```no_run
+/// use std::fs::File;
+///
+/// use fo4_ba2::{Ba2Editor, Result};
+///
+/// fn remove_file() -> Result<()> {
+///     let mut editor = Ba2Editor::open(File::open("Mod - Main.ba2")?)?;
+///     editor.remove("meshes\\clutter\\bucket.nif")?;
+///     editor.commit(File::create("Mod - Main.new.ba2")?)
+/// }
+/// ```
+pub struct Ba2Editor {
+    writer: Ba2Writer,
+}
+
+impl Ba2Editor {
+    /// Open an archive for editing. New files are compressed if any of the archive's
+    /// chunks are. An empty archive is compressed, as with a new [Ba2Writer].
+    ///
+    /// # Errors
+    /// If the archive cannot be read, or if any of its entries has no name.
+    pub fn open<R: 'static + Read + Seek>(r: R) -> Result<Ba2Editor> {
+        let ba2 = Rc::new(Ba2::new(r)?);
+        let mut writer = Ba2Writer::new(ba2.inner.format);
+        let mut chunks = ba2.entries().flat_map(|entry| entry.chunks()).peekable();
+        if chunks.peek().is_some() {
+            writer.set_compressed(chunks.any(|chunk| chunk.compressed_size().is_some()));
+        }
+
+        for (index, entry) in ba2.entries().enumerate() {
+            let name = entry
+                .name()
+                .ok_or_else(|| WriteError::MissingName(hash_name(entry.hash())))?;
+            let stored = StoredEntry {
+                archive: ba2.clone(),
+                index,
+            };
+            writer.add_stored(name, stored)?;
+        }
+        Ok(Ba2Editor { writer })
+    }
+
+    /// Whether the archive contains a file at `path`, including staged changes.
+    pub fn contains(&self, path: &str) -> bool {
+        self.writer.contains(path)
+    }
+
+    /// Add a new file, read from `r` when the archive is committed. Textures must be
+    /// complete DDS files.
+    ///
+    /// # Errors
+    /// If the archive already contains a file at `path`.
+    pub fn add<R: 'static + Read>(&mut self, path: &str, r: R) -> Result<()> {
+        if self.contains(path) {
+            return Err(WriteError::AlreadyExists(path.to_owned()).into());
+        }
+        self.writer.add(path, ReaderData(r))
+    }
+
+    /// Replace the contents of an existing file with data read from `r`.
+    ///
+    /// # Errors
+    /// If the archive does not contain a file at `path`.
+    pub fn replace<R: 'static + Read>(&mut self, path: &str, r: R) -> Result<()> {
+        if !self.contains(path) {
+            return Err(WriteError::NotFound(path.to_owned()).into());
+        }
+        self.writer.add(path, ReaderData(r))
+    }
+
+    /// Remove the file at `path`.
+    ///
+    /// # Errors
+    /// If the archive does not contain a file at `path`.
+    pub fn remove(&mut self, path: &str) -> Result<()> {
+        if !self.writer.remove(path) {
+            return Err(WriteError::NotFound(path.to_owned()).into());
+        }
+        Ok(())
+    }
+
+    /// Rename the file at `from` to `to`, which may be in a different folder. Its
+    /// contents are copied as stored.
+    ///
+    /// # Errors
+    /// If the archive does not contain a file at `from`, or already contains one at
+    /// `to`.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        if !self.contains(from) {
+            return Err(WriteError::NotFound(from.to_owned()).into());
+        }
+        if self.contains(to) {
+            return Err(WriteError::AlreadyExists(to.to_owned()).into());
+        }
+        self.writer.rename(from, to)?;
+        Ok(())
+    }
+
+    /// The number of files in the archive, including staged changes.
+    pub fn len(&self) -> usize {
+        self.writer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writer.is_empty()
+    }
+
+    /// Write the archive with every staged change applied.
+    ///
+    /// The new archive must not be written over the archive being edited, since
+    /// unchanged entries are read from it while writing.
+    pub fn commit<W: Write + Seek>(self, w: W) -> Result<()> {
+        self.writer.write_to(w)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{raw::Format, Ba2, Ba2Writer};
+
+    use super::Ba2Editor;
+
+    fn contents(bytes: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let ba2 = Ba2::new(Cursor::new(bytes.to_vec())).unwrap();
+        ba2.entries()
+            .map(|entry| {
+                let mut data = Vec::new();
+                entry.extract_to(&mut data).unwrap();
+                (entry.name().unwrap().to_owned(), data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut writer = Ba2Writer::new(Format::General);
+        writer.add("meshes/a.nif", b"aaaa".to_vec()).unwrap();
+        writer.add("meshes/b.nif", b"bbbb".to_vec()).unwrap();
+        writer.add("meshes/c.nif", b"cccc".to_vec()).unwrap();
+        let mut original = Cursor::new(Vec::new());
+        writer.write_to(&mut original).unwrap();
+
+        let mut editor = Ba2Editor::open(Cursor::new(original.into_inner())).unwrap();
+        editor.remove("Meshes\\A.nif").unwrap();
+        editor
+            .rename("meshes\\b.nif", "meshes/moved/b.nif")
+            .unwrap();
+        editor.replace("meshes/c.nif", &b"new c"[..]).unwrap();
+        editor.add("textures/d.txt", &b"dddd"[..]).unwrap();
+        assert!(editor.add("meshes/c.nif", &b""[..]).is_err());
+        assert!(editor.remove("meshes/a.nif").is_err());
+
+        let mut edited = Cursor::new(Vec::new());
+        editor.commit(&mut edited).unwrap();
+
+        let expected = [
+            ("meshes\\moved\\b.nif", &b"bbbb"[..]),
+            ("meshes\\c.nif", b"new c"),
+            ("textures\\d.txt", b"dddd"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(name, data)| (name.to_owned(), data.to_vec()))
+            .collect();
+        assert_eq!(contents(edited.get_ref()), expected);
+    }
+
+    #[test]
+    fn test_infer_compression() {
+        for compressed in [false, true] {
+            let mut writer = Ba2Writer::new(Format::General);
+            writer.set_compressed(compressed);
+            writer.add("meshes/a.nif", b"a".repeat(100)).unwrap();
+            let mut original = Cursor::new(Vec::new());
+            writer.write_to(&mut original).unwrap();
+
+            let mut editor = Ba2Editor::open(Cursor::new(original.into_inner())).unwrap();
+            let data = Cursor::new(b"b".repeat(100));
+            editor.add("meshes/b.nif", data).unwrap();
+            let mut edited = Cursor::new(Vec::new());
+            editor.commit(&mut edited).unwrap();
+
+            let ba2 = Ba2::new(Cursor::new(edited.into_inner())).unwrap();
+            let entry = ba2.by_name("meshes/b.nif").unwrap();
+            let chunk = entry.chunks().next().unwrap();
+            assert_eq!(chunk.compressed_size().is_some(), compressed);
+        }
+    }
+}
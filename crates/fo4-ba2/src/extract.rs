@@ -0,0 +1,187 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+};
+
+use threadpool::ThreadPool;
+
+use crate::{chunk_data::ChunkData, Entries, Entry, Hash, Result};
+
+/// Options for [Ba2::extract](crate::Ba2::extract).
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    /// The number of threads used to decompress and write files. Defaults to the
+    /// number of CPUs.
+    pub threads: usize,
+
+    /// Whether textures from DX10 archives are written as complete DDS files. When
+    /// disabled, the chunks of each texture are written exactly as stored. Defaults
+    /// to `true`.
+    pub dds_headers: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            threads: num_cpus::get(),
+            dds_headers: true,
+        }
+    }
+}
+
+/// Extracts every entry into `out`.
+///
+/// The archive is read sequentially on the calling thread. As soon as the (still
+/// compressed) data of an entry has been read into memory, it is handed to a
+/// threadpool to be decompressed and written to a file. See `performance.md` for the
+/// reasoning behind this strategy.
+///
+/// Only a few entries per thread are held in memory at once: reading waits for
+/// earlier entries to be written. Extraction stops at the first error.
+pub fn extract(entries: Entries, out: &Path, options: &ExtractOptions) -> Result<()> {
+    let threads = options.threads.max(1);
+    let pool = ThreadPool::new(threads);
+    let (sender, receiver) = channel();
+    let max_pending = threads * 2;
+    let mut pending = 0;
+
+    for entry in entries {
+        if pending == max_pending {
+            receiver.recv().map_err(io::Error::other)??;
+            pending -= 1;
+        }
+
+        let path = match entry.name() {
+            Some(name) => entry_path(out, name),
+            None => entry_path(out, &hash_name(entry.hash())),
+        };
+        let job = Job::read(&entry, options)?;
+        let sender = sender.clone();
+
+        pool.execute(move || {
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| job.save(&path))).unwrap_or_else(|_| {
+                    Err(io::Error::other("a thread extracting files panicked").into())
+                });
+            // The receiver is only gone once extraction has failed.
+            let _ = sender.send(result);
+        });
+        pending += 1;
+    }
+
+    for _ in 0..pending {
+        receiver.recv().map_err(io::Error::other)??;
+    }
+    Ok(())
+}
+
+/// The in-memory data of a single entry, ready to be decompressed and written.
+struct Job {
+    header: Option<Vec<u8>>,
+    chunks: Vec<ChunkData>,
+}
+
+impl Job {
+    fn read(entry: &Entry, options: &ExtractOptions) -> Result<Job> {
+        let header = match entry {
+            Entry::DirectX(e) if options.dds_headers => Some(e.dds_header()?),
+            _ => None,
+        };
+
+        let mut chunks = Vec::new();
+        for chunk in entry.chunks() {
+            chunks.push(chunk.data()?);
+        }
+
+        Ok(Job { header, chunks })
+    }
+
+    fn save(self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut out = BufWriter::new(File::create(path)?);
+        if let Some(header) = &self.header {
+            out.write_all(header)?;
+        }
+        for mut chunk in self.chunks {
+            io::copy(&mut chunk, &mut out)?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Joins an archive path onto `out`. Archive paths use '\\' as a separator, and any
+/// component that could escape `out` is dropped.
+pub(crate) fn entry_path(out: &Path, name: &str) -> PathBuf {
+    let mut path = out.to_owned();
+    for component in name.split(['\\', '/']) {
+        if !matches!(component, "" | "." | "..") {
+            path.push(component);
+        }
+    }
+    path
+}
+
+/// Builds a stand-in name for an entry without one, from the components of its hash.
+/// The extension is stored verbatim in the hash, so only the directory and file stem
+/// are lost.
+pub(crate) fn hash_name(hash: Hash) -> String {
+    let mut name = format!("{:08x}\\{:08x}", hash.directory(), hash.file());
+
+    let extension = hash.extension().to_le_bytes();
+    let extension = extension.iter().take_while(|&&byte| byte != 0);
+    for (i, &byte) in extension.enumerate() {
+        if i == 0 {
+            name.push('.');
+        }
+        name.push(windows_1252::decode(byte));
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use crate::{raw::Format, Ba2, Ba2Writer, ExtractOptions};
+
+    #[test]
+    fn test_extract() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let files: Vec<_> = (0..10)
+            .map(|i| {
+                (
+                    format!("meshes/dir{}/file{}.nif", i % 3, i),
+                    vec![i as u8; 100 * i],
+                )
+            })
+            .collect();
+
+        let mut writer = Ba2Writer::new(Format::General);
+        for (path, data) in &files {
+            writer.add(path, data.clone()).unwrap();
+        }
+        let mut out = Cursor::new(Vec::new());
+        writer.write_to(&mut out).unwrap();
+        let ba2 = Ba2::new(Cursor::new(out.into_inner())).unwrap();
+
+        // Fewer threads than files, so that reading waits for files to be written.
+        let options = ExtractOptions {
+            threads: 2,
+            ..Default::default()
+        };
+        ba2.extract(dir, &options).unwrap();
+        for (path, data) in &files {
+            assert_eq!(&fs::read(dir.join(path)).unwrap(), data);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! This module implements the Fallout 4 path hashing algorithm.
+//!
+//! Every BA2 entry is identified by a [Hash] of its path, made up of the CRC-32 of
+//! the directory, the CRC-32 of the file stem, and the first four bytes of the
+//! extension. Unlike names, these hashes are always present, so they can be used to
+//! look up entries in archives that were written without a string table.
+
+use crate::raw::{
+    path::{normalize, split, split_extension},
+    Hash,
+};
+
+/// Computes the hash of a file path, with normalization.
+///
+/// Both '\\' and '/' are accepted as separators, and letters are lowercased. Returns
+/// [None] if the path contains a character that cannot be encoded as Windows-1252, a
+/// '..' component, or is absolute, empty or too long.
+pub fn hash_file_path(path: &str) -> Option<Hash> {
+    let path = normalize(path.replace('\\', "/").to_lowercase())?;
+    if path.is_empty() {
+        return None;
+    }
+
+    let (directory, file_name) = unsafe { split(&path) };
+    let (stem, extension) = unsafe { split_extension(file_name) };
+
+    let mut ext = [0; 4];
+    let extension = extension.get(1..).unwrap_or_default();
+    for (dst, &src) in ext.iter_mut().zip(extension) {
+        *dst = src;
+    }
+
+    let hash = Hash::new(crc32(stem), u32::from_le_bytes(ext), crc32(directory));
+    Some(hash)
+}
+
+/// The CRC-32 used by the engine. This is the standard reflected CRC-32, but with an
+/// initial value of zero and no final inversion.
+fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB88320
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    let mut crc = 0u32;
+    for &byte in bytes {
+        crc = (crc >> 8) ^ TABLE[((crc ^ byte as u32) & 0xff) as usize];
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hash_file_path;
+
+    #[test]
+    fn test_hash_file_path() {
+        let hash = hash_file_path("Textures\\Armor\\Dog.dds").unwrap();
+        assert_eq!(hash.extension(), u32::from_le_bytes(*b"dds\0"));
+
+        let same = &[
+            "textures/armor/dog.dds",
+            "TEXTURES\\\\armor//DOG.dds",
+            "textures/armor/dog.dds/",
+        ];
+        for path in same {
+            assert_eq!(hash_file_path(path), Some(hash));
+        }
+
+        assert_ne!(hash_file_path("textures/armor/dog.nif"), Some(hash));
+        assert_ne!(hash_file_path("textures/dog.dds"), Some(hash));
+
+        assert_eq!(hash_file_path(""), None);
+        assert_eq!(hash_file_path("textures/../dog.dds"), None);
+        assert_eq!(hash_file_path("/textures/dog.dds"), None);
+        assert_eq!(hash_file_path("textures/🚀.dds"), None);
+    }
+}
@@ -0,0 +1,424 @@
+//! Unpack an archive along with its layout, and pack it back the same way.
+//!
+//! The layout records everything about an archive that is not in its files: the
+//! format and version, whether names are stored, the order of the entries, the
+//! description of every texture, how every entry is split into chunks and whether
+//! each chunk is compressed, and whether identical chunks share their data. Packing
+//! the unpacked files with their layout rebuilds the archive with the same records
+//! and names.
+//!
+//! Compressed chunks are compressed again when packing, so the result only matches
+//! the original byte for byte if it was compressed the same way, such as by this
+//! crate. The data of chunks is written in the order of the entries.
+//!
+//! A layout is saved as text, with the settings of the archive first and then, for
+//! every entry, a `file` line with its path, a `texture` line for textures, and a
+//! `chunk` line for each of its chunks.
+
+use std::{
+    collections::HashSet,
+    convert::TryInto,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use bsa_core::{vfs::normalize, write::WriteSummary, WriteError};
+
+use crate::{
+    extract::{entry_path, hash_name},
+    raw::{Format, Version},
+    texture::Texture,
+    Ba2, Ba2Writer, FileData, ReadError, Result,
+};
+
+const LAYOUT_HEADER: &str = "ba2-layout 2";
+
+/// The layout of an archive, as needed to rebuild it from its unpacked files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ba2Layout {
+    pub format: Format,
+    /// The version written in the header.
+    pub version: Version,
+    /// Whether the archive has a string table holding the name of every entry.
+    pub include_names: bool,
+    /// Whether chunks with identical stored contents share their data.
+    pub shared_data: bool,
+    /// Every entry, in the order of the records.
+    pub files: Vec<FileLayout>,
+}
+
+/// How a single entry is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLayout {
+    /// The path of the entry, as stored in the archive.
+    pub path: String,
+    pub entry: EntryLayout,
+}
+
+/// How the contents of an entry are stored. See
+/// [Ba2Writer::add_with_layout].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryLayout {
+    /// The description of the texture, for entries of DX10 archives.
+    pub texture: Option<Texture>,
+    pub chunks: Vec<ChunkLayout>,
+}
+
+/// How a range of the contents of an entry is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLayout {
+    pub compressed: bool,
+    /// The length of the range, after the DDS header for textures.
+    pub decompressed_size: u32,
+    /// The mipmaps contained in the chunk, for textures.
+    pub mip_first: u16,
+    pub mip_last: u16,
+}
+
+impl Ba2Layout {
+    /// Load a layout saved by [Ba2Layout::save].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Ba2Layout> {
+        let text = fs::read_to_string(path)?;
+        Ok(Ba2Layout::parse(&text).ok_or(ReadError::InvalidLayout)?)
+    }
+
+    fn parse(text: &str) -> Option<Ba2Layout> {
+        let mut lines = text.lines();
+        if lines.next()? != LAYOUT_HEADER {
+            return None;
+        }
+        let mut setting = |name: &str| {
+            let line = lines.next()?;
+            line.strip_prefix(name)?.strip_prefix(' ')
+        };
+        let format = match setting("format")? {
+            "general" => Format::General,
+            "dx10" => Format::DirectX,
+            _ => return None,
+        };
+        let version = match setting("version")? {
+            "1" => Version::V1,
+            _ => return None,
+        };
+        let include_names = setting("include-names")?.parse().ok()?;
+        let shared_data = setting("shared-data")?.parse().ok()?;
+
+        let mut files: Vec<FileLayout> = Vec::new();
+        for line in lines {
+            let (kind, rest) = line.split_once(' ')?;
+            if kind == "file" {
+                files.push(FileLayout {
+                    path: rest.to_owned(),
+                    entry: EntryLayout {
+                        texture: None,
+                        chunks: Vec::new(),
+                    },
+                });
+                continue;
+            }
+
+            let entry = &mut files.last_mut()?.entry;
+            let fields = rest
+                .split(' ')
+                .map(|field| field.parse().ok())
+                .collect::<Option<Vec<u32>>>()?;
+            match (kind, &fields[..]) {
+                ("texture", &[width, height, mip_count, format, flags, tile_mode]) => {
+                    entry.texture = Some(Texture {
+                        width: width.try_into().ok()?,
+                        height: height.try_into().ok()?,
+                        mip_count: mip_count.try_into().ok()?,
+                        format: format.try_into().ok()?,
+                        flags: flags.try_into().ok()?,
+                        tile_mode: tile_mode.try_into().ok()?,
+                    })
+                }
+                ("chunk", &[compressed, decompressed_size, mip_first, mip_last]) => {
+                    entry.chunks.push(ChunkLayout {
+                        compressed: compressed != 0,
+                        decompressed_size,
+                        mip_first: mip_first.try_into().ok()?,
+                        mip_last: mip_last.try_into().ok()?,
+                    })
+                }
+                _ => return None,
+            }
+        }
+        Some(Ba2Layout {
+            format,
+            version,
+            include_names,
+            shared_data,
+            files,
+        })
+    }
+
+    /// Save the layout as text.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let format = match self.format {
+            Format::General => "general",
+            Format::DirectX => "dx10",
+        };
+        let mut text = format!("{}\n", LAYOUT_HEADER);
+        writeln!(text, "format {}", format).unwrap();
+        writeln!(text, "version {}", self.version as u32).unwrap();
+        writeln!(text, "include-names {}", self.include_names).unwrap();
+        writeln!(text, "shared-data {}", self.shared_data).unwrap();
+        for file in &self.files {
+            writeln!(text, "file {}", file.path).unwrap();
+            if let Some(t) = file.entry.texture {
+                writeln!(
+                    text,
+                    "texture {} {} {} {} {} {}",
+                    t.width, t.height, t.mip_count, t.format, t.flags, t.tile_mode
+                )
+                .unwrap();
+            }
+            for chunk in &file.entry.chunks {
+                writeln!(
+                    text,
+                    "chunk {} {} {} {}",
+                    chunk.compressed as u8,
+                    chunk.decompressed_size,
+                    chunk.mip_first,
+                    chunk.mip_last
+                )
+                .unwrap();
+            }
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+/// Extract every entry of the archive at `archive` into `dir`, returning the layout
+/// of the archive. Textures are written as complete DDS files.
+///
+/// # Errors
+/// If the archive does not include names, or stores the same path more than once, as
+/// the files would overwrite each other. See [unpack_with_names] for archives without
+/// a string table.
+pub fn unpack<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, dir: Q) -> Result<Ba2Layout> {
+    unpack_inner(archive.as_ref(), dir.as_ref(), &[] as &[&str])
+}
+
+/// Like [unpack], but first recovers the names of unnamed entries from `candidates`,
+/// as by [Ba2::recover_names].
+///
+/// # Errors
+/// If an entry has neither a stored nor a recovered name.
+pub fn unpack_with_names<P, Q, S>(archive: P, dir: Q, candidates: &[S]) -> Result<Ba2Layout>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    S: AsRef<str>,
+{
+    unpack_inner(archive.as_ref(), dir.as_ref(), candidates)
+}
+
+fn unpack_inner<S: AsRef<str>>(
+    archive_path: &Path,
+    dir: &Path,
+    candidates: &[S],
+) -> Result<Ba2Layout> {
+    let mut ba2 = Ba2::new(BufReader::new(File::open(archive_path)?))?;
+    ba2.recover_names(candidates);
+
+    let mut paths = HashSet::new();
+    for path in ba2.entries().filter_map(|entry| entry.name()) {
+        if !paths.insert(normalize(path)) {
+            return Err(WriteError::DuplicatePath(path.to_owned()).into());
+        }
+    }
+
+    let mut offsets = Vec::new();
+    let mut files = Vec::new();
+    for entry in ba2.entries() {
+        let path = entry
+            .name()
+            .ok_or_else(|| WriteError::MissingName(hash_name(entry.hash())))?;
+        let local = entry_path(dir, path);
+        if let Some(parent) = local.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = BufWriter::new(File::create(&local)?);
+        entry.extract_to(&mut out)?;
+        out.flush()?;
+
+        offsets.extend(entry.chunks().map(|chunk| chunk.offset()));
+        files.push(FileLayout {
+            path: path.to_owned(),
+            entry: entry.layout(),
+        });
+    }
+    offsets.sort_unstable();
+    let shared_data = offsets.windows(2).any(|pair| pair[0] == pair[1]);
+
+    Ok(Ba2Layout {
+        format: ba2.format(),
+        version: ba2.version(),
+        include_names: ba2.has_string_table(),
+        shared_data,
+        files,
+    })
+}
+
+/// Write an archive of the files in `dir`, as described by `layout`. Files in `dir`
+/// that are not in the layout are left out.
+pub fn pack<P: AsRef<Path>, W: Write + Seek>(
+    dir: P,
+    layout: &Ba2Layout,
+    w: W,
+) -> Result<WriteSummary> {
+    let dir = dir.as_ref();
+    let mut writer = Ba2Writer::new(layout.format);
+    writer.set_version(layout.version);
+    writer.set_include_names(layout.include_names);
+    writer.set_deduplicate(layout.shared_data);
+    for file in &layout.files {
+        let source = SourceFile(entry_path(dir, &file.path));
+        writer.add_with_layout(&file.path, source, file.entry.clone())?;
+    }
+    writer.write_to(w)
+}
+
+/// A source file, opened only when the archive is written.
+struct SourceFile(PathBuf);
+
+impl FileData for SourceFile {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        let mut f = File::open(&self.0)?;
+        Ok(io::copy(&mut f, w)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use bytemuck::Zeroable;
+    use dds::DxgiFormat;
+
+    use crate::{
+        raw::{DataFileIndex, DirectXChunkHeader, Format, Hash},
+        texture::{dds_header, Texture},
+        Ba2Writer,
+    };
+
+    use super::{pack, unpack, unpack_with_names, Ba2Layout, ChunkLayout, EntryLayout};
+
+    #[test]
+    fn test_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let unpacked = dir.join("unpacked");
+
+        // A texture with two mipmaps, stored as one chunk each.
+        let header = DirectXChunkHeader {
+            id: Hash::zeroed(),
+            data_file_index: DataFileIndex::zeroed(),
+            chunk_count: 2,
+            height: 8,
+            width: 8,
+            mip_count: 2,
+            format: u32::from(DxgiFormat::Bc1Unorm) as u8,
+            flags: 0,
+            tile_mode: 8,
+        };
+        let mut dds = dds_header(&header).unwrap();
+        dds.extend_from_slice(&[0x55; 32]);
+        dds.extend_from_slice(&[0xaa; 8]);
+        let chunk = |compressed, decompressed_size, mip| ChunkLayout {
+            compressed,
+            decompressed_size,
+            mip_first: mip,
+            mip_last: mip,
+        };
+        let layout = EntryLayout {
+            texture: Some(Texture::from(&header)),
+            chunks: vec![chunk(true, 32, 0), chunk(false, 8, 1)],
+        };
+
+        let mut writer = Ba2Writer::new(Format::DirectX);
+        writer
+            .add_with_layout("textures/b.dds", dds.clone(), layout.clone())
+            .unwrap();
+        writer.add("textures/a.dds", dds).unwrap();
+        let archive = dir.join("test.ba2");
+        writer
+            .write_to(fs::File::create(&archive).unwrap())
+            .unwrap();
+
+        let unpacked_layout = unpack(&archive, &unpacked).unwrap();
+        assert_eq!(unpacked_layout.files[0].path, "textures\\b.dds");
+        assert_eq!(unpacked_layout.files[0].entry, layout);
+
+        unpacked_layout.save(dir.join("layout")).unwrap();
+        let loaded = Ba2Layout::load(dir.join("layout")).unwrap();
+        assert_eq!(loaded, unpacked_layout);
+
+        let mut packed = Cursor::new(Vec::new());
+        pack(&unpacked, &loaded, &mut packed).unwrap();
+        assert_eq!(packed.into_inner(), fs::read(&archive).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip_without_names() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let unpacked = dir.join("unpacked");
+
+        let mut writer = Ba2Writer::new(Format::General);
+        writer.set_include_names(false);
+        writer.add("meshes/a.nif", vec![0x11; 64]).unwrap();
+        writer.add("meshes/b.nif", vec![0x22; 64]).unwrap();
+        let archive = dir.join("test.ba2");
+        writer
+            .write_to(fs::File::create(&archive).unwrap())
+            .unwrap();
+
+        assert!(unpack(&archive, dir.join("unnamed")).is_err());
+        let names = ["meshes\\a.nif", "meshes\\b.nif"];
+        let unpacked_layout = unpack_with_names(&archive, &unpacked, &names).unwrap();
+        assert!(!unpacked_layout.include_names);
+        assert_eq!(fs::read(unpacked.join("meshes/b.nif")).unwrap(), [0x22; 64]);
+
+        unpacked_layout.save(dir.join("layout")).unwrap();
+        let loaded = Ba2Layout::load(dir.join("layout")).unwrap();
+        assert_eq!(loaded, unpacked_layout);
+
+        let mut packed = Cursor::new(Vec::new());
+        pack(&unpacked, &loaded, &mut packed).unwrap();
+        assert_eq!(packed.into_inner(), fs::read(&archive).unwrap());
+    }
+
+    #[test]
+    fn test_unpack_duplicate_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        let mut writer = Ba2Writer::new(Format::General);
+        writer.add("meshes/a.nif", vec![0x11; 8]).unwrap();
+        writer.add("meshes/b.nif", vec![0x22; 8]).unwrap();
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write_to(&mut bytes).unwrap();
+
+        // Rename the second entry in the string table, differing only in case.
+        let mut bytes = bytes.into_inner();
+        let at = bytes.windows(5).position(|w| w == b"b.nif").unwrap();
+        bytes[at] = b'A';
+        let archive = dir.join("test.ba2");
+        fs::write(&archive, bytes).unwrap();
+
+        let unpacked = dir.join("unpacked");
+        let error = unpack(&archive, &unpacked).unwrap_err();
+        assert!(
+            error.to_string().contains("stored more than once"),
+            "{}",
+            error
+        );
+        assert!(!unpacked.exists());
+    }
+}
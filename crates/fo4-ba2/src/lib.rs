@@ -1,17 +1,33 @@
 use std::io;
 
-use raw::Format;
 use thiserror::Error;
 
+pub mod hash;
+pub mod layout;
+
+mod archive;
 mod chunk_data;
 mod common;
+mod edit;
+mod extract;
+mod merge;
 mod raw;
 mod read;
+mod texture;
+mod verify;
+mod write;
 
+pub use bsa_core::{policy::CompressionPolicy, write::WriteSummary, WriteError};
+pub use edit::Ba2Editor;
+pub use extract::ExtractOptions;
+pub use merge::merge;
+pub use raw::{Format, Hash, Version};
 pub use read::{
     Ba2, Chunk, Chunks, DirectXChunk, DirectXChunks, DirectXEntry, Entries, Entry, GeneralChunk,
     GeneralChunks, GeneralEntry,
 };
+pub use texture::Texture;
+pub use write::{Ba2Writer, FileData, RawChunk, RawEntry, ReaderData};
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -21,10 +37,23 @@ pub enum Error {
     #[error(transparent)]
     Read(#[from] ReadError),
 
+    #[error(transparent)]
+    Write(#[from] WriteError),
+
     #[error(transparent)]
     Io(#[from] io::Error),
 }
 
+impl From<Error> for bsa_core::Error {
+    fn from(e: Error) -> bsa_core::Error {
+        match e {
+            Error::Io(e) => e.into(),
+            Error::Read(e) => io::Error::new(io::ErrorKind::InvalidData, e).into(),
+            Error::Write(e) => e.into(),
+        }
+    }
+}
+
 #[non_exhaustive]
 #[derive(Debug, Error)]
 pub enum ReadError {
@@ -42,4 +71,10 @@ pub enum ReadError {
 
     #[error("invalid chunk sentinel: 0x{0:x} (required to be 0xBAADF00D)")]
     InvalidChunkSentinel(u32),
+
+    #[error("unsupported texture format: {0}")]
+    UnsupportedTextureFormat(u8),
+
+    #[error("invalid layout")]
+    InvalidLayout,
 }
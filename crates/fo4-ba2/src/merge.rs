@@ -0,0 +1,104 @@
+use std::{
+    io::{self, Read, Seek, Write},
+    rc::Rc,
+};
+
+use bsa_core::{
+    merge::{MergeSummary, Precedence},
+    WriteError,
+};
+
+use crate::{
+    extract::hash_name,
+    write::{Ba2Writer, StoredEntry},
+    Ba2, Result,
+};
+
+/// Merge the archives `inputs` into a single archive, and write it to `out`.
+///
+/// Every chunk is copied as stored, without decompressing it.
+///
+/// # Errors
+/// If `inputs` is empty, if any of the entries has no name, or if the archives are
+/// not all of the same format.
+pub fn merge<R, W>(inputs: Vec<R>, precedence: Precedence, out: W) -> Result<MergeSummary>
+where
+    R: 'static + Read + Seek,
+    W: Write + Seek,
+{
+    let mut writer: Option<Ba2Writer> = None;
+    let mut summary = MergeSummary::default();
+
+    for r in inputs {
+        let ba2 = Rc::new(Ba2::new(r)?);
+        let writer = writer.get_or_insert_with(|| Ba2Writer::new(ba2.inner.format));
+
+        for (index, entry) in ba2.entries().enumerate() {
+            let name = entry
+                .name()
+                .ok_or_else(|| WriteError::MissingName(hash_name(entry.hash())))?;
+            if writer.contains(name) {
+                summary.overridden += 1;
+                if precedence == Precedence::First {
+                    continue;
+                }
+            }
+            let stored = StoredEntry {
+                archive: ba2.clone(),
+                index,
+            };
+            writer.add_stored(name, stored)?;
+        }
+    }
+
+    let writer = writer
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no archives to merge"))?;
+    summary.files = writer.len();
+    writer.write_to(out)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bsa_core::merge::Precedence;
+
+    use crate::{Ba2, Ba2Writer, Format};
+
+    use super::merge;
+
+    fn archive(format: Format, files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut writer = Ba2Writer::new(format);
+        for &(path, contents) in files {
+            writer.add(path, contents.to_vec()).unwrap();
+        }
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write_to(&mut bytes).unwrap();
+        bytes.set_position(0);
+        bytes
+    }
+
+    #[test]
+    fn test_merge() {
+        let inputs = vec![
+            archive(Format::General, &[("meshes/a.nif", b"1")]),
+            archive(
+                Format::General,
+                &[("meshes/a.nif", b"2"), ("meshes/b.nif", b"b")],
+            ),
+        ];
+        let mut out = Cursor::new(Vec::new());
+        let summary = merge(inputs, Precedence::First, &mut out).unwrap();
+        assert_eq!((summary.files, summary.overridden), (2, 1));
+
+        out.set_position(0);
+        let ba2 = Ba2::new(out).unwrap();
+        let mut data = Vec::new();
+        ba2.by_name("meshes/a.nif")
+            .unwrap()
+            .extract_to(&mut data)
+            .unwrap();
+        assert_eq!(data, b"1");
+    }
+}
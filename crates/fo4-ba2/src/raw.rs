@@ -14,7 +14,7 @@ pub const CHUNK_DATA_SENTINEL: u32 = 0xBAADF00D;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Version {
-    V1,
+    V1 = 1,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -249,19 +249,32 @@ pub struct GeneralChunkData {
     pub decompressed_size: u32,
 }
 
+impl RawGeneralChunkData {
+    pub fn sentinel(&self) -> u32 {
+        u32::from_le_bytes(self.sentinel)
+    }
+}
+
+impl GeneralChunkData {
+    /// Convert a chunk record without checking its sentinel.
+    pub fn from_raw_unchecked(data: RawGeneralChunkData) -> Self {
+        GeneralChunkData {
+            data_file_offset: u64::from_le_bytes(data.data_file_offset),
+            compressed_size: NonZeroU32::new(u32::from_le_bytes(data.compressed_size)),
+            decompressed_size: u32::from_le_bytes(data.decompressed_size),
+        }
+    }
+}
+
 impl TryFrom<RawGeneralChunkData> for GeneralChunkData {
     type Error = ReadError;
 
     fn try_from(data: RawGeneralChunkData) -> Result<Self, Self::Error> {
-        let sentinel = u32::from_le_bytes(data.sentinel);
+        let sentinel = data.sentinel();
         if sentinel != CHUNK_DATA_SENTINEL {
             Err(ReadError::InvalidChunkSentinel(sentinel))
         } else {
-            Ok(GeneralChunkData {
-                data_file_offset: u64::from_le_bytes(data.data_file_offset),
-                compressed_size: NonZeroU32::new(u32::from_le_bytes(data.compressed_size)),
-                decompressed_size: u32::from_le_bytes(data.decompressed_size),
-            })
+            Ok(GeneralChunkData::from_raw_unchecked(data))
         }
     }
 }
@@ -308,26 +321,40 @@ pub struct DirectXChunkData {
     pub mip_last: u16,
 }
 
+impl RawDirectXChunkData {
+    pub fn sentinel(&self) -> u32 {
+        u32::from_le_bytes(self.sentinel)
+    }
+}
+
+impl DirectXChunkData {
+    /// Convert a chunk record without checking its sentinel.
+    pub fn from_raw_unchecked(data: RawDirectXChunkData) -> Self {
+        DirectXChunkData {
+            data_file_offset: u64::from_le_bytes(data.data_file_offset),
+            compressed_size: NonZeroU32::new(u32::from_le_bytes(data.compressed_size)),
+            decompressed_size: u32::from_le_bytes(data.decompressed_size),
+            mip_first: u16::from_le_bytes(data.mip_first),
+            mip_last: u16::from_le_bytes(data.mip_last),
+        }
+    }
+}
+
 impl TryFrom<RawDirectXChunkData> for DirectXChunkData {
     type Error = ReadError;
 
     fn try_from(data: RawDirectXChunkData) -> Result<Self, Self::Error> {
-        let sentinel = u32::from_le_bytes(data.sentinel);
+        let sentinel = data.sentinel();
         if sentinel != CHUNK_DATA_SENTINEL {
             Err(ReadError::InvalidChunkSentinel(sentinel))
         } else {
-            Ok(DirectXChunkData {
-                data_file_offset: u64::from_le_bytes(data.data_file_offset),
-                compressed_size: NonZeroU32::new(u32::from_le_bytes(data.compressed_size)),
-                decompressed_size: u32::from_le_bytes(data.decompressed_size),
-                mip_first: u16::from_le_bytes(data.mip_first),
-                mip_last: u16::from_le_bytes(data.mip_last),
-            })
+            Ok(DirectXChunkData::from_raw_unchecked(data))
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Zeroable, Pod)]
+/// A computed file path hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Zeroable, Pod)]
 #[repr(C)]
 pub struct Hash {
     file: [u8; 4],
@@ -336,7 +363,13 @@ pub struct Hash {
 }
 
 impl Hash {
-    // pub unsafe fn from_filename_bytes(bytes: &[u8]) -> Hash {}
+    pub fn new(file: u32, extension: u32, directory: u32) -> Hash {
+        Hash {
+            file: file.to_le_bytes(),
+            extension: extension.to_le_bytes(),
+            directory: directory.to_le_bytes(),
+        }
+    }
 
     pub fn file(&self) -> u32 {
         u32::from_le_bytes(self.file)
@@ -1,24 +1,34 @@
 use std::{
+    borrow::Cow,
     cell::RefCell,
-    convert::{TryFrom, TryInto},
-    io::{Read, Seek, SeekFrom},
+    collections::HashMap,
+    convert::TryFrom,
+    io::{self, Read, Seek, SeekFrom, Write},
     mem,
     num::NonZeroU32,
     ops::DerefMut,
+    path::Path,
     slice,
 };
 
+use bsa_core::verify::Report;
 use smallvec::SmallVec;
 
 use crate::{
     chunk_data::ChunkData,
     common::{read_pod, read_smallvec, read_vec, read_wstring},
+    extract::{self, ExtractOptions},
+    hash::hash_file_path,
+    layout::{ChunkLayout, EntryLayout},
     raw::{
-        DirectXChunkData, DirectXChunkHeader, Format, GeneralChunkData, GeneralChunkHeader, Header,
-        RawDirectXChunkData, RawDirectXChunkHeader, RawGeneralChunkData, RawGeneralChunkHeader,
-        RawHeader,
+        DirectXChunkData, DirectXChunkHeader, Format, GeneralChunkData, GeneralChunkHeader, Hash,
+        Header, RawDirectXChunkData, RawDirectXChunkHeader, RawGeneralChunkData,
+        RawGeneralChunkHeader, RawHeader, Version, CHUNK_DATA_SENTINEL,
     },
-    Result,
+    texture::{self, Texture},
+    verify,
+    write::{RawChunk, RawEntry},
+    Error, ReadError, Result,
 };
 
 /// The Fallout 4 BA2 archive.
@@ -52,7 +62,7 @@ pub struct Ba2<R>
 where
     R: Read + Seek,
 {
-    inner: Ba2Inner<R>,
+    pub(crate) inner: Ba2Inner<R>,
 }
 
 impl<R> Ba2<R>
@@ -61,10 +71,34 @@ where
 {
     pub fn new(r: R) -> Result<Ba2<R>> {
         Ok(Ba2 {
-            inner: Ba2Inner::new(r)?,
+            inner: Ba2Inner::new(r, false)?,
         })
     }
 
+    /// Open an archive even if some chunk records have an invalid sentinel.
+    ///
+    /// [Ba2::new] refuses such archives. This is meant for [verifying](Ba2::verify) a
+    /// damaged archive, which reports every invalid sentinel as an issue.
+    pub fn new_lenient(r: R) -> Result<Ba2<R>> {
+        Ok(Ba2 {
+            inner: Ba2Inner::new(r, true)?,
+        })
+    }
+
+    pub fn format(&self) -> Format {
+        self.inner.format
+    }
+
+    pub fn version(&self) -> Version {
+        self.inner.version
+    }
+
+    /// Whether the archive has a string table holding the names of its entries.
+    /// Names recovered with [Ba2::recover_names] are not stored in the archive.
+    pub fn has_string_table(&self) -> bool {
+        self.inner.has_string_table
+    }
+
     pub fn entries(&self) -> Entries {
         let inner = match &self.inner.chunks {
             Ba2Chunks::General(chunks) => EntriesInner::General(chunks.iter()),
@@ -72,15 +106,79 @@ where
         };
 
         Entries {
-            strings: self.inner.strings.as_ref().map(|strings| strings.iter()),
+            names: self.inner.names.iter(),
             inner,
             ba2: &self.inner,
         }
     }
+
+    /// Get an entry by the hash of its path.
+    ///
+    /// Hashes are always present, so this works even for entries without a name.
+    pub fn by_hash(&self, hash: Hash) -> Option<Entry<'_>> {
+        let index = *self.inner.index.get(&hash)?;
+        let ba2: &Ba2Inner<dyn ReadSeek> = &self.inner;
+        Some(ba2.entry(index))
+    }
+
+    /// Get an entry by name.
+    ///
+    /// Returns [None] if no entry with that name is present, or the name is not a
+    /// valid archive path.
+    pub fn by_name<S: AsRef<str>>(&self, name: S) -> Option<Entry<'_>> {
+        self.by_hash(hash_file_path(name.as_ref())?)
+    }
+
+    /// Recover the names of unnamed entries from a list of candidate paths.
+    ///
+    /// Every candidate is hashed, and if an entry without a name has that hash, the
+    /// candidate becomes its name. Entries that already have a name are left alone.
+    /// Returns the number of names recovered.
+    pub fn recover_names<I, S>(&mut self, candidates: I) -> usize
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut recovered = 0;
+        for candidate in candidates {
+            let candidate = candidate.as_ref();
+            let index = hash_file_path(candidate).and_then(|hash| self.inner.index.get(&hash));
+            if let Some(&index) = index {
+                let name = &mut self.inner.names[index];
+                if name.is_none() {
+                    *name = Some(candidate.to_owned());
+                    recovered += 1;
+                }
+            }
+        }
+        recovered
+    }
+
+    /// Check the integrity of every entry in the archive.
+    ///
+    /// Every chunk is decompressed and its length compared against the stored
+    /// decompressed length, names are checked against their stored hashes, chunk
+    /// offsets are checked to fall within the archive. Invalid chunk record sentinels
+    /// are only reported for archives opened with [Ba2::new_lenient], as [Ba2::new]
+    /// refuses to open them.
+    ///
+    /// Problems are collected into a [Report] rather than returned as errors. An error
+    /// is only returned if the archive itself cannot be read.
+    pub fn verify(&self) -> Result<Report> {
+        verify::verify(self.entries(), &self.inner)
+    }
+
+    /// Extract all files in the archive to a directory.
+    ///
+    /// Entries without a name are written to
+    /// `<directory hash>/<file hash>.<extension>`, with both hashes in hexadecimal.
+    pub fn extract<P: AsRef<Path>>(&self, dir: P, options: &ExtractOptions) -> Result<()> {
+        extract::extract(self.entries(), dir.as_ref(), options)
+    }
 }
 
 pub struct Entries<'a> {
-    strings: Option<slice::Iter<'a, String>>,
+    names: slice::Iter<'a, Option<String>>,
     inner: EntriesInner<'a>,
     ba2: &'a Ba2Inner<dyn 'a + ReadSeek>,
 }
@@ -89,11 +187,7 @@ impl<'a> Iterator for Entries<'a> {
     type Item = Entry<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let string = self
-            .strings
-            .as_mut()
-            .and_then(|strings| strings.next())
-            .map(|s| s.as_ref());
+        let string = self.names.next().and_then(|name| name.as_deref());
 
         match &mut self.inner {
             EntriesInner::General(entries) => {
@@ -134,6 +228,23 @@ impl<'a> Entry<'a> {
         }
     }
 
+    /// Get the hash of this entry's path.
+    pub fn hash(&self) -> Hash {
+        match self {
+            Entry::General(e) => e.hash(),
+            Entry::DirectX(e) => e.hash(),
+        }
+    }
+
+    /// The name of this entry, or its hash in hexadecimal if the archive does not
+    /// include names.
+    pub fn display_name(&self) -> Cow<'a, str> {
+        match self.name() {
+            Some(name) => Cow::Borrowed(name),
+            None => Cow::Owned(extract::hash_name(self.hash())),
+        }
+    }
+
     pub fn chunks(&self) -> Chunks<'a> {
         match self {
             Entry::General(e) => Chunks {
@@ -144,6 +255,50 @@ impl<'a> Entry<'a> {
             },
         }
     }
+
+    /// Extract this entry to a provided writer.
+    ///
+    /// Textures are written as complete DDS files. See [DirectXEntry::extract_to].
+    pub fn extract_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        match self {
+            Entry::General(e) => e.extract_to(out),
+            Entry::DirectX(e) => e.extract_to(out),
+        }
+    }
+
+    /// Read every chunk of this entry as stored, without decompressing them, to be
+    /// added to another archive with [Ba2Writer::add_raw](crate::Ba2Writer::add_raw).
+    pub fn raw(&self) -> Result<RawEntry> {
+        let texture = match self {
+            Entry::General(_) => None,
+            Entry::DirectX(e) => Some(e.texture()),
+        };
+        let chunks = self
+            .chunks()
+            .map(|chunk| chunk.raw())
+            .collect::<Result<_>>()?;
+        Ok(RawEntry { texture, chunks })
+    }
+
+    /// How this entry is stored: its texture and the split of its chunks, to be
+    /// added to another archive with
+    /// [Ba2Writer::add_with_layout](crate::Ba2Writer::add_with_layout).
+    pub fn layout(&self) -> EntryLayout {
+        let texture = match self {
+            Entry::General(_) => None,
+            Entry::DirectX(e) => Some(e.texture()),
+        };
+        let chunks = self
+            .chunks()
+            .map(|chunk| ChunkLayout {
+                compressed: chunk.compressed_size().is_some(),
+                decompressed_size: chunk.decompressed_size(),
+                mip_first: chunk.mip_first(),
+                mip_last: chunk.mip_last(),
+            })
+            .collect();
+        EntryLayout { texture, chunks }
+    }
 }
 
 pub struct Chunks<'a> {
@@ -173,66 +328,167 @@ impl Chunk<'_> {
             ChunkInner::DirectX(chunk) => chunk.open(),
         }
     }
+
+    /// Read the data of this chunk as stored, without decompressing it.
+    pub fn raw(&self) -> Result<RawChunk> {
+        match self.inner {
+            ChunkInner::General(chunk) => chunk.raw(),
+            ChunkInner::DirectX(chunk) => chunk.raw(),
+        }
+    }
+
+    /// The offset of this chunk's data in the archive.
+    pub fn offset(&self) -> u64 {
+        match self.inner {
+            ChunkInner::General(chunk) => chunk.offset(),
+            ChunkInner::DirectX(chunk) => chunk.offset(),
+        }
+    }
+
+    /// The size of this chunk's data as stored, or [None] if it is not compressed.
+    pub fn compressed_size(&self) -> Option<u32> {
+        match self.inner {
+            ChunkInner::General(chunk) => chunk.compressed_size(),
+            ChunkInner::DirectX(chunk) => chunk.compressed_size(),
+        }
+    }
+
+    /// The size of this chunk's data after decompression.
+    pub fn decompressed_size(&self) -> u32 {
+        match self.inner {
+            ChunkInner::General(chunk) => chunk.decompressed_size(),
+            ChunkInner::DirectX(chunk) => chunk.decompressed_size(),
+        }
+    }
+
+    /// The first mipmap level contained in this chunk, or 0 if it is not a texture.
+    pub fn mip_first(&self) -> u16 {
+        match self.inner {
+            ChunkInner::General(_) => 0,
+            ChunkInner::DirectX(chunk) => chunk.mip_first(),
+        }
+    }
+
+    /// The last mipmap level contained in this chunk, or 0 if it is not a texture.
+    pub fn mip_last(&self) -> u16 {
+        match self.inner {
+            ChunkInner::General(_) => 0,
+            ChunkInner::DirectX(chunk) => chunk.mip_last(),
+        }
+    }
 }
 
-struct Ba2Inner<R>
+pub(crate) struct Ba2Inner<R>
 where
     R: ?Sized + Read + Seek,
 {
     chunks: Ba2Chunks,
-    strings: Option<Vec<String>>,
-    reader: RefCell<R>,
+    pub(crate) names: Vec<Option<String>>,
+    pub(crate) index: HashMap<Hash, usize>,
+    pub(crate) format: Format,
+    pub(crate) version: Version,
+    /// Whether the archive has a string table.
+    pub(crate) has_string_table: bool,
+    /// The index of the entry and the sentinel of every chunk record with an invalid
+    /// sentinel. Only ever non-empty for leniently opened archives.
+    pub(crate) bad_sentinels: Vec<(usize, u32)>,
+    pub(crate) reader: RefCell<R>,
 }
 
 impl<R> Ba2Inner<R>
 where
     R: Read + Seek,
 {
-    pub fn new(mut r: R) -> Result<Ba2Inner<R>> {
+    pub fn new(mut r: R, lenient: bool) -> Result<Ba2Inner<R>> {
         let mut header = [0; mem::size_of::<RawHeader>()];
         r.read_exact(&mut header)?;
         let header: RawHeader = bytemuck::cast(header);
         let header = Header::try_from(header)?;
 
+        let mut bad_sentinels = Vec::new();
         let chunks = match header.format {
             Format::General => {
-                let chunks = read_general_chunks(&mut r, header.file_count as usize)?;
+                let n = header.file_count as usize;
+                let chunks = read_general_chunks(&mut r, n, &mut bad_sentinels)?;
                 Ba2Chunks::General(chunks)
             }
             Format::DirectX => {
-                let chunks = read_directx_chunks(&mut r, header.file_count as usize)?;
+                let n = header.file_count as usize;
+                let chunks = read_directx_chunks(&mut r, n, &mut bad_sentinels)?;
                 Ba2Chunks::DirectX(chunks)
             }
         };
+        if let (false, Some(&(_, sentinel))) = (lenient, bad_sentinels.first()) {
+            return Err(ReadError::InvalidChunkSentinel(sentinel).into());
+        }
 
-        let strings = if let Some(offset) = header.string_table_offset {
+        let names = if let Some(offset) = header.string_table_offset {
             let off = offset.get();
-            Some(read_string_table(&mut r, off, header.file_count)?)
+            read_string_table(&mut r, off, header.file_count)?
         } else {
-            None
+            vec![None; header.file_count as usize]
         };
 
-        let reader = RefCell::new(r);
+        let index = match &chunks {
+            Ba2Chunks::General(chunks) => index_by_hash(chunks.iter().map(|c| c.header.id)),
+            Ba2Chunks::DirectX(chunks) => index_by_hash(chunks.iter().map(|c| c.header.id)),
+        };
 
-        for s in strings.as_ref().unwrap() {
-            println!("{}", s)
-        }
+        let reader = RefCell::new(r);
 
         Ok(Ba2Inner {
             chunks,
+            names,
+            index,
+            format: header.format,
+            version: header.version,
+            has_string_table: header.string_table_offset.is_some(),
+            bad_sentinels,
             reader,
-            strings,
         })
     }
 }
 
 impl Ba2Inner<dyn '_ + ReadSeek> {
+    pub(crate) fn entry(&self, index: usize) -> Entry<'_> {
+        let name = self.names[index].as_deref();
+        match &self.chunks {
+            Ba2Chunks::General(chunks) => Entry::General(GeneralEntry {
+                name,
+                inner: &chunks[index],
+                ba2: self,
+            }),
+            Ba2Chunks::DirectX(chunks) => Entry::DirectX(DirectXEntry {
+                name,
+                inner: &chunks[index],
+                ba2: self,
+            }),
+        }
+    }
+
     pub fn chunk_data(
         &self,
         offset: u64,
         compressed_len: Option<NonZeroU32>,
         uncompressed_len: u32,
     ) -> Result<ChunkData> {
+        let buf = self.raw_data(offset, compressed_len, uncompressed_len)?;
+
+        let data = if compressed_len.is_some() {
+            ChunkData::compressed(buf)
+        } else {
+            ChunkData::uncompressed(buf)
+        };
+        Ok(data)
+    }
+
+    /// Read the data of a chunk as stored, without decompressing it.
+    fn raw_data(
+        &self,
+        offset: u64,
+        compressed_len: Option<NonZeroU32>,
+        uncompressed_len: u32,
+    ) -> Result<Vec<u8>> {
         let mut r = self.reader.borrow_mut();
         r.seek(SeekFrom::Start(offset))?;
 
@@ -243,22 +499,21 @@ impl Ba2Inner<dyn '_ + ReadSeek> {
         };
         let raw_len = raw_len as usize;
 
-        let buf = read_vec(r.deref_mut(), raw_len)?;
-
-        let data = if compressed_len.is_some() {
-            ChunkData::compressed(buf)
-        } else {
-            ChunkData::uncompressed(buf)
-        };
-        Ok(data)
+        Ok(read_vec(r.deref_mut(), raw_len)?)
     }
 }
 
-trait ReadSeek: Read + Seek {}
+pub(crate) trait ReadSeek: Read + Seek {}
 
 impl<R> ReadSeek for R where R: Read + Seek {}
 
-fn read_general_chunk<R>(r: &mut R) -> Result<GeneralChunkInner>
+/// Reads the entry at `index` and its chunk records. Chunk records that do not hold
+/// [CHUNK_DATA_SENTINEL] are recorded in `bad_sentinels`.
+fn read_general_chunk<R>(
+    r: &mut R,
+    index: usize,
+    bad_sentinels: &mut Vec<(usize, u32)>,
+) -> Result<GeneralChunkInner>
 where
     R: ?Sized + Read + Seek,
 {
@@ -270,24 +525,37 @@ where
 
     let mut data = SmallVec::with_capacity(header.chunk_count as usize);
     for chunk in raw_data {
-        data.push(chunk.try_into()?);
+        if chunk.sentinel() != CHUNK_DATA_SENTINEL {
+            bad_sentinels.push((index, chunk.sentinel()));
+        }
+        data.push(GeneralChunkData::from_raw_unchecked(chunk));
     }
 
     Ok(GeneralChunkInner { header, data })
 }
 
-fn read_general_chunks<R>(r: &mut R, n: usize) -> Result<Vec<GeneralChunkInner>>
+fn read_general_chunks<R>(
+    r: &mut R,
+    n: usize,
+    bad_sentinels: &mut Vec<(usize, u32)>,
+) -> Result<Vec<GeneralChunkInner>>
 where
     R: ?Sized + Read + Seek,
 {
     let mut chunks = Vec::new();
-    for _ in 0..n {
-        chunks.push(read_general_chunk(r)?);
+    for index in 0..n {
+        chunks.push(read_general_chunk(r, index, bad_sentinels)?);
     }
     Ok(chunks)
 }
 
-fn read_directx_chunk<R>(r: &mut R) -> Result<DirectXChunkInner>
+/// Reads the entry at `index` and its chunk records. Chunk records that do not hold
+/// [CHUNK_DATA_SENTINEL] are recorded in `bad_sentinels`.
+fn read_directx_chunk<R>(
+    r: &mut R,
+    index: usize,
+    bad_sentinels: &mut Vec<(usize, u32)>,
+) -> Result<DirectXChunkInner>
 where
     R: ?Sized + Read + Seek,
 {
@@ -299,36 +567,52 @@ where
 
     let mut data = SmallVec::with_capacity(header.chunk_count as usize);
     for chunk in raw_data {
-        data.push(chunk.try_into()?);
+        if chunk.sentinel() != CHUNK_DATA_SENTINEL {
+            bad_sentinels.push((index, chunk.sentinel()));
+        }
+        data.push(DirectXChunkData::from_raw_unchecked(chunk));
     }
 
     Ok(DirectXChunkInner { header, data })
 }
 
-fn read_directx_chunks<R>(r: &mut R, n: usize) -> Result<Vec<DirectXChunkInner>>
+fn read_directx_chunks<R>(
+    r: &mut R,
+    n: usize,
+    bad_sentinels: &mut Vec<(usize, u32)>,
+) -> Result<Vec<DirectXChunkInner>>
 where
     R: ?Sized + Read + Seek,
 {
     let mut chunks = Vec::new();
-    for _ in 0..n {
-        chunks.push(read_directx_chunk(r)?);
+    for index in 0..n {
+        chunks.push(read_directx_chunk(r, index, bad_sentinels)?);
     }
     Ok(chunks)
 }
 
-fn read_string_table<R>(r: &mut R, off: u64, file_count: u32) -> Result<Vec<String>>
+/// Reads the names of all entries. Some tools strip names by truncating the string
+/// table, so any names past the end of the file are treated as missing.
+fn read_string_table<R>(r: &mut R, off: u64, file_count: u32) -> Result<Vec<Option<String>>>
 where
     R: ?Sized + Read + Seek,
 {
-    let mut strings = Vec::with_capacity(file_count as usize);
+    let mut strings = vec![None; file_count as usize];
     r.seek(SeekFrom::Start(off))?;
-    for _ in 0..file_count {
-        let string = read_wstring(r)?;
-        strings.push(string);
+    for string in &mut strings {
+        match read_wstring(r) {
+            Ok(s) => *string = Some(s),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
     }
     Ok(strings)
 }
 
+fn index_by_hash<I: Iterator<Item = Hash>>(hashes: I) -> HashMap<Hash, usize> {
+    hashes.enumerate().map(|(i, hash)| (hash, i)).collect()
+}
+
 enum Ba2Chunks {
     General(Vec<GeneralChunkInner>),
     DirectX(Vec<DirectXChunkInner>),
@@ -358,12 +642,25 @@ impl<'a> GeneralEntry<'a> {
         self.name
     }
 
+    /// Get the hash of this entry's path.
+    pub fn hash(&self) -> Hash {
+        self.inner.header.id
+    }
+
     pub fn chunks(&self) -> GeneralChunks<'a> {
         GeneralChunks {
             entry: *self,
             chunks: self.inner.data.iter(),
         }
     }
+
+    /// Extract this entry to a provided writer.
+    pub fn extract_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        for chunk in self.chunks() {
+            io::copy(&mut chunk.open()?, out)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct GeneralChunks<'a> {
@@ -398,6 +695,38 @@ impl GeneralChunk<'_> {
         let ba2: &Ba2Inner<dyn ReadSeek> = self.ba2;
         ba2.chunk_data(offset, compressed_len, uncompressed_len)
     }
+
+    /// Read the data of this chunk as stored, without decompressing it.
+    pub fn raw(&self) -> Result<RawChunk> {
+        let chunk = self.inner;
+        let ba2: &Ba2Inner<dyn ReadSeek> = self.ba2;
+        Ok(RawChunk {
+            data: ba2.raw_data(
+                chunk.data_file_offset,
+                chunk.compressed_size,
+                chunk.decompressed_size,
+            )?,
+            compressed: chunk.compressed_size.is_some(),
+            decompressed_size: chunk.decompressed_size,
+            mip_first: 0,
+            mip_last: 0,
+        })
+    }
+
+    /// The offset of this chunk's data in the archive.
+    pub fn offset(&self) -> u64 {
+        self.inner.data_file_offset
+    }
+
+    /// The size of this chunk's data as stored, or [None] if it is not compressed.
+    pub fn compressed_size(&self) -> Option<u32> {
+        self.inner.compressed_size.map(NonZeroU32::get)
+    }
+
+    /// The size of this chunk's data after decompression.
+    pub fn decompressed_size(&self) -> u32 {
+        self.inner.decompressed_size
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -412,12 +741,45 @@ impl<'a> DirectXEntry<'a> {
         self.name
     }
 
+    /// Get the hash of this entry's path.
+    pub fn hash(&self) -> Hash {
+        self.inner.header.id
+    }
+
     pub fn chunks(&self) -> DirectXChunks<'a> {
         DirectXChunks {
             entry: *self,
             chunks: self.inner.data.iter(),
         }
     }
+
+    /// Build the DDS header for this texture.
+    ///
+    /// DX10 archives do not store the header of their textures, so concatenating the
+    /// chunks of an entry does not produce a usable file. Prepending this header to
+    /// the decompressed chunks does.
+    pub fn dds_header(&self) -> Result<Vec<u8>> {
+        texture::dds_header(&self.inner.header)
+    }
+
+    /// The description of the texture, which takes the place of its DDS header.
+    pub fn texture(&self) -> Texture {
+        Texture::from(&self.inner.header)
+    }
+
+    /// The tile mode of the texture, used by consoles.
+    pub fn tile_mode(&self) -> u8 {
+        self.inner.header.tile_mode
+    }
+
+    /// Extract this texture as a complete DDS file to a provided writer.
+    pub fn extract_to<W: Write>(&self, out: &mut W) -> Result<()> {
+        out.write_all(&self.dds_header()?)?;
+        for chunk in self.chunks() {
+            io::copy(&mut chunk.open()?, out)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct DirectXChunks<'a> {
@@ -452,6 +814,47 @@ impl DirectXChunk<'_> {
         self.ba2
             .chunk_data(offset, compressed_len, uncompressed_len)
     }
+
+    /// Read the data of this chunk as stored, without decompressing it.
+    pub fn raw(&self) -> Result<RawChunk> {
+        let chunk = self.inner;
+        Ok(RawChunk {
+            data: self.ba2.raw_data(
+                chunk.data_file_offset,
+                chunk.compressed_size,
+                chunk.decompressed_size,
+            )?,
+            compressed: chunk.compressed_size.is_some(),
+            decompressed_size: chunk.decompressed_size,
+            mip_first: chunk.mip_first,
+            mip_last: chunk.mip_last,
+        })
+    }
+
+    /// The offset of this chunk's data in the archive.
+    pub fn offset(&self) -> u64 {
+        self.inner.data_file_offset
+    }
+
+    /// The size of this chunk's data as stored, or [None] if it is not compressed.
+    pub fn compressed_size(&self) -> Option<u32> {
+        self.inner.compressed_size.map(NonZeroU32::get)
+    }
+
+    /// The size of this chunk's data after decompression.
+    pub fn decompressed_size(&self) -> u32 {
+        self.inner.decompressed_size
+    }
+
+    /// The first mipmap level contained in this chunk.
+    pub fn mip_first(&self) -> u16 {
+        self.inner.mip_first
+    }
+
+    /// The last mipmap level contained in this chunk.
+    pub fn mip_last(&self) -> u16 {
+        self.inner.mip_last
+    }
 }
 
 enum ChunkInner<'a> {
@@ -463,3 +866,128 @@ enum ChunksInner<'a> {
     General(GeneralChunks<'a>),
     DirectX(DirectXChunks<'a>),
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bsa_core::verify::IssueKind;
+    use bytemuck::{bytes_of, Zeroable};
+
+    use crate::{
+        hash::hash_file_path,
+        raw::{
+            DataFileIndex, GeneralChunkData, GeneralChunkHeader, Header, RawGeneralChunkData,
+            RawGeneralChunkHeader, RawHeader, Version,
+        },
+    };
+
+    use super::{Ba2, Format};
+
+    /// Builds a GNRL archive with a single uncompressed entry and no string table.
+    fn nameless_archive(path: &str, data: &[u8]) -> Vec<u8> {
+        let header = Header {
+            version: Version::V1,
+            format: Format::General,
+            file_count: 1,
+            string_table_offset: None,
+        };
+        let chunk_header = GeneralChunkHeader {
+            id: hash_file_path(path).unwrap(),
+            data_file_index: DataFileIndex::zeroed(),
+            chunk_count: 1,
+        };
+        let data_offset = 24 + 16 + 20;
+        let chunk = GeneralChunkData {
+            data_file_offset: data_offset,
+            compressed_size: None,
+            decompressed_size: data.len() as u32,
+        };
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(bytes_of(&RawHeader::from(header)));
+        buf.extend_from_slice(bytes_of(&RawGeneralChunkHeader::from(chunk_header)));
+        buf.extend_from_slice(bytes_of(&RawGeneralChunkData::from(chunk)));
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    #[test]
+    fn test_nameless_archive() {
+        let path = "meshes\\clutter\\bucket.nif";
+        let bytes = nameless_archive(path, b"hello");
+        let mut ba2 = Ba2::new(Cursor::new(bytes)).unwrap();
+
+        let entry = ba2.entries().next().unwrap();
+        assert_eq!(entry.name(), None);
+        assert_eq!(entry.hash(), hash_file_path(path).unwrap());
+
+        let mut data = Vec::new();
+        ba2.by_name("Meshes/Clutter/Bucket.nif")
+            .unwrap()
+            .extract_to(&mut data)
+            .unwrap();
+        assert_eq!(data, b"hello");
+
+        let candidates = ["meshes\\clutter\\pail.nif", path];
+        assert_eq!(ba2.recover_names(candidates), 1);
+        assert_eq!(ba2.entries().next().unwrap().name(), Some(path));
+    }
+
+    #[test]
+    fn test_verify() {
+        let path = "meshes\\clutter\\bucket.nif";
+        let mut bytes = nameless_archive(path, b"hello");
+        let ba2 = Ba2::new(Cursor::new(bytes.clone())).unwrap();
+        let report = ba2.verify().unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.entries, 1);
+
+        bytes.truncate(bytes.len() - 1);
+        let ba2 = Ba2::new(Cursor::new(bytes)).unwrap();
+        let kinds: Vec<_> = ba2
+            .verify()
+            .unwrap()
+            .issues
+            .into_iter()
+            .map(|i| i.kind)
+            .collect();
+        let out_of_bounds = IssueKind::OutOfBounds {
+            offset: 60,
+            len: 5,
+            archive_len: 64,
+        };
+        assert_eq!(kinds, [out_of_bounds]);
+    }
+
+    #[test]
+    fn test_verify_offset_overflow() {
+        let mut bytes = nameless_archive("meshes\\clutter\\bucket.nif", b"hello");
+        let offset = u64::MAX - 2;
+        bytes[40..48].copy_from_slice(&offset.to_le_bytes());
+        let ba2 = Ba2::new(Cursor::new(bytes)).unwrap();
+        let report = ba2.verify().unwrap();
+        let out_of_bounds = IssueKind::OutOfBounds {
+            offset,
+            len: 5,
+            archive_len: 65,
+        };
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].kind, out_of_bounds);
+    }
+
+    #[test]
+    fn test_verify_sentinel() {
+        let mut bytes = nameless_archive("meshes\\clutter\\bucket.nif", b"hello");
+        bytes[56..60].copy_from_slice(&0xDEADBEEFu32.to_le_bytes());
+        assert!(Ba2::new(Cursor::new(bytes.clone())).is_err());
+
+        let ba2 = Ba2::new_lenient(Cursor::new(bytes)).unwrap();
+        let report = ba2.verify().unwrap();
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(
+            report.issues[0].kind,
+            IssueKind::InvalidChunkSentinel(0xDEADBEEF)
+        );
+    }
+}
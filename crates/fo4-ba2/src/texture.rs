@@ -0,0 +1,369 @@
+//! Reconstruction of DDS headers for DX10 archive entries.
+//!
+//! DX10 archives strip the header from every texture they contain. The information
+//! required to rebuild it (dimensions, mipmap count, format and cubemap flag) is kept
+//! in each entry's [DirectXChunkHeader] instead. When writing, the same information
+//! is parsed from the DDS header of each texture.
+
+use std::convert::{TryFrom, TryInto};
+
+use dds::{
+    AlphaMode, Caps, Caps2, Dimension, DxgiFormat, FourCc, Header, HeaderDx10, HeaderFlags,
+    MiscFlags, PixelFormat, PixelFormatFlags, MAGIC,
+};
+
+use crate::{raw::DirectXChunkHeader, ReadError, Result};
+
+/// Set in [DirectXChunkHeader::flags] when the texture is a cubemap.
+const CUBEMAP_FLAG: u8 = 0x1;
+
+/// The description of a texture stored in a DX10 archive, in place of its DDS
+/// header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Texture {
+    pub width: u16,
+    pub height: u16,
+    pub mip_count: u8,
+    /// The DXGI format.
+    pub format: u8,
+    /// 1 if the texture is a cubemap.
+    pub flags: u8,
+    /// The tile mode used by consoles.
+    pub tile_mode: u8,
+}
+
+impl From<&DirectXChunkHeader> for Texture {
+    fn from(header: &DirectXChunkHeader) -> Texture {
+        Texture {
+            width: header.width,
+            height: header.height,
+            mip_count: header.mip_count,
+            format: header.format,
+            flags: header.flags,
+            tile_mode: header.tile_mode,
+        }
+    }
+}
+
+impl Texture {
+    /// Parse the header of a DDS file. Returns the texture and the length of the
+    /// header, after which the data of every mipmap follows.
+    pub fn from_dds(bytes: &[u8]) -> Option<(Texture, usize)> {
+        if bytes.get(..4)? != MAGIC {
+            return None;
+        }
+        let header = Header::from_bytes(bytes.get(4..128)?.try_into().ok()?)?;
+
+        let (format, header_len) = if header.pixel_format.fourcc == FourCc::DX10 {
+            let dx10 = HeaderDx10::from_bytes(bytes.get(128..148)?.try_into().ok()?)?;
+            (dx10.format, 148)
+        } else {
+            (legacy_format(&header.pixel_format)?, 128)
+        };
+
+        let cubemap = header.caps2.contains(Caps2::CUBEMAP);
+        let texture = Texture {
+            width: header.width.try_into().ok()?,
+            height: header.height.try_into().ok()?,
+            mip_count: header.mipmap_count.max(1).try_into().ok()?,
+            format: u32::from(format).try_into().ok()?,
+            flags: if cubemap { CUBEMAP_FLAG } else { 0 },
+            tile_mode: 8,
+        };
+        Some((texture, header_len))
+    }
+}
+
+/// The format described by a pre-DX10 pixel format.
+///
+/// Tools fill in these headers loosely, so only the fields that matter are compared.
+/// Block compressed formats are recognized by their FourCC alone, including the
+/// aliases of BC4 and BC5 and the premultiplied DXT2 and DXT4, which share the layout
+/// of DXT3 and DXT5. Uncompressed formats are recognized by their bit count and masks,
+/// with the alpha mask only counting if the alpha flag is set.
+fn legacy_format(pixel_format: &PixelFormat) -> Option<DxgiFormat> {
+    if pixel_format.flags.contains(PixelFormatFlags::FOURCC) {
+        return match &pixel_format.fourcc.0 {
+            b"DXT1" => Some(DxgiFormat::Bc1Unorm),
+            b"DXT2" | b"DXT3" => Some(DxgiFormat::Bc2Unorm),
+            b"DXT4" | b"DXT5" => Some(DxgiFormat::Bc3Unorm),
+            b"ATI1" | b"BC4U" => Some(DxgiFormat::Bc4Unorm),
+            b"BC4S" => Some(DxgiFormat::Bc4Snorm),
+            b"ATI2" | b"BC5U" => Some(DxgiFormat::Bc5Unorm),
+            b"BC5S" => Some(DxgiFormat::Bc5Snorm),
+            _ => None,
+        };
+    }
+
+    let alpha = if pixel_format.flags.contains(PixelFormatFlags::ALPHA_PIXELS) {
+        pixel_format.alpha_bit_mask
+    } else {
+        0
+    };
+    let masks = (
+        pixel_format.red_bit_mask,
+        pixel_format.green_bit_mask,
+        pixel_format.blue_bit_mask,
+    );
+    match (pixel_format.rgb_bit_count, masks, alpha) {
+        (32, (0xff0000, 0xff00, 0xff), 0xff000000) => Some(DxgiFormat::B8G8R8A8Unorm),
+        (32, (0xff0000, 0xff00, 0xff), 0) => Some(DxgiFormat::B8G8R8X8Unorm),
+        (32, (0xff, 0xff00, 0xff0000), _) => Some(DxgiFormat::R8G8B8A8Unorm),
+        (8, (0xff, 0, 0), 0) => Some(DxgiFormat::R8Unorm),
+        _ => None,
+    }
+}
+
+/// Builds a complete DDS header, including the magic number and, where the format
+/// cannot be described by a legacy pixel format, the DX10 extension header.
+pub fn dds_header(header: &DirectXChunkHeader) -> Result<Vec<u8>> {
+    let format = DxgiFormat::try_from(header.format as u32)
+        .map_err(|_| ReadError::UnsupportedTextureFormat(header.format))?;
+    let width = header.width as u32;
+    let height = header.height as u32;
+    let mipmap_count = header.mip_count.max(1) as u32;
+    let cubemap = header.flags & CUBEMAP_FLAG != 0;
+
+    let mut flags = HeaderFlags::CAPS
+        | HeaderFlags::HEIGHT
+        | HeaderFlags::WIDTH
+        | HeaderFlags::PIXEL_FORMAT
+        | HeaderFlags::MIPMAP_COUNT;
+    if format.block_size().is_some() {
+        flags |= HeaderFlags::LINEAR_SIZE;
+    } else {
+        flags |= HeaderFlags::PITCH;
+    }
+
+    let mut caps = Caps::TEXTURE;
+    let mut caps2 = Caps2::empty();
+    if 1 < mipmap_count {
+        caps |= Caps::COMPLEX | Caps::MIPMAP;
+    }
+    if cubemap {
+        caps |= Caps::COMPLEX;
+        caps2 |= Caps2::CUBEMAP | Caps2::CUBEMAP_ALL_FACES;
+    }
+
+    let legacy = legacy_pixel_format(format);
+    let pixel_format = legacy.unwrap_or_else(|| fourcc_pixel_format(FourCc::DX10));
+
+    let dds_header = Header {
+        flags,
+        height,
+        width,
+        pitch_or_linear_size: format.pitch_or_linear_size(width, height).unwrap_or(0),
+        depth: 0,
+        mipmap_count,
+        pixel_format,
+        caps,
+        caps2,
+    };
+
+    let mut buf = Vec::with_capacity(MAGIC.len() + 124 + 20);
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&dds_header.to_bytes());
+
+    if legacy.is_none() {
+        let misc_flags = if cubemap {
+            MiscFlags::CUBEMAP
+        } else {
+            MiscFlags::empty()
+        };
+        let dx10_header = HeaderDx10 {
+            format,
+            dimension: Dimension::Texture2D,
+            misc_flags,
+            array_size: 1,
+            alpha_mode: AlphaMode::Unknown,
+        };
+        buf.extend_from_slice(&dx10_header.to_bytes());
+    }
+
+    Ok(buf)
+}
+
+/// Returns the pre-DX10 pixel format for `format`, if one exists. Older tools (and
+/// some of the games' own loaders) only understand these, so they are preferred over
+/// the DX10 extension wherever possible.
+fn legacy_pixel_format(format: DxgiFormat) -> Option<PixelFormat> {
+    let pixel_format = match format {
+        DxgiFormat::Bc1Unorm => fourcc_pixel_format(FourCc::DXT1),
+        DxgiFormat::Bc2Unorm => fourcc_pixel_format(FourCc::DXT3),
+        DxgiFormat::Bc3Unorm => fourcc_pixel_format(FourCc::DXT5),
+        DxgiFormat::Bc4Unorm => fourcc_pixel_format(FourCc::ATI1),
+        DxgiFormat::Bc5Unorm => fourcc_pixel_format(FourCc::ATI2),
+        DxgiFormat::B8G8R8A8Unorm => rgb_pixel_format(0xff0000, 0xff00, 0xff, 0xff000000),
+        DxgiFormat::B8G8R8X8Unorm => rgb_pixel_format(0xff0000, 0xff00, 0xff, 0),
+        DxgiFormat::R8G8B8A8Unorm => rgb_pixel_format(0xff, 0xff00, 0xff0000, 0xff000000),
+        DxgiFormat::R8Unorm => PixelFormat {
+            flags: PixelFormatFlags::LUMINANCE,
+            fourcc: FourCc::NONE,
+            rgb_bit_count: 8,
+            red_bit_mask: 0xff,
+            green_bit_mask: 0,
+            blue_bit_mask: 0,
+            alpha_bit_mask: 0,
+        },
+        _ => return None,
+    };
+
+    Some(pixel_format)
+}
+
+fn fourcc_pixel_format(fourcc: FourCc) -> PixelFormat {
+    PixelFormat {
+        flags: PixelFormatFlags::FOURCC,
+        fourcc,
+        rgb_bit_count: 0,
+        red_bit_mask: 0,
+        green_bit_mask: 0,
+        blue_bit_mask: 0,
+        alpha_bit_mask: 0,
+    }
+}
+
+fn rgb_pixel_format(red: u32, green: u32, blue: u32, alpha: u32) -> PixelFormat {
+    let mut flags = PixelFormatFlags::RGB;
+    if alpha != 0 {
+        flags |= PixelFormatFlags::ALPHA_PIXELS;
+    }
+
+    PixelFormat {
+        flags,
+        fourcc: FourCc::NONE,
+        rgb_bit_count: 32,
+        red_bit_mask: red,
+        green_bit_mask: green,
+        blue_bit_mask: blue,
+        alpha_bit_mask: alpha,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryInto, io::Cursor};
+
+    use bytemuck::Zeroable;
+    use dds::{Caps2, DxgiFormat, FourCc, Header, HeaderDx10, MiscFlags, MAGIC};
+
+    use crate::raw::{DataFileIndex, DirectXChunkHeader, Hash};
+
+    use super::{dds_header, Texture};
+    use crate::{raw::Format, Ba2, Ba2Writer};
+
+    fn chunk_header(format: DxgiFormat, flags: u8) -> DirectXChunkHeader {
+        DirectXChunkHeader {
+            id: Hash::zeroed(),
+            data_file_index: DataFileIndex::zeroed(),
+            chunk_count: 1,
+            height: 512,
+            width: 256,
+            mip_count: 10,
+            format: u32::from(format) as u8,
+            flags,
+            tile_mode: 8,
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> Header {
+        assert_eq!(bytes[..4], MAGIC);
+        Header::from_bytes(bytes[4..128].try_into().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_legacy_header() {
+        let bytes = dds_header(&chunk_header(DxgiFormat::Bc1Unorm, 0)).unwrap();
+        assert_eq!(bytes.len(), 128);
+
+        let header = parse(&bytes);
+        assert_eq!(header.width, 256);
+        assert_eq!(header.height, 512);
+        assert_eq!(header.mipmap_count, 10);
+        assert_eq!(header.pitch_or_linear_size, 64 * 128 * 8);
+        assert_eq!(header.pixel_format.fourcc, FourCc::DXT1);
+    }
+
+    #[test]
+    fn test_dx10_cubemap_header() {
+        let bytes = dds_header(&chunk_header(DxgiFormat::Bc7UnormSrgb, 1)).unwrap();
+        assert_eq!(bytes.len(), 148);
+
+        let header = parse(&bytes);
+        assert_eq!(header.pixel_format.fourcc, FourCc::DX10);
+        assert!(header
+            .caps2
+            .contains(Caps2::CUBEMAP | Caps2::CUBEMAP_ALL_FACES));
+
+        let dx10 = HeaderDx10::from_bytes(bytes[128..].try_into().unwrap()).unwrap();
+        assert_eq!(dx10.format, DxgiFormat::Bc7UnormSrgb);
+        assert_eq!(dx10.misc_flags, MiscFlags::CUBEMAP);
+    }
+
+    /// A legacy DDS file of the format, with 4 bytes of its pixel format set to `value`
+    /// at `offset`. The pixel format starts at byte 76 with its size, followed by its
+    /// flags, FourCC, bit count and the red, green, blue and alpha masks.
+    fn legacy_dds(format: DxgiFormat, offset: usize, value: [u8; 4]) -> Vec<u8> {
+        let mut header = chunk_header(format, 0);
+        header.width = 4;
+        header.height = 4;
+        header.mip_count = 1;
+        let mut bytes = dds_header(&header).unwrap();
+        assert_eq!(bytes.len(), 128);
+        bytes[offset..offset + 4].copy_from_slice(&value);
+        bytes
+    }
+
+    #[test]
+    fn test_non_canonical_legacy_headers() {
+        const FLAGS: usize = 80;
+        const FOURCC: usize = 84;
+        const ALPHA_MASK: usize = 104;
+        let fourcc_alpha_pixels = (0x4 | 0x1u32).to_le_bytes();
+        let cases = [
+            (DxgiFormat::Bc4Unorm, FOURCC, *b"BC4U", DxgiFormat::Bc4Unorm),
+            (DxgiFormat::Bc5Unorm, FOURCC, *b"BC5U", DxgiFormat::Bc5Unorm),
+            (DxgiFormat::Bc5Unorm, FOURCC, *b"BC5S", DxgiFormat::Bc5Snorm),
+            (
+                DxgiFormat::Bc1Unorm,
+                FLAGS,
+                fourcc_alpha_pixels,
+                DxgiFormat::Bc1Unorm,
+            ),
+            (DxgiFormat::Bc2Unorm, FOURCC, *b"DXT2", DxgiFormat::Bc2Unorm),
+            (DxgiFormat::Bc3Unorm, FOURCC, *b"DXT4", DxgiFormat::Bc3Unorm),
+            (
+                DxgiFormat::B8G8R8X8Unorm,
+                ALPHA_MASK,
+                0xff000000u32.to_le_bytes(),
+                DxgiFormat::B8G8R8X8Unorm,
+            ),
+        ];
+        for (format, offset, value, expected) in cases {
+            let dds = legacy_dds(format, offset, value);
+            let (texture, len) = Texture::from_dds(&dds).unwrap();
+            assert_eq!(len, 128);
+            assert_eq!(texture.format, u32::from(expected) as u8, "{:?}", expected);
+        }
+        let unknown = legacy_dds(DxgiFormat::Bc1Unorm, FOURCC, *b"ABCD");
+        assert!(Texture::from_dds(&unknown).is_none());
+    }
+
+    #[test]
+    fn test_pack_non_canonical_header() {
+        let mut dds = legacy_dds(DxgiFormat::Bc5Unorm, 84, *b"BC5U");
+        dds.extend_from_slice(&[0x11; 16]);
+        let mut writer = Ba2Writer::new(Format::DirectX);
+        writer.add("textures/a.dds", dds).unwrap();
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write_to(&mut bytes).unwrap();
+
+        let ba2 = Ba2::new(Cursor::new(bytes.into_inner())).unwrap();
+        let entry = ba2.by_name("textures/a.dds").unwrap();
+        let mut data = Vec::new();
+        entry.extract_to(&mut data).unwrap();
+        // The header is rebuilt with the canonical FourCC.
+        assert_eq!(&data[84..88], b"ATI2");
+        assert_eq!(data[128..], [0x11; 16]);
+    }
+}
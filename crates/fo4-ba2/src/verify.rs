@@ -0,0 +1,78 @@
+use std::io::{self, SeekFrom};
+
+use bsa_core::verify::{IssueKind, Report};
+
+use crate::{
+    extract::hash_name,
+    hash::hash_file_path,
+    read::{Ba2Inner, ReadSeek},
+    Entries, Result,
+};
+
+/// Checks every entry in the archive. See [Ba2::verify](crate::Ba2::verify).
+pub fn verify(entries: Entries, ba2: &Ba2Inner<dyn '_ + ReadSeek>) -> Result<Report> {
+    let mut report = Report::new();
+    let archive_len = ba2.reader.borrow_mut().seek(SeekFrom::End(0))?;
+
+    for (index, entry) in entries.enumerate() {
+        report.entries += 1;
+        let name = match entry.name() {
+            Some(name) => {
+                match hash_file_path(name) {
+                    Some(hash) if hash == entry.hash() => {}
+                    Some(_) => report.push(Some(name), IssueKind::HashMismatch),
+                    None => report.push(Some(name), IssueKind::InvalidName),
+                }
+                name.to_owned()
+            }
+            None => hash_name(entry.hash()),
+        };
+
+        for &(_, sentinel) in ba2.bad_sentinels.iter().filter(|(i, _)| *i == index) {
+            report.push(
+                Some(name.as_str()),
+                IssueKind::InvalidChunkSentinel(sentinel),
+            );
+        }
+
+        for chunk in entry.chunks() {
+            let offset = chunk.offset();
+            let len = chunk
+                .compressed_size()
+                .unwrap_or_else(|| chunk.decompressed_size());
+            let len = len as u64;
+            if offset.checked_add(len).is_none_or(|end| end > archive_len) {
+                let kind = IssueKind::OutOfBounds {
+                    offset,
+                    len,
+                    archive_len,
+                };
+                report.push(Some(name.as_str()), kind);
+                continue;
+            }
+
+            let mut data = match chunk.data() {
+                Ok(data) => data,
+                Err(e) => {
+                    report.push(Some(name.as_str()), IssueKind::Read(e.to_string()));
+                    continue;
+                }
+            };
+
+            let expected = chunk.decompressed_size() as u64;
+            match io::copy(&mut data, &mut io::sink()) {
+                Ok(actual) if actual != expected => {
+                    let kind = IssueKind::LengthMismatch { expected, actual };
+                    report.push(Some(name.as_str()), kind);
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let kind = IssueKind::Decompression(e.to_string());
+                    report.push(Some(name.as_str()), kind);
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
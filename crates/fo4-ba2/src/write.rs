@@ -0,0 +1,839 @@
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
+    num::{NonZeroU32, NonZeroU64},
+    rc::Rc,
+    sync::Arc,
+};
+
+use bsa_core::{pipeline::Pipeline, policy::CompressionPolicy, write::WriteSummary, WriteError};
+use bytemuck::{bytes_of, Zeroable};
+use flate2::write::ZlibEncoder;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    hash::hash_file_path,
+    layout::{ChunkLayout, EntryLayout},
+    raw::{
+        DataFileIndex, DirectXChunkData, DirectXChunkHeader, Format, GeneralChunkData,
+        GeneralChunkHeader, Hash, Header, RawDirectXChunkData, RawDirectXChunkHeader,
+        RawGeneralChunkData, RawGeneralChunkHeader, RawHeader, Version,
+    },
+    read::{Ba2Inner, ReadSeek},
+    texture::Texture,
+    Ba2, Entry, Result,
+};
+
+/// The data of a file to be written to an archive.
+pub trait FileData {
+    /// Write the contents of the file to `w`, returning the number of bytes written.
+    /// Textures are written as complete DDS files.
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64>;
+}
+
+impl FileData for Vec<u8> {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        w.write_all(self)?;
+        Ok(self.len() as u64)
+    }
+}
+
+impl FileData for fs::File {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        self.seek(SeekFrom::Start(0))?;
+        Ok(io::copy(self, w)?)
+    }
+}
+
+/// The contents of a file, read from any reader once the archive is written.
+pub struct ReaderData<R>(pub R);
+
+impl<R: Read> FileData for ReaderData<R> {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        Ok(io::copy(&mut self.0, w)?)
+    }
+}
+
+/// A chunk of an entry as stored in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawChunk {
+    /// The data of the chunk, compressed with zlib if `compressed` is set.
+    pub data: Vec<u8>,
+    pub compressed: bool,
+    pub decompressed_size: u32,
+    /// The mipmaps contained in the chunk, for textures.
+    pub mip_first: u16,
+    pub mip_last: u16,
+}
+
+/// An entry as stored in an archive: its chunks, and the description of the texture
+/// for entries of DX10 archives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawEntry {
+    pub texture: Option<Texture>,
+    pub chunks: Vec<RawChunk>,
+}
+
+/// An entry of an archive that has been read, copied without decompression.
+pub(crate) struct StoredEntry<R: Read + Seek> {
+    pub archive: Rc<Ba2<R>>,
+    pub index: usize,
+}
+
+/// [StoredEntry], without the type of the reader.
+trait Stored {
+    fn texture(&self) -> Option<Texture>;
+
+    fn chunk_count(&self) -> usize;
+
+    fn raw_chunks(&mut self) -> Result<Vec<RawChunk>>;
+}
+
+impl Stored for RawEntry {
+    fn texture(&self) -> Option<Texture> {
+        self.texture
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    fn raw_chunks(&mut self) -> Result<Vec<RawChunk>> {
+        Ok(mem::take(&mut self.chunks))
+    }
+}
+
+impl<R: Read + Seek> Stored for StoredEntry<R> {
+    fn texture(&self) -> Option<Texture> {
+        match self.entry() {
+            Entry::General(_) => None,
+            Entry::DirectX(entry) => Some(entry.texture()),
+        }
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.entry().chunks().count()
+    }
+
+    fn raw_chunks(&mut self) -> Result<Vec<RawChunk>> {
+        Ok(self.entry().raw()?.chunks)
+    }
+}
+
+impl<R: Read + Seek> StoredEntry<R> {
+    fn entry(&self) -> Entry<'_> {
+        let ba2: &Ba2Inner<dyn ReadSeek> = &self.archive.inner;
+        ba2.entry(self.index)
+    }
+}
+
+enum Payload {
+    /// Read on the thread writing the archive.
+    Data(Box<dyn FileData>),
+    /// Read on the thread compressing it.
+    SendData(Box<dyn FileData + Send>),
+    Stored(Box<dyn Stored>),
+}
+
+/// The contents of a file, as handed to the thread compressing it.
+enum Input {
+    Contents(Vec<u8>),
+    Data(Box<dyn FileData + Send>),
+    Stored(Option<Texture>, Vec<RawChunk>),
+}
+
+struct File {
+    /// The path, with `\` as the separator.
+    name: String,
+    hash: Hash,
+    payload: Payload,
+    /// How to split a new file into chunks, if not as a single chunk.
+    layout: Option<EntryLayout>,
+}
+
+/// Writes a BA2 archive.
+///
+/// Entries are written in the order they are added, or by path once they are
+/// [sorted](Ba2Writer::sort). New textures must be complete DDS files. New files are
+/// stored as a single chunk, unless they are added with a layout. Chunks with
+/// identical stored contents are written once, and share their data.
+///
+/// Whether a new file is compressed is decided by the rule of the
+/// [CompressionPolicy] for its extension, and by the writer's default otherwise.
+///
+/// Files are compressed on a thread pool, while the archive is written on the
+/// calling thread in the same order regardless of the number of threads. At most
+/// twice as many files as threads are held in memory at once. The same files, in the
+/// same order, always produce the same bytes.
+pub struct Ba2Writer {
+    format: Format,
+    version: Version,
+    compressed: bool,
+    policy: CompressionPolicy,
+    include_names: bool,
+    deduplicate: bool,
+    threads: usize,
+    files: Vec<File>,
+    index: HashMap<Hash, usize>,
+}
+
+impl Ba2Writer {
+    pub fn new(format: Format) -> Ba2Writer {
+        Ba2Writer {
+            format,
+            version: Version::V1,
+            compressed: true,
+            policy: CompressionPolicy::new(),
+            include_names: true,
+            deduplicate: true,
+            threads: num_cpus::get(),
+            files: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Set the version written in the header. Defaults to [Version::V1].
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    /// Set whether new files are compressed. Files copied from another archive keep
+    /// their compression.
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed;
+    }
+
+    pub fn policy(&self) -> &CompressionPolicy {
+        &self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: CompressionPolicy) {
+        self.policy = policy;
+    }
+
+    /// Set whether the string table, which holds the name of every entry, is written.
+    pub fn set_include_names(&mut self, include_names: bool) {
+        self.include_names = include_names;
+    }
+
+    /// Set whether chunks with identical stored contents share their data.
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.deduplicate = deduplicate;
+    }
+
+    /// Set the number of threads reading and compressing files. Defaults to the
+    /// number of CPUs.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Add a file, replacing any file previously added with the same path.
+    ///
+    /// # Errors
+    /// If the path cannot be stored in an archive, or if its hash is the same as that
+    /// of a different path already added.
+    pub fn add<D: 'static + FileData>(&mut self, path: &str, data: D) -> Result<()> {
+        self.insert(path, Payload::Data(Box::new(data)), None)
+    }
+
+    /// Add a file stored as described by `layout`, such as that of an entry of
+    /// another archive: split into its chunks with their compression, and with its
+    /// texture described the same way. See [Ba2Writer::add].
+    ///
+    /// Writing the archive fails with [WriteError::LayoutMismatch] if the sizes of the
+    /// chunks do not add up to the contents of the file, or if the layout has a
+    /// texture and this is not a DX10 archive, or the other way around.
+    pub fn add_with_layout<D: 'static + FileData>(
+        &mut self,
+        path: &str,
+        data: D,
+        layout: EntryLayout,
+    ) -> Result<()> {
+        self.insert(path, Payload::Data(Box::new(data)), Some(layout))
+    }
+
+    /// Add a file that can be read on another thread, such as a file on disk. It is
+    /// read on the thread pool along with being compressed, instead of on the thread
+    /// writing the archive. See [Ba2Writer::add].
+    pub fn add_send<D: 'static + FileData + Send>(&mut self, path: &str, data: D) -> Result<()> {
+        self.insert(path, Payload::SendData(Box::new(data)), None)
+    }
+
+    /// Add an entry as it is stored in another archive. Its chunks are copied
+    /// verbatim, keeping their compression.
+    ///
+    /// # Errors
+    /// If the path cannot be stored in an archive, or if its hash is the same as that
+    /// of a different path already added. Writing the archive fails if the entry is
+    /// a texture and this is not a DX10 archive, or the other way around.
+    pub fn add_raw(&mut self, path: &str, entry: RawEntry) -> Result<()> {
+        self.insert(path, Payload::Stored(Box::new(entry)), None)
+    }
+
+    pub(crate) fn add_stored<R: 'static + Read + Seek>(
+        &mut self,
+        path: &str,
+        entry: StoredEntry<R>,
+    ) -> Result<()> {
+        self.insert(path, Payload::Stored(Box::new(entry)), None)
+    }
+
+    fn insert(&mut self, path: &str, payload: Payload, layout: Option<EntryLayout>) -> Result<()> {
+        let (name, hash) = name_and_hash(path)?;
+        match self.index.get(&hash) {
+            Some(&i) => {
+                let existing = &mut self.files[i];
+                if existing.name.to_lowercase() != name.to_lowercase() {
+                    return Err(collision(&existing.name, &name).into());
+                }
+                existing.name = name;
+                existing.payload = payload;
+                existing.layout = layout;
+            }
+            None => {
+                self.index.insert(hash, self.files.len());
+                self.files.push(File {
+                    name,
+                    hash,
+                    payload,
+                    layout,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a file has been added at `path`.
+    pub fn contains(&self, path: &str) -> bool {
+        hash_file_path(path).is_some_and(|hash| self.index.contains_key(&hash))
+    }
+
+    /// Remove the file at `path`, returning whether it was present.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let i = match hash_file_path(path).and_then(|hash| self.index.get(&hash)) {
+            Some(&i) => i,
+            None => return false,
+        };
+        self.files.remove(i);
+        self.reindex();
+        true
+    }
+
+    /// Move the file at `from` to `to`, keeping its position. Returns whether it was
+    /// present.
+    pub(crate) fn rename(&mut self, from: &str, to: &str) -> Result<bool> {
+        let (name, hash) = name_and_hash(to)?;
+        let i = match hash_file_path(from).and_then(|hash| self.index.get(&hash)) {
+            Some(&i) => i,
+            None => return Ok(false),
+        };
+        if let Some(&existing) = self.index.get(&hash) {
+            if existing != i {
+                return Err(collision(&self.files[existing].name, &name).into());
+            }
+        }
+        let file = &mut self.files[i];
+        file.name = name;
+        file.hash = hash;
+        self.reindex();
+        Ok(true)
+    }
+
+    /// Sort the files added so far by path, ignoring case, so that the archive does
+    /// not depend on the order they were added in.
+    pub fn sort(&mut self) {
+        self.files
+            .sort_by_cached_key(|file| file.name.to_lowercase());
+        self.reindex();
+    }
+
+    fn reindex(&mut self) {
+        self.index = self
+            .files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| (file.hash, i))
+            .collect();
+    }
+
+    /// The number of files added.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Write the archive.
+    ///
+    /// Records are written with placeholder values first, and filled in once the
+    /// sizes of the compressed chunks are known.
+    pub fn write_to<W: Write + Seek>(mut self, mut w: W) -> Result<WriteSummary> {
+        let start = w.stream_position()?;
+        let file_count: u32 = self
+            .files
+            .len()
+            .try_into()
+            .map_err(|_| WriteError::TooLarge)?;
+
+        let (header_len, chunk_len) = match self.format {
+            Format::General => (16, 20),
+            Format::DirectX => (24, 24),
+        };
+        let mut chunk_counts = Vec::with_capacity(self.files.len());
+        for file in &mut self.files {
+            let count = match &file.payload {
+                Payload::Data(_) | Payload::SendData(_) => {
+                    file.layout.as_ref().map_or(1, |layout| layout.chunks.len())
+                }
+                Payload::Stored(stored) => {
+                    if stored.texture().is_some() != (self.format == Format::DirectX) {
+                        return Err(WriteError::FormatMismatch(file.name.clone()).into());
+                    }
+                    stored.chunk_count()
+                }
+            };
+            let count: u8 = count.try_into().map_err(|_| WriteError::TooLarge)?;
+            chunk_counts.push(count);
+        }
+        let records_len: u64 = chunk_counts
+            .iter()
+            .map(|&count| header_len + count as u64 * chunk_len)
+            .sum();
+
+        w.write_all(&[0; 24])?;
+        w.write_all(&vec![0; records_len as usize])?;
+
+        let mut summary = WriteSummary {
+            files: self.files.len(),
+            ..WriteSummary::default()
+        };
+        let mut written = HashMap::new();
+        let mut records = Vec::with_capacity(records_len as usize);
+        let payloads = self.files.iter_mut().map(|file| {
+            let payload = mem::replace(&mut file.payload, Payload::Data(Box::new(Vec::new())));
+            (file.name.clone(), payload, file.layout.take())
+        });
+        let payloads: Vec<_> = payloads.collect();
+
+        // Files are read and compressed on the pool, and written here in order.
+        let policy = Arc::new(self.policy.clone());
+        let mut pipeline: Pipeline<Result<_>> = Pipeline::new(self.threads, self.threads * 2);
+        let mut jobs = payloads.into_iter();
+        for (file, &chunk_count) in self.files.iter().zip(&chunk_counts) {
+            while !pipeline.is_full() {
+                let (name, payload, layout) = match jobs.next() {
+                    Some(job) => job,
+                    None => break,
+                };
+                let input = match payload {
+                    Payload::Data(mut data) => {
+                        let mut contents = Vec::new();
+                        data.write_to(&mut contents)?;
+                        Input::Contents(contents)
+                    }
+                    Payload::SendData(data) => Input::Data(data),
+                    Payload::Stored(mut stored) => {
+                        Input::Stored(stored.texture(), stored.raw_chunks()?)
+                    }
+                };
+                let compressed = policy.extension_rule(&name).unwrap_or(self.compressed);
+                let (format, deduplicate, policy) = (self.format, self.deduplicate, policy.clone());
+                pipeline.submit(move || {
+                    let (texture, chunks) = match input {
+                        Input::Stored(texture, chunks) => (texture, chunks),
+                        Input::Contents(contents) => {
+                            new_chunks(&name, format, compressed, &policy, layout, contents)?
+                        }
+                        Input::Data(mut data) => {
+                            let mut contents = Vec::new();
+                            data.write_to(&mut contents)?;
+                            new_chunks(&name, format, compressed, &policy, layout, contents)?
+                        }
+                    };
+                    let chunks = chunks
+                        .into_iter()
+                        .map(|chunk| {
+                            let digest = deduplicate.then(|| digest(&chunk));
+                            (chunk, digest)
+                        })
+                        .collect::<Vec<_>>();
+                    Ok((texture, chunks))
+                });
+            }
+            let (texture, chunks) = pipeline.next_result().unwrap()??;
+
+            match texture {
+                None => {
+                    let header = GeneralChunkHeader {
+                        id: file.hash,
+                        data_file_index: DataFileIndex::zeroed(),
+                        chunk_count,
+                    };
+                    records.extend_from_slice(bytes_of(&RawGeneralChunkHeader::from(header)));
+                }
+                Some(texture) => {
+                    let header = DirectXChunkHeader {
+                        id: file.hash,
+                        data_file_index: DataFileIndex::zeroed(),
+                        chunk_count,
+                        height: texture.height,
+                        width: texture.width,
+                        mip_count: texture.mip_count,
+                        format: texture.format,
+                        flags: texture.flags,
+                        tile_mode: texture.tile_mode,
+                    };
+                    records.extend_from_slice(bytes_of(&RawDirectXChunkHeader::from(header)));
+                }
+            }
+
+            for (chunk, digest) in chunks {
+                let data_file_offset = match digest.and_then(|digest| written.get(&digest)) {
+                    Some(&offset) => {
+                        summary.deduplicated += 1;
+                        summary.bytes_saved += chunk.data.len() as u64;
+                        offset
+                    }
+                    None => {
+                        let offset = w.stream_position()? - start;
+                        w.write_all(&chunk.data)?;
+                        if let Some(digest) = digest {
+                            written.insert(digest, offset);
+                        }
+                        offset
+                    }
+                };
+                let compressed_size = if chunk.compressed {
+                    let len = u32::try_from(chunk.data.len()).map_err(|_| WriteError::TooLarge)?;
+                    NonZeroU32::new(len)
+                } else {
+                    None
+                };
+
+                if texture.is_none() {
+                    let data = GeneralChunkData {
+                        data_file_offset,
+                        compressed_size,
+                        decompressed_size: chunk.decompressed_size,
+                    };
+                    records.extend_from_slice(bytes_of(&RawGeneralChunkData::from(data)));
+                } else {
+                    let data = DirectXChunkData {
+                        data_file_offset,
+                        compressed_size,
+                        decompressed_size: chunk.decompressed_size,
+                        mip_first: chunk.mip_first,
+                        mip_last: chunk.mip_last,
+                    };
+                    records.extend_from_slice(bytes_of(&RawDirectXChunkData::from(data)));
+                }
+            }
+        }
+
+        let string_table_offset = if self.include_names {
+            let offset = w.stream_position()? - start;
+            for file in &self.files {
+                let name = windows_1252::encode_string(file.name.clone())
+                    .map_err(|_| WriteError::InvalidPath(file.name.clone()))?;
+                w.write_all(&(name.len() as u16).to_le_bytes())?;
+                w.write_all(&name)?;
+            }
+            NonZeroU64::new(offset)
+        } else {
+            None
+        };
+
+        let end = w.stream_position()?;
+        let header = Header {
+            version: self.version,
+            format: self.format,
+            file_count,
+            string_table_offset,
+        };
+        w.seek(SeekFrom::Start(start))?;
+        w.write_all(bytes_of(&RawHeader::from(header)))?;
+        w.write_all(&records)?;
+        w.seek(SeekFrom::Start(end))?;
+        w.flush()?;
+
+        Ok(summary)
+    }
+}
+
+/// Identifies the stored contents of a chunk, for deduplication.
+fn digest(chunk: &RawChunk) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([chunk.compressed as u8]);
+    hasher.update(chunk.decompressed_size.to_le_bytes());
+    hasher.update(&chunk.data);
+    hasher.finalize().into()
+}
+
+/// Turn the contents of a new file into chunks: split as described by `layout`, or
+/// into a single chunk without one. Textures have their DDS header removed, and are
+/// described by the returned [Texture] instead. Without a layout, data that does not
+/// compress well enough for `policy` is stored uncompressed.
+fn new_chunks(
+    name: &str,
+    format: Format,
+    compressed: bool,
+    policy: &CompressionPolicy,
+    layout: Option<EntryLayout>,
+    buf: Vec<u8>,
+) -> Result<(Option<Texture>, Vec<RawChunk>)> {
+    let (texture, buf) = match format {
+        Format::General => (None, &buf[..]),
+        Format::DirectX => {
+            let (texture, header_len) = Texture::from_dds(&buf)
+                .ok_or_else(|| WriteError::InvalidTexture(name.to_owned()))?;
+            (Some(texture), &buf[header_len..])
+        }
+    };
+
+    // A layout is followed exactly, while a single chunk follows the policy.
+    let (layout, exact) = match layout {
+        Some(layout) => {
+            let size: u64 = layout
+                .chunks
+                .iter()
+                .map(|chunk| chunk.decompressed_size as u64)
+                .sum();
+            if layout.texture.is_some() != texture.is_some() || size != buf.len() as u64 {
+                return Err(WriteError::LayoutMismatch(name.to_owned()).into());
+            }
+            (layout, true)
+        }
+        None => {
+            let decompressed_size = u32::try_from(buf.len()).map_err(|_| WriteError::TooLarge)?;
+            let mip_last = texture.map_or(0, |texture| texture.mip_count as u16 - 1);
+            let chunk = ChunkLayout {
+                compressed,
+                decompressed_size,
+                mip_first: 0,
+                mip_last,
+            };
+            let layout = EntryLayout {
+                texture,
+                chunks: vec![chunk],
+            };
+            (layout, false)
+        }
+    };
+
+    let mut chunks = Vec::with_capacity(layout.chunks.len());
+    let mut rest = buf;
+    for chunk in &layout.chunks {
+        let (buf, next) = rest.split_at(chunk.decompressed_size as usize);
+        rest = next;
+
+        let mut data = None;
+        if chunk.compressed {
+            let level = flate2::Compression::new(policy.zlib_level());
+            let mut encoder = ZlibEncoder::new(Vec::new(), level);
+            encoder.write_all(buf)?;
+            let compressed = encoder.finish()?;
+            let saves_space = compressed.len() < buf.len()
+                && policy.keeps_compressed(buf.len() as u64, compressed.len() as u64);
+            if exact || saves_space {
+                data = Some(compressed);
+            }
+        }
+        chunks.push(RawChunk {
+            compressed: data.is_some(),
+            data: data.unwrap_or_else(|| buf.to_vec()),
+            decompressed_size: chunk.decompressed_size,
+            mip_first: chunk.mip_first,
+            mip_last: chunk.mip_last,
+        });
+    }
+    Ok((layout.texture, chunks))
+}
+
+/// Normalize the separators of a path, and compute its hash.
+fn name_and_hash(path: &str) -> Result<(String, Hash)> {
+    let invalid = || WriteError::InvalidPath(path.to_owned());
+    let hash = hash_file_path(path).ok_or_else(invalid)?;
+    let name = path
+        .split(['\\', '/'])
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join("\\");
+    Ok((name, hash))
+}
+
+fn collision(existing: &str, new: &str) -> WriteError {
+    WriteError::HashCollision(existing.to_owned(), new.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bsa_core::Archive;
+    use bytemuck::Zeroable;
+    use dds::DxgiFormat;
+
+    use crate::{
+        raw::{DataFileIndex, DirectXChunkHeader, Format, Hash},
+        texture::dds_header,
+        Ba2,
+    };
+
+    use super::Ba2Writer;
+
+    #[test]
+    fn test_write_general() {
+        let mut writer = Ba2Writer::new(Format::General);
+        writer.add("meshes/a.nif", b"old a".to_vec()).unwrap();
+        writer.add("Meshes\\A.nif", b"new a".to_vec()).unwrap();
+        writer.add("meshes/b.nif", b"b".to_vec()).unwrap();
+        writer.add("meshes/c.nif", b"c".to_vec()).unwrap();
+        assert!(writer.remove("meshes/b.nif"));
+        assert_eq!(writer.len(), 2);
+        let mut out = Cursor::new(Vec::new());
+        writer.write_to(&mut out).unwrap();
+
+        let ba2 = Ba2::new(Cursor::new(out.into_inner())).unwrap();
+        let names: Vec<_> = ba2.entries().map(|entry| entry.name().unwrap()).collect();
+        assert_eq!(names, ["Meshes\\A.nif", "meshes\\c.nif"]);
+        let mut extracted = Vec::new();
+        let entry = ba2.by_name("meshes/a.nif").unwrap();
+        entry.extract_to(&mut extracted).unwrap();
+        assert_eq!(extracted, b"new a");
+        assert_eq!(Archive::by_index(&ba2, 0).size().unwrap(), 5);
+        assert!(ba2.verify().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_write_texture() {
+        let header = DirectXChunkHeader {
+            id: Hash::zeroed(),
+            data_file_index: DataFileIndex::zeroed(),
+            chunk_count: 1,
+            height: 4,
+            width: 4,
+            mip_count: 1,
+            format: u32::from(DxgiFormat::Bc1Unorm) as u8,
+            flags: 0,
+            tile_mode: 8,
+        };
+        let mut dds = dds_header(&header).unwrap();
+        dds.extend_from_slice(&[0x55; 8]);
+
+        let mut writer = Ba2Writer::new(Format::DirectX);
+        writer.add("textures/dirt.dds", dds.clone()).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        writer.write_to(&mut out).unwrap();
+
+        let ba2 = Ba2::new(Cursor::new(out.into_inner())).unwrap();
+        let entry = ba2.by_name("textures\\dirt.dds").unwrap();
+        let mut extracted = Vec::new();
+        entry.extract_to(&mut extracted).unwrap();
+        assert_eq!(extracted, dds);
+        let size = Archive::by_index(&ba2, 0).size().unwrap();
+        assert_eq!(size, dds.len() as u64);
+        assert!(ba2.verify().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_raw_copy() {
+        let mut writer = Ba2Writer::new(Format::General);
+        writer.add("meshes/a.nif", b"a".repeat(100)).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        writer.write_to(&mut out).unwrap();
+        let source = Ba2::new(Cursor::new(out.into_inner())).unwrap();
+        let raw = source.by_name("meshes/a.nif").unwrap().raw().unwrap();
+        assert!(raw.chunks[0].compressed);
+
+        // The destination does not compress new files, but the chunk stays compressed.
+        let mut writer = Ba2Writer::new(Format::General);
+        writer.set_compressed(false);
+        writer.add_raw("meshes/b.nif", raw.clone()).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        writer.write_to(&mut out).unwrap();
+        let copy = Ba2::new(Cursor::new(out.into_inner())).unwrap();
+        assert_eq!(copy.by_name("meshes/b.nif").unwrap().raw().unwrap(), raw);
+
+        let mut writer = Ba2Writer::new(Format::DirectX);
+        writer.add_raw("meshes/b.nif", raw).unwrap();
+        assert!(writer.write_to(Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let write = |names: &[&str], threads| {
+            let mut writer = Ba2Writer::new(Format::General);
+            writer.set_threads(threads);
+            for name in names {
+                writer.add(name, name.repeat(50).into_bytes()).unwrap();
+            }
+            writer.sort();
+            let mut out = Cursor::new(Vec::new());
+            writer.write_to(&mut out).unwrap();
+            out.into_inner()
+        };
+
+        let names = [
+            "meshes/b.nif",
+            "Meshes/A.nif",
+            "textures/c.dds",
+            "meshes/c.nif",
+        ];
+        let mut reversed = names;
+        reversed.reverse();
+        let bytes = write(&names, 1);
+        assert_eq!(bytes, write(&reversed, 4));
+
+        let ba2 = Ba2::new(Cursor::new(bytes)).unwrap();
+        let names: Vec<_> = ba2
+            .entries()
+            .map(|e| e.name().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Meshes\\A.nif",
+                "meshes\\b.nif",
+                "meshes\\c.nif",
+                "textures\\c.dds"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        let mut writer = Ba2Writer::new(Format::General);
+        writer.add("meshes/a.nif", b"same".repeat(100)).unwrap();
+        writer.add("meshes/b.nif", b"same".repeat(100)).unwrap();
+        writer.add("meshes/c.nif", b"other".to_vec()).unwrap();
+        let mut out = Cursor::new(Vec::new());
+        let summary = writer.write_to(&mut out).unwrap();
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.deduplicated, 1);
+        assert!(summary.bytes_saved > 0);
+
+        let ba2 = Ba2::new(Cursor::new(out.into_inner())).unwrap();
+        let offset = |name| {
+            let entry = ba2.by_name(name).unwrap();
+            let offset = entry.chunks().next().unwrap().offset();
+            offset
+        };
+        assert_eq!(offset("meshes\\a.nif"), offset("meshes\\b.nif"));
+        let mut data = Vec::new();
+        ba2.by_name("meshes\\b.nif")
+            .unwrap()
+            .extract_to(&mut data)
+            .unwrap();
+        assert_eq!(data, b"same".repeat(100));
+        assert!(ba2.verify().unwrap().is_ok());
+    }
+}
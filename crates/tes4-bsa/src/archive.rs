@@ -1,12 +1,20 @@
 use std::{
     io::{Read, Seek},
     marker::PhantomData,
+    ops::Range,
     path::Path,
 };
 
-use bsa_core::{Archive, Entries, Entry, ReadError, Result};
+use bsa_core::{verify::Report, Archive, Entries, Entry, ReadError, Result};
 
-use crate::{raw_archive::RawArchive, read_at::ReadAt, Bsa};
+use crate::{
+    hash::Hash,
+    index::{Duplicate, LookupIndex},
+    raw_archive::RawArchive,
+    read_at::ReadAt,
+    resolve::NameResolver,
+    ArchiveFlags, Bsa, Compression, FileFlags, RawBlock,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Index {
@@ -40,6 +48,96 @@ where
         }
     }
 
+    /// Get an entry by the hashes of its directory and file name.
+    ///
+    /// Hashes are always present, so this works even in archives that do not include
+    /// names.
+    pub fn by_hash(&self, folder_hash: Hash, file_hash: Hash) -> Option<Entry<'_, Self>> {
+        let index = self.inner.find_file_by_hash(folder_hash, file_hash)?;
+        Some(Entry::new(&self.inner, index))
+    }
+
+    /// Recover missing directory and file names using a [NameResolver].
+    ///
+    /// Names that are already present are left alone. Returns the number of names
+    /// recovered.
+    pub fn resolve_names(&mut self, resolver: &NameResolver) -> usize {
+        let mut recovered = 0;
+        for dir in &mut self.inner.dirs {
+            if dir.name.is_none() {
+                dir.name = resolver.resolve_directory(dir.hash).map(str::to_owned);
+                recovered += dir.name.is_some() as usize;
+            }
+            for file in &mut dir.files {
+                if file.name.is_none() {
+                    file.name = resolver.resolve_file_name(file.hash).map(str::to_owned);
+                    recovered += file.name.is_some() as usize;
+                }
+            }
+        }
+        if recovered > 0 {
+            self.inner.index = LookupIndex::new(&self.inner.dirs);
+        }
+        recovered
+    }
+
+    /// The flags of the archive, as stored in its header.
+    pub fn archive_flags(&self) -> ArchiveFlags {
+        self.inner.archive_flags
+    }
+
+    /// The flags describing the kinds of files in the archive, as stored in its header.
+    pub fn file_flags(&self) -> FileFlags {
+        self.inner.file_flags
+    }
+
+    /// The stored hashes of the directory and the name of the file at `index`.
+    pub fn hashes(&self, index: Index) -> (Hash, Hash) {
+        let dir = &self.inner.dirs[index.folder as usize];
+        (dir.hash, dir.files[index.file as usize].hash)
+    }
+
+    /// The range of the archive holding the stored block of the file at `index`.
+    pub fn block_range(&self, index: Index) -> Range<u64> {
+        let dir = &self.inner.dirs[index.folder as usize];
+        let file = &dir.files[index.file as usize];
+        let offset = u64::from(file.block_offset);
+        offset..offset + u64::from(file.block_len)
+    }
+
+    /// How the file at `index` is compressed, or [None] if it is stored uncompressed.
+    pub fn compression(&self, index: Index) -> Option<Compression> {
+        let dir = &self.inner.dirs[index.folder as usize];
+        dir.files[index.file as usize].compression
+    }
+
+    /// The contents of the file at `index` as they are stored, without decompressing
+    /// them. Writers copy the block verbatim when the compression matches. See
+    /// [RawWriter::add_raw](crate::RawWriter::add_raw).
+    pub fn raw_block(&self, index: Index) -> Result<RawBlock> {
+        self.inner.raw_block(index)
+    }
+
+    /// Paths stored more than once in the archive. Lookups by name return the first
+    /// of them.
+    pub fn duplicates(&self) -> &[Duplicate] {
+        self.inner.index.duplicates()
+    }
+
+    /// Check the integrity of every entry in the archive.
+    ///
+    /// Every entry is decompressed and its length compared against the stored
+    /// uncompressed length, names are checked against their stored hashes,
+    /// directories and files are checked to be sorted by hash, and duplicate paths are
+    /// reported. Data offsets are checked to fall within the archive, and embedded
+    /// file names are compared against the name tables.
+    ///
+    /// Problems are collected into a [Report] rather than returned as errors. An error
+    /// is only returned if the archive itself cannot be read.
+    pub fn verify(&self) -> Result<Report> {
+        self.inner.verify()
+    }
+
     pub fn extract1<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
         self.inner.extract1(dir.as_ref())
     }
@@ -0,0 +1,155 @@
+//! Byte-level deltas between two versions of a file.
+//!
+//! The old version is split into fixed-size blocks, indexed by a rolling checksum.
+//! The new version is scanned a byte at a time for blocks of the old one, and encoded
+//! as a sequence of copies from the old version and literal insertions.
+
+use std::collections::HashMap;
+
+use bsa_core::ReadError;
+
+use crate::Result;
+
+const BLOCK_LEN: usize = 64;
+
+const COPY: u8 = 0;
+const INSERT: u8 = 1;
+
+/// An Adler-style checksum over a window, which can be rolled forward a byte at a
+/// time.
+#[derive(Clone, Copy)]
+struct Rolling {
+    a: u32,
+    b: u32,
+}
+
+impl Rolling {
+    fn new(window: &[u8]) -> Rolling {
+        let mut a = 0u32;
+        let mut b = 0u32;
+        for &byte in window {
+            a = a.wrapping_add(byte as u32);
+            b = b.wrapping_add(a);
+        }
+        Rolling { a, b }
+    }
+
+    fn roll(&mut self, out: u8, into: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(into as u32);
+        self.b = self
+            .b
+            .wrapping_sub((BLOCK_LEN as u32).wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+
+    fn value(self) -> u32 {
+        (self.b << 16) | (self.a & 0xffff)
+    }
+}
+
+/// Encode `new` as a delta against `old`.
+pub(crate) fn encode(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut blocks: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, block) in old.chunks_exact(BLOCK_LEN).enumerate() {
+        let offset = i * BLOCK_LEN;
+        blocks
+            .entry(Rolling::new(block).value())
+            .or_default()
+            .push(offset);
+    }
+
+    let mut delta = Vec::new();
+    let mut literal_start = 0;
+    let mut pos = 0;
+    let mut rolling = None;
+
+    while pos + BLOCK_LEN <= new.len() {
+        let window = &new[pos..pos + BLOCK_LEN];
+        let checksum = *rolling.get_or_insert_with(|| Rolling::new(window));
+
+        let found = blocks.get(&checksum.value()).and_then(|offsets| {
+            offsets
+                .iter()
+                .copied()
+                .find(|&offset| &old[offset..offset + BLOCK_LEN] == window)
+        });
+
+        match found {
+            Some(offset) => {
+                let len = BLOCK_LEN
+                    + old[offset + BLOCK_LEN..]
+                        .iter()
+                        .zip(&new[pos + BLOCK_LEN..])
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                insert(&mut delta, &new[literal_start..pos]);
+                copy(&mut delta, offset, len);
+                pos += len;
+                literal_start = pos;
+                rolling = None;
+            }
+            None => {
+                if pos + BLOCK_LEN < new.len() {
+                    if let Some(rolling) = &mut rolling {
+                        rolling.roll(new[pos], new[pos + BLOCK_LEN]);
+                    }
+                }
+                pos += 1;
+            }
+        }
+    }
+    insert(&mut delta, &new[literal_start..]);
+
+    delta
+}
+
+fn insert(delta: &mut Vec<u8>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        delta.push(INSERT);
+        delta.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        delta.extend_from_slice(bytes);
+    }
+}
+
+fn copy(delta: &mut Vec<u8>, offset: usize, len: usize) {
+    delta.push(COPY);
+    delta.extend_from_slice(&(offset as u64).to_le_bytes());
+    delta.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+/// Rebuild the new version of a file from the old version and a delta.
+pub(crate) fn apply(old: &[u8], mut delta: &[u8]) -> Result<Vec<u8>> {
+    let mut new = Vec::new();
+    while let Some((&op, rest)) = delta.split_first() {
+        delta = rest;
+        match op {
+            COPY => {
+                let offset = next_u64(&mut delta)? as usize;
+                let len = next_u64(&mut delta)? as usize;
+                let end = offset.checked_add(len).ok_or(ReadError::InvalidPatch)?;
+                let bytes = old.get(offset..end).ok_or(ReadError::InvalidPatch)?;
+                new.extend_from_slice(bytes);
+            }
+            INSERT => {
+                let len = next_u64(&mut delta)? as usize;
+                if delta.len() < len {
+                    return Err(ReadError::InvalidPatch.into());
+                }
+                let (bytes, rest) = delta.split_at(len);
+                new.extend_from_slice(bytes);
+                delta = rest;
+            }
+            _ => return Err(ReadError::InvalidPatch.into()),
+        }
+    }
+    Ok(new)
+}
+
+fn next_u64(bytes: &mut &[u8]) -> Result<u64> {
+    if bytes.len() < 8 {
+        return Err(ReadError::InvalidPatch.into());
+    }
+    let (value, rest) = bytes.split_at(8);
+    *bytes = rest;
+    Ok(u64::from_le_bytes(value.try_into().unwrap()))
+}
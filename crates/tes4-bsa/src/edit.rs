@@ -0,0 +1,142 @@
+//! Editing of existing archives.
+
+use std::{
+    io::{Read, Seek, Write},
+    rc::Rc,
+};
+
+use bsa_core::WriteError;
+
+use crate::{
+    raw_archive::RawArchive,
+    write::{RawWriter, ReaderData, StoredFile},
+    Result, Version,
+};
+
+/// Stages changes to an existing archive of any version, and writes the result as a
+/// new archive with the same version and flags.
+///
+/// Every file of the archive is staged unchanged when it is opened. Files that are not
+/// replaced are copied to the new archive as stored, without decompressing them, even
+/// when they are renamed. Hashes, sort order and offsets are computed again when the
+/// archive is committed.
+///
+/// # Examples
+/// Remove a single file from an archive.
+/// ```no_run
+/// use std::fs::File;
+///
+/// use tes4_bsa::{edit::BsaEditor, Result};
+///
+/// fn remove_file() -> Result<()> {
+///     let mut editor = BsaEditor::open(File::open("Mod.bsa")?)?;
+///     editor.remove("meshes/clutter/bucket.nif")?;
+///     editor.commit(File::create("Mod.new.bsa")?)
+/// }
+/// ```
+pub struct BsaEditor {
+    writer: RawWriter,
+}
+
+impl BsaEditor {
+    /// Open an archive for editing.
+    ///
+    /// # Errors
+    /// If the archive cannot be read, or if it does not include names.
+    pub fn open<R: 'static + Read + Seek>(r: R) -> Result<BsaEditor> {
+        let archive = RawArchive::new(r)?;
+        let paths = archive.paths()?;
+        let archive = Rc::new(archive);
+
+        let mut writer = RawWriter::new(archive.version);
+        writer.set_archive_flags(archive.archive_flags);
+        writer.set_file_flags(archive.file_flags);
+        for (path, index) in paths {
+            let compressed = archive.get(index).1.compression.is_some();
+            let stored = StoredFile {
+                archive: archive.clone(),
+                index,
+            };
+            writer.add_with_compression(&path, compressed, stored)?;
+        }
+        Ok(BsaEditor { writer })
+    }
+
+    pub fn version(&self) -> Version {
+        self.writer.version()
+    }
+
+    /// Whether the archive contains a file at `path`, including staged changes.
+    pub fn contains(&self, path: &str) -> bool {
+        self.writer.contains(path)
+    }
+
+    /// Add a new file, read from `r` when the archive is committed. It is compressed
+    /// if the archive's files are by default.
+    ///
+    /// # Errors
+    /// If the archive already contains a file at `path`.
+    pub fn add<R: 'static + Read>(&mut self, path: &str, r: R) -> Result<()> {
+        if self.contains(path) {
+            return Err(WriteError::AlreadyExists(path.to_owned()).into());
+        }
+        self.writer.add(path, ReaderData(r))
+    }
+
+    /// Replace the contents of an existing file with data read from `r`.
+    ///
+    /// # Errors
+    /// If the archive does not contain a file at `path`.
+    pub fn replace<R: 'static + Read>(&mut self, path: &str, r: R) -> Result<()> {
+        if !self.contains(path) {
+            return Err(WriteError::NotFound(path.to_owned()).into());
+        }
+        self.writer.add(path, ReaderData(r))
+    }
+
+    /// Remove the file at `path`.
+    ///
+    /// # Errors
+    /// If the archive does not contain a file at `path`.
+    pub fn remove(&mut self, path: &str) -> Result<()> {
+        if !self.writer.remove(path) {
+            return Err(WriteError::NotFound(path.to_owned()).into());
+        }
+        Ok(())
+    }
+
+    /// Rename the file at `from` to `to`, which may be in a different folder. Its
+    /// contents and compression are kept.
+    ///
+    /// # Errors
+    /// If the archive does not contain a file at `from`, or already contains one at
+    /// `to`.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        if !self.contains(from) {
+            return Err(WriteError::NotFound(from.to_owned()).into());
+        }
+        if self.contains(to) {
+            return Err(WriteError::AlreadyExists(to.to_owned()).into());
+        }
+        self.writer.rename(from, to)?;
+        Ok(())
+    }
+
+    /// The number of files in the archive, including staged changes.
+    pub fn len(&self) -> usize {
+        self.writer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writer.is_empty()
+    }
+
+    /// Write the archive with every staged change applied.
+    ///
+    /// The new archive must not be written over the archive being edited, since
+    /// unchanged files are read from it while writing.
+    pub fn commit<W: Write + Seek>(self, w: W) -> Result<()> {
+        self.writer.write_to(w)?;
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 //! This module implements the TES4 hashing algorithm and the `Hash` type.
 
 /// A computed filename hash.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Hash {
     pub(crate) last: u8,
     pub(crate) last2: u8,
@@ -157,8 +157,6 @@ pub fn hash_file_name(name: &str) -> Option<Hash> {
         name.push(byte);
     }
     let (stem, extension) = split_extension(&name);
-    dbg!(stem);
-    dbg!(extension);
     if stem.is_empty() || MAX_PATH <= stem.len() || 16 <= extension.len() {
         None
     } else {
@@ -0,0 +1,133 @@
+//! Lookup of entries by hash.
+//!
+//! Lookups binary search the directory and file records by hash, which only works if
+//! the archive was written sorted. Some third party tools write records unsorted, in
+//! which case the index falls back to a hash map. Distinct names may also collide on
+//! the same hash, so every entry matching a hash is returned and the caller decides
+//! between them by comparing full names.
+
+use std::collections::HashMap;
+
+use bsa_core::vfs::normalize;
+
+use crate::{archive::Index, hash::Hash, raw_archive::Dir};
+
+pub(crate) struct LookupIndex {
+    /// Every entry by its directory and file hashes. Only built if the archive is not
+    /// sorted.
+    map: Option<HashMap<(Hash, Hash), Vec<Index>>>,
+    duplicates: Vec<Duplicate>,
+}
+
+/// A path that is stored more than once in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    pub path: String,
+    /// Every entry stored under this path, in archive order.
+    pub entries: Vec<Index>,
+}
+
+impl LookupIndex {
+    pub fn new(dirs: &[Dir]) -> LookupIndex {
+        let mut map: HashMap<(Hash, Hash), Vec<Index>> = HashMap::new();
+        for (folder, dir) in dirs.iter().enumerate() {
+            for (file, record) in dir.files.iter().enumerate() {
+                let index = Index {
+                    folder: folder as u32,
+                    file: file as u32,
+                };
+                map.entry((dir.hash, record.hash)).or_default().push(index);
+            }
+        }
+
+        let mut duplicates = Vec::new();
+        for entries in map.values().filter(|entries| entries.len() > 1) {
+            let mut paths: Vec<(String, Vec<Index>)> = Vec::new();
+            for &index in entries {
+                let path = normalize(&full_name(dirs, index));
+                match paths.iter_mut().find(|(p, _)| *p == path) {
+                    Some((_, indices)) => indices.push(index),
+                    None => paths.push((path, vec![index])),
+                }
+            }
+            for (_, mut entries) in paths.into_iter().filter(|(_, e)| e.len() > 1) {
+                entries.sort_by_key(|index| (index.folder, index.file));
+                let path = full_name(dirs, entries[0]);
+                duplicates.push(Duplicate { path, entries });
+            }
+        }
+        duplicates
+            .sort_by_key(|duplicate| (duplicate.entries[0].folder, duplicate.entries[0].file));
+
+        let sorted = is_sorted(dirs.iter().map(|dir| dir.hash), false)
+            && dirs
+                .iter()
+                .all(|dir| is_sorted(dir.files.iter().map(|file| file.hash), false));
+
+        LookupIndex {
+            map: if sorted { None } else { Some(map) },
+            duplicates,
+        }
+    }
+
+    pub fn duplicates(&self) -> &[Duplicate] {
+        &self.duplicates
+    }
+
+    /// Every entry with the given hashes, in archive order.
+    pub fn find(&self, dirs: &[Dir], folder_hash: Hash, file_hash: Hash) -> Vec<Index> {
+        if let Some(map) = &self.map {
+            return map
+                .get(&(folder_hash, file_hash))
+                .cloned()
+                .unwrap_or_default();
+        }
+
+        let mut indices = Vec::new();
+        let start = dirs.partition_point(|dir| dir.hash < folder_hash);
+        for (folder, dir) in dirs.iter().enumerate().skip(start) {
+            if dir.hash != folder_hash {
+                break;
+            }
+            let start = dir.files.partition_point(|file| file.hash < file_hash);
+            let files = dir.files[start..]
+                .iter()
+                .take_while(|file| file.hash == file_hash);
+            for (file, _) in files.enumerate() {
+                indices.push(Index {
+                    folder: folder as u32,
+                    file: (start + file) as u32,
+                });
+            }
+        }
+        indices
+    }
+}
+
+/// Whether two paths are the same, ignoring case and the type of separator.
+pub(crate) fn same_path(a: &str, b: &str) -> bool {
+    normalize(a) == normalize(b)
+}
+
+fn full_name(dirs: &[Dir], index: Index) -> String {
+    let dir = &dirs[index.folder as usize];
+    let file = &dir.files[index.file as usize];
+    format!("{}/{}", dir.display_name(), file.display_name())
+}
+
+/// Whether `hashes` are in increasing order. The engine binary searches directories
+/// and files, so [verify](crate::BsaArchive::verify) requires a `strict` order, while
+/// the lookup allows equal hashes as it handles them.
+pub(crate) fn is_sorted<I: Iterator<Item = Hash>>(mut hashes: I, strict: bool) -> bool {
+    let mut prev = match hashes.next() {
+        Some(hash) => hash,
+        None => return true,
+    };
+    for hash in hashes {
+        if hash < prev || (strict && hash == prev) {
+            return false;
+        }
+        prev = hash;
+    }
+    true
+}
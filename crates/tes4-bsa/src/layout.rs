@@ -0,0 +1,208 @@
+//! Unpack an archive along with its layout, and pack it back the same way.
+//!
+//! The layout records everything about an archive that is not in its files: the
+//! version, the archive and file flags, whether each file is compressed, whether
+//! identical files share their data, and the order the data is stored in. Packing the
+//! unpacked files with their layout rebuilds the archive with the same header,
+//! records and names.
+//!
+//! Compressed files are compressed again when packing, so the result only matches the
+//! original byte for byte if it was compressed the same way, such as by this crate.
+//! Archives with uncompressed files, or written by this crate, round-trip exactly.
+//!
+//! A layout is saved as text, with the settings of the archive first and then one
+//! line per file in data order: `c` or `u` for a compressed or uncompressed file,
+//! followed by its path.
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use bsa_core::{write::WriteSummary, ReadError, WriteError};
+
+use crate::{
+    archive::Index,
+    raw_archive::{ArchiveFlags, FileFlags, RawArchive},
+    repack::SourceFile,
+    write::{DataOrder, RawWriter},
+    Result, Version,
+};
+
+const LAYOUT_HEADER: &str = "bsa-layout 1";
+
+/// The layout of an archive, as needed to rebuild it from its unpacked files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BsaLayout {
+    pub version: Version,
+    pub archive_flags: ArchiveFlags,
+    pub file_flags: FileFlags,
+    /// Whether files with identical stored contents share their data.
+    pub shared_data: bool,
+    /// Every file, in the order its data is stored.
+    pub files: Vec<FileLayout>,
+}
+
+/// How a single file is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLayout {
+    /// The path of the file, as stored in the archive.
+    pub path: String,
+    pub compressed: bool,
+}
+
+impl BsaLayout {
+    /// Load a layout saved by [BsaLayout::save].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BsaLayout> {
+        let text = fs::read_to_string(path)?;
+        Ok(BsaLayout::parse(&text).ok_or(ReadError::InvalidLayout)?)
+    }
+
+    fn parse(text: &str) -> Option<BsaLayout> {
+        let mut lines = text.lines();
+        if lines.next()? != LAYOUT_HEADER {
+            return None;
+        }
+        let mut setting = |name: &str| {
+            let line = lines.next()?;
+            line.strip_prefix(name)?.strip_prefix(' ')
+        };
+        let version = Version::from_number(setting("version")?.parse().ok()?)?;
+        let archive_flags = ArchiveFlags::from_bits(parse_hex(setting("archive-flags")?)?)?;
+        let file_flags = FileFlags::from_bits(parse_hex(setting("file-flags")?)?.try_into().ok()?)?;
+        let shared_data = setting("shared-data")?.parse().ok()?;
+
+        let mut files = Vec::new();
+        for line in lines {
+            let (compressed, path) = line.split_once(' ')?;
+            let compressed = match compressed {
+                "c" => true,
+                "u" => false,
+                _ => return None,
+            };
+            files.push(FileLayout {
+                path: path.to_owned(),
+                compressed,
+            });
+        }
+        Some(BsaLayout {
+            version,
+            archive_flags,
+            file_flags,
+            shared_data,
+            files,
+        })
+    }
+
+    /// Save the layout as text.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut text = format!("{}\n", LAYOUT_HEADER);
+        writeln!(text, "version {}", self.version.number()).unwrap();
+        writeln!(text, "archive-flags {:#x}", self.archive_flags.bits()).unwrap();
+        writeln!(text, "file-flags {:#x}", self.file_flags.bits()).unwrap();
+        writeln!(text, "shared-data {}", self.shared_data).unwrap();
+        for file in &self.files {
+            let compressed = if file.compressed { 'c' } else { 'u' };
+            writeln!(text, "{} {}", compressed, file.path).unwrap();
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s.strip_prefix("0x")?, 16).ok()
+}
+
+/// Extract every file of the archive at `archive` into `dir`, returning the layout of
+/// the archive.
+///
+/// # Errors
+/// If the archive does not include names, or stores the same path more than once, as
+/// the files would overwrite each other.
+pub fn unpack<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, dir: Q) -> Result<BsaLayout> {
+    unpack_inner(archive.as_ref(), dir.as_ref())
+}
+
+fn unpack_inner(archive_path: &Path, dir: &Path) -> Result<BsaLayout> {
+    let archive = RawArchive::new(BufReader::new(File::open(archive_path)?))?;
+    if let Some(duplicate) = archive.index.duplicates().first() {
+        return Err(WriteError::DuplicatePath(duplicate.path.clone()).into());
+    }
+
+    let mut blocks = Vec::new();
+    for (folder, entry) in archive.dirs.iter().enumerate() {
+        for (file, file_entry) in entry.files.iter().enumerate() {
+            let (dir_name, file_name) = match (&entry.name, &file_entry.name) {
+                (Some(dir_name), Some(file_name)) => (dir_name, file_name),
+                _ => {
+                    let name = format!("{}/{}", entry.display_name(), file_entry.display_name());
+                    return Err(WriteError::MissingName(name).into());
+                }
+            };
+            let index = Index {
+                folder: folder as u32,
+                file: file as u32,
+            };
+            let path = format!("{}\\{}", dir_name, file_name);
+            blocks.push((file_entry.block_offset, path, index));
+        }
+    }
+    blocks.sort_by_key(|&(offset, _, _)| offset);
+    let shared_data = blocks.windows(2).any(|pair| pair[0].0 == pair[1].0);
+
+    let mut files = Vec::with_capacity(blocks.len());
+    for (_, path, index) in blocks {
+        let local = local_path(dir, &path);
+        if let Some(parent) = local.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = BufWriter::new(File::create(&local)?);
+        archive.raw_block(index)?.decompress_to(&mut out)?;
+        out.flush()?;
+
+        let compressed = archive.get(index).1.compression.is_some();
+        files.push(FileLayout { path, compressed });
+    }
+
+    Ok(BsaLayout {
+        version: archive.version,
+        archive_flags: archive.archive_flags,
+        file_flags: archive.file_flags,
+        shared_data,
+        files,
+    })
+}
+
+/// Write an archive of the files in `dir`, as described by `layout`. Files in `dir`
+/// that are not in the layout are left out.
+pub fn pack<P: AsRef<Path>, W: Write + Seek>(
+    dir: P,
+    layout: &BsaLayout,
+    w: W,
+) -> Result<WriteSummary> {
+    let dir = dir.as_ref();
+    let mut writer = RawWriter::new(layout.version);
+    writer.set_archive_flags(layout.archive_flags);
+    writer.set_file_flags(layout.file_flags);
+    writer.set_deduplicate(layout.shared_data);
+    writer.set_data_order(DataOrder::Added);
+    for file in &layout.files {
+        let source = SourceFile(local_path(dir, &file.path));
+        writer.add_with_compression(&file.path, file.compressed, source)?;
+    }
+    writer.write_to(w)
+}
+
+/// Joins an archive path onto `dir`, dropping any component that could escape it.
+fn local_path(dir: &Path, path: &str) -> PathBuf {
+    let mut local = dir.to_owned();
+    for component in path.split(['\\', '/']) {
+        if !matches!(component, "" | "." | "..") {
+            local.push(component);
+        }
+    }
+    local
+}
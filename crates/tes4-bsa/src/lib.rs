@@ -4,17 +4,32 @@ use private::Sealed;
 
 pub mod hash;
 
+pub mod edit;
+pub mod layout;
+pub mod merge;
+pub mod patch;
+pub mod repack;
+
 mod archive;
 mod bytes;
 mod common;
+mod delta;
+mod index;
 mod raw_archive;
 mod read_at;
+mod resolve;
+mod verify;
+mod write;
 
 #[cfg(test)]
 mod tests;
 
 pub use archive::{BsaArchive, Index};
-pub use bsa_core::{Error, Result};
+pub use bsa_core::{policy::CompressionPolicy, write::WriteSummary, Error, Result};
+pub use index::Duplicate;
+pub use raw_archive::{ArchiveFlags, FileFlags};
+pub use resolve::NameResolver;
+pub use write::{BsaWriter, DataOrder, FileData, RawBlock, RawWriter, ReaderData};
 
 pub type Tes4Archive<R> = BsaArchive<Tes4, R>;
 pub type Fo3Archive<R> = BsaArchive<Fo3, R>;
@@ -22,6 +37,12 @@ pub type FnvArchive<R> = BsaArchive<Fnv, R>;
 pub type Tes5Archive<R> = BsaArchive<Tes5, R>;
 pub type SseArchive<R> = BsaArchive<Sse, R>;
 
+pub type Tes4Writer = BsaWriter<Tes4>;
+pub type Fo3Writer = BsaWriter<Fo3>;
+pub type FnvWriter = BsaWriter<Fnv>;
+pub type Tes5Writer = BsaWriter<Tes5>;
+pub type SseWriter = BsaWriter<Sse>;
+
 trait ReadSeek: Read + Seek {}
 
 impl<R: Read + Seek> ReadSeek for R {}
@@ -33,6 +54,27 @@ pub enum Version {
     V105,
 }
 
+impl Version {
+    /// The version number stored in the header.
+    pub(crate) fn number(self) -> u32 {
+        match self {
+            Version::V103 => 103,
+            Version::V104 => 104,
+            Version::V105 => 105,
+        }
+    }
+
+    /// The version with the number stored in the header.
+    pub(crate) fn from_number(number: u32) -> Option<Version> {
+        match number {
+            103 => Some(Version::V103),
+            104 => Some(Version::V104),
+            105 => Some(Version::V105),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Compression {
     Zlib,
@@ -0,0 +1,87 @@
+//! Combine several archives into one.
+
+use std::{
+    io::{self, Read, Seek, Write},
+    rc::Rc,
+};
+
+use bsa_core::merge::{MergeSummary, Precedence};
+
+use crate::{
+    raw_archive::{ArchiveFlags, FileFlags, RawArchive},
+    write::{RawWriter, StoredFile},
+    Result, Version,
+};
+
+/// Merge the archives `inputs`, of any versions, into a single archive of `version`,
+/// and write it to `out`. If `version` is [None], the version of the first archive is
+/// used.
+///
+/// The archive flags of the merged archive are worked out for `version` rather than
+/// copied: names are included, the archive is compressed if any of the archives is,
+/// and file names are embedded if any of the archives embeds them and `version`
+/// supports it. Flags only used on consoles are left unset. The merged archive has the
+/// file flags of every archive. Files keep their compression. Their stored blocks are
+/// copied verbatim if the codec is the same in both versions, and decompressed and
+/// compressed again otherwise.
+///
+/// # Errors
+/// If `inputs` is empty, or if any of the archives does not include names.
+pub fn merge<R, W>(
+    inputs: Vec<R>,
+    mut version: Option<Version>,
+    precedence: Precedence,
+    out: W,
+) -> Result<MergeSummary>
+where
+    R: 'static + Read + Seek,
+    W: Write + Seek,
+{
+    let mut writer: Option<RawWriter> = None;
+    let mut summary = MergeSummary::default();
+
+    for r in inputs {
+        let archive = RawArchive::new(r)?;
+        let paths = archive.paths()?;
+        let archive = Rc::new(archive);
+
+        let target = *version.get_or_insert(archive.version);
+        let writer = writer.get_or_insert_with(|| {
+            let mut writer = RawWriter::new(target);
+            writer.set_file_flags(FileFlags::empty());
+            writer
+        });
+        writer.set_file_flags(writer.file_flags() | archive.file_flags);
+        if archive.archive_flags.contains(ArchiveFlags::COMPRESSED) {
+            writer.set_compressed(true);
+        }
+        let embeds = archive.version != Version::V103
+            && archive
+                .archive_flags
+                .contains(ArchiveFlags::EMBED_FILENAMES);
+        if embeds && target != Version::V103 {
+            writer.set_embed_file_names(true);
+        }
+
+        for (path, index) in paths {
+            if writer.contains(&path) {
+                summary.overridden += 1;
+                if precedence == Precedence::First {
+                    continue;
+                }
+            }
+            let compressed = archive.get(index).1.compression.is_some();
+            let stored = StoredFile {
+                archive: archive.clone(),
+                index,
+            };
+            writer.add_with_compression(&path, compressed, stored)?;
+        }
+    }
+
+    let writer = writer
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no archives to merge"))?;
+    summary.files = writer.len();
+    writer.write_to(out)?;
+    Ok(summary)
+}
@@ -0,0 +1,295 @@
+//! Binary patches between two versions of an archive.
+//!
+//! A patch lists every file of the new archive, along with how to obtain its
+//! contents from the old archive:
+//!
+//! * Unchanged files are copied from the old archive. Their stored blocks are copied
+//!   verbatim when their compression is the same in both versions.
+//! * Changed files are stored as a byte-level delta against the old version of the
+//!   file, or as compressed contents if that is smaller.
+//! * Added files are stored as compressed contents.
+//!
+//! Files of the old archive that the patch does not mention are removed. Files are
+//! matched by path, so both archives must include names.
+//!
+//! A patch records a digest of the old archive, and refuses to apply to any other.
+//! Applying a patch produces an archive with the same contents, names, flags and
+//! compression as the new archive, but it is not guaranteed to be byte-identical to
+//! it.
+//!
+//! # Format
+//! All integers are little endian.
+//!
+//! | Field          | Type       |
+//! |----------------|------------|
+//! | Magic          | `BSAPATCH` |
+//! | Format version | `u32`      |
+//! | Old digest     | SHA-256    |
+//! | New version    | `u32`      |
+//! | Archive flags  | `u32`      |
+//! | File flags     | `u32`      |
+//! | Record count   | `u32`      |
+//!
+//! Each record is an operation (`u8`), flags (`u8`, where `1` means compressed), the
+//! length of the path (`u16`) and the path. Data and delta records follow with the
+//! length of a zlib stream (`u64`) and the stream itself.
+
+use std::{
+    cell::RefCell,
+    io::{self, Read, Seek, SeekFrom, Write},
+    rc::Rc,
+};
+
+use bsa_core::{ReadError, WriteError};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    archive::Index,
+    delta,
+    raw_archive::{ArchiveFlags, FileFlags, RawArchive},
+    write::{FileData, RawWriter, StoredFile},
+    Result, Version,
+};
+
+const MAGIC: &[u8] = b"BSAPATCH";
+const FORMAT_VERSION: u32 = 1;
+
+const COPY: u8 = 0;
+const DATA: u8 = 1;
+const DELTA: u8 = 2;
+
+const COMPRESSED: u8 = 0x1;
+
+/// What a patch changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PatchSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    pub unchanged: usize,
+}
+
+/// Create a patch from the archive `old` to the archive `new`, of any versions, and
+/// write it to `out`.
+pub fn create_patch<R1, R2, W>(mut old: R1, new: R2, mut out: W) -> Result<PatchSummary>
+where
+    R1: Read + Seek,
+    R2: Read + Seek,
+    W: Write,
+{
+    let digest = digest(&mut old)?;
+    let old = RawArchive::new(old)?;
+    let new = RawArchive::new(new)?;
+    let old_paths = old.paths()?;
+    let new_paths = new.paths()?;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&digest);
+    for value in [
+        new.version.number(),
+        new.archive_flags.bits(),
+        new.file_flags.bits() as u32,
+        new_paths.len() as u32,
+    ] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    out.write_all(&header)?;
+
+    let mut summary = PatchSummary::default();
+    for (path, &index) in &new_paths {
+        let (_, file) = new.get(index);
+        let flags = if file.compression.is_some() {
+            COMPRESSED
+        } else {
+            0
+        };
+        let new_contents = contents(&new, index)?;
+
+        let (op, payload) = match old_paths.get(path) {
+            Some(&old_index) => {
+                let old_contents = contents(&old, old_index)?;
+                if old_contents == new_contents {
+                    summary.unchanged += 1;
+                    (COPY, None)
+                } else {
+                    summary.modified += 1;
+                    let data = zlib(&new_contents)?;
+                    let delta = zlib(&delta::encode(&old_contents, &new_contents))?;
+                    if delta.len() < data.len() {
+                        (DELTA, Some(delta))
+                    } else {
+                        (DATA, Some(data))
+                    }
+                }
+            }
+            None => {
+                summary.added += 1;
+                (DATA, Some(zlib(&new_contents)?))
+            }
+        };
+
+        let path_len: u16 = path
+            .len()
+            .try_into()
+            .map_err(|_| WriteError::InvalidPath(path.clone()))?;
+        let mut record = vec![op, flags];
+        record.extend_from_slice(&path_len.to_le_bytes());
+        record.extend_from_slice(path.as_bytes());
+        if let Some(payload) = &payload {
+            record.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        }
+        out.write_all(&record)?;
+        if let Some(payload) = &payload {
+            out.write_all(payload)?;
+        }
+    }
+    summary.removed = old_paths
+        .keys()
+        .filter(|path| !new_paths.contains_key(*path))
+        .count();
+
+    out.flush()?;
+    Ok(summary)
+}
+
+/// Rebuild the new archive from the archive `old` and a patch created from it, and
+/// write it to `out`.
+///
+/// # Errors
+/// If `old` is not the archive the patch was created from.
+pub fn apply_patch<R, P, W>(mut old: R, mut patch: P, out: W) -> Result<()>
+where
+    R: 'static + Read + Seek,
+    P: 'static + Read + Seek,
+    W: Write + Seek,
+{
+    let mut header = [0; 60];
+    patch.read_exact(&mut header)?;
+    let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+    if &header[..8] != MAGIC || u32_at(8) != FORMAT_VERSION {
+        return Err(ReadError::InvalidPatch.into());
+    }
+    if header[12..44] != digest(&mut old)? {
+        return Err(ReadError::PatchMismatch.into());
+    }
+    let version = match u32_at(44) {
+        103 => Version::V103,
+        104 => Version::V104,
+        105 => Version::V105,
+        _ => return Err(ReadError::InvalidPatch.into()),
+    };
+    let archive_flags = ArchiveFlags::from_bits(u32_at(48)).ok_or(ReadError::InvalidPatch)?;
+    let file_flags = u16::try_from(u32_at(52))
+        .ok()
+        .and_then(FileFlags::from_bits)
+        .ok_or(ReadError::InvalidPatch)?;
+    let count = u32_at(56);
+
+    let old = Rc::new(RawArchive::new(old)?);
+    let old_paths = old.paths()?;
+
+    let mut writer = RawWriter::new(version);
+    writer.set_archive_flags(archive_flags);
+    writer.set_file_flags(file_flags);
+
+    let patch = Rc::new(RefCell::new(patch));
+    for _ in 0..count {
+        let mut record = [0; 4];
+        let mut p = patch.borrow_mut();
+        p.read_exact(&mut record)?;
+        let [op, flags, len @ ..] = record;
+        let mut path = vec![0; u16::from_le_bytes(len) as usize];
+        p.read_exact(&mut path)?;
+        let path = String::from_utf8(path).map_err(|_| ReadError::InvalidPatch)?;
+
+        let stored = |index: Index| StoredFile {
+            archive: old.clone(),
+            index,
+        };
+        let base = old_paths.get(&path).map(|&index| stored(index));
+        let compressed = flags & COMPRESSED != 0;
+
+        if op == COPY {
+            let base = base.ok_or(ReadError::InvalidPatch)?;
+            writer.add_with_compression(&path, compressed, base)?;
+            continue;
+        }
+
+        let mut len = [0; 8];
+        p.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+        let offset = p.stream_position()?;
+        p.seek(SeekFrom::Current(len as i64))?;
+
+        let base = match op {
+            DATA => None,
+            DELTA => Some(base.ok_or(ReadError::InvalidPatch)?),
+            _ => return Err(ReadError::InvalidPatch.into()),
+        };
+        let data = PatchData {
+            patch: patch.clone(),
+            offset,
+            len,
+            base,
+        };
+        writer.add_with_compression(&path, compressed, data)?;
+    }
+
+    writer.write_to(out)?;
+    Ok(())
+}
+
+/// The contents of a file stored in a patch, read once the archive is written.
+struct PatchData<P, R> {
+    patch: Rc<RefCell<P>>,
+    offset: u64,
+    len: u64,
+    /// The file the contents are a delta against, if they are a delta.
+    base: Option<StoredFile<R>>,
+}
+
+impl<P: Read + Seek, R: Read + Seek> FileData for PatchData<P, R> {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        let mut payload = Vec::new();
+        {
+            let mut patch = self.patch.borrow_mut();
+            patch.seek(SeekFrom::Start(self.offset))?;
+            let mut decoder = ZlibDecoder::new(patch.by_ref().take(self.len));
+            decoder.read_to_end(&mut payload)?;
+        }
+
+        let contents = match &mut self.base {
+            Some(base) => {
+                let mut old = Vec::new();
+                base.write_to(&mut old)?;
+                delta::apply(&old, &payload)?
+            }
+            None => payload,
+        };
+        w.write_all(&contents)?;
+        Ok(contents.len() as u64)
+    }
+}
+
+fn digest<R: Read + Seek>(r: &mut R) -> Result<[u8; 32]> {
+    r.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::new();
+    io::copy(r, &mut hasher)?;
+    r.seek(SeekFrom::Start(0))?;
+    Ok(hasher.finalize().into())
+}
+
+fn contents<R: Read + Seek>(archive: &RawArchive<R>, index: Index) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    archive.raw_block(index)?.decompress_to(&mut contents)?;
+    Ok(contents)
+}
+
+fn zlib(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
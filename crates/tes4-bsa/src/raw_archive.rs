@@ -1,6 +1,7 @@
 use std::{
     borrow::Cow,
     cell::RefCell,
+    collections::BTreeMap,
     fs,
     io::{self, Cursor, Read, Seek, SeekFrom, Write},
     mem,
@@ -14,7 +15,7 @@ use std::{
 };
 
 use bitflags::bitflags;
-use bsa_core::{detail::EntriesImpl, helpers::read_vec, ReadError};
+use bsa_core::{detail::EntriesImpl, helpers::read_vec, ReadError, WriteError};
 use bytes::Bytes;
 use flate2::bufread::ZlibDecoder;
 use lz4_flex::frame::FrameDecoder;
@@ -26,7 +27,9 @@ use crate::{
     bytes::BytesExt,
     common::read_vec_at,
     hash::{hash_file_path, Hash},
+    index::{self, LookupIndex},
     read_at::ReadAt,
+    write::RawBlock,
     Bsa, BsaArchive, Compression, Result, Version,
 };
 
@@ -35,25 +38,51 @@ where
     R: ?Sized,
 {
     pub version: Version,
+    pub archive_flags: ArchiveFlags,
+    pub file_flags: FileFlags,
     pub embed_file_names: bool,
     pub dirs: Vec<Dir>,
+    pub(crate) index: LookupIndex,
     pub reader: RefCell<R>,
 }
 
 pub struct Dir {
-    pub name: String,
+    pub name: Option<String>,
     pub hash: Hash,
     pub files: Vec<File>,
 }
 
+impl Dir {
+    /// The name of this directory, or its hash in hexadecimal if the archive does
+    /// not include directory names.
+    pub fn display_name(&self) -> Cow<'_, str> {
+        display_name(self.name.as_deref(), self.hash)
+    }
+}
+
 pub struct File {
-    pub name: String,
+    pub name: Option<String>,
     pub hash: Hash,
     pub block_len: u32,
     pub block_offset: u32,
     pub compression: Option<Compression>,
 }
 
+impl File {
+    /// The name of this file, or its hash in hexadecimal if the archive does not
+    /// include file names.
+    pub fn display_name(&self) -> Cow<'_, str> {
+        display_name(self.name.as_deref(), self.hash)
+    }
+}
+
+fn display_name(name: Option<&str>, hash: Hash) -> Cow<'_, str> {
+    match name {
+        Some(name) => Cow::Borrowed(name),
+        None => Cow::Owned(format!("{:016x}", hash.to_u64())),
+    }
+}
+
 impl<R> RawArchive<R>
 where
     R: Read + Seek,
@@ -79,8 +108,19 @@ where
             }
         });
 
-        let file_record_blocks_len =
-            header.file_count * 16 + header.folder_count + header.total_folder_name_len;
+        // Archives may be written without directory and/or file names, in which case
+        // entries can only be identified by their hashes.
+        let include_dirnames = header
+            .archive_flags
+            .contains(ArchiveFlags::INCLUDE_DIRNAMES);
+        let include_filenames = header
+            .archive_flags
+            .contains(ArchiveFlags::INCLUDE_FILENAMES);
+
+        let mut file_record_blocks_len = header.file_count * 16;
+        if include_dirnames {
+            file_record_blocks_len += header.folder_count + header.total_folder_name_len;
+        }
 
         let file_record_blocks = read_vec(&mut r, file_record_blocks_len as usize)?;
         let file_names_block = read_vec(&mut r, header.total_file_name_len as usize)?;
@@ -98,7 +138,11 @@ where
         };
 
         for folder_record in folder_records {
-            let name = file_record_blocks.read_bzstring()?.replace('\\', "/");
+            let name = if include_dirnames {
+                Some(file_record_blocks.read_bzstring()?.replace('\\', "/"))
+            } else {
+                None
+            };
             let file_records = file_record_blocks
                 .read_bytes(folder_record.count as usize * 16)
                 .map_err(|_| ReadError::Eof)?;
@@ -108,9 +152,13 @@ where
             for bytes in file_records.chunks_exact(16) {
                 let bytes = bytes.try_into().unwrap();
                 let file_record = FileRecord::from_bytes(bytes);
-                let name = file_names_block.read_zstring()?.into_owned();
+                let name = if include_filenames {
+                    Some(file_names_block.read_zstring()?.into_owned())
+                } else {
+                    None
+                };
 
-                let compressed = if file_record.len & (1 << 30) != 0 {
+                let compressed = if file_record.len & COMPRESSION_TOGGLE != 0 {
                     !default_compressed
                 } else {
                     default_compressed
@@ -122,7 +170,7 @@ where
                     name,
                     compression,
                     hash: file_record.hash,
-                    block_len: file_record.len,
+                    block_len: file_record.len & !COMPRESSION_TOGGLE,
                     block_offset: file_record.offset,
                 };
                 files.push(file);
@@ -140,34 +188,58 @@ where
             && header.archive_flags.contains(ArchiveFlags::EMBED_FILENAMES);
 
         let reader = RefCell::new(r);
+        let index = LookupIndex::new(&dirs);
 
         Ok(RawArchive {
             version: header.version,
+            archive_flags: header.archive_flags,
+            file_flags: header.file_flags,
             embed_file_names,
             reader,
             dirs,
+            index,
         })
     }
 
+    /// Find a file by its full path. If several entries share the hashes of the path,
+    /// the first whose name matches is returned. Failing that, the first entry with
+    /// neither a stored nor an embedded name is returned, as the hashes are all there
+    /// is to go on. Entries named differently are never returned.
     pub fn find_file_by_name(&self, name: &str) -> Option<Index> {
         let (folder_hash, file_hash) = hash_file_path(name)?;
-        let dir_index = self
-            .dirs
-            .binary_search_by_key(&folder_hash, |dir| dir.hash)
-            .ok()?;
-
-        let dir = &self.dirs[dir_index];
-        let file_index = dir
-            .files
-            .binary_search_by_key(&file_hash, |file| file.hash)
-            .ok()?;
-
-        let index = Index {
-            folder: dir_index as u32,
-            file: file_index as u32,
-        };
+        let candidates = self.index.find(&self.dirs, folder_hash, file_hash);
+
+        let mut unnamed = None;
+        for candidate in candidates {
+            match self.full_name(candidate) {
+                Some(full_name) if index::same_path(&full_name, name) => return Some(candidate),
+                Some(_) => {}
+                None => {
+                    unnamed.get_or_insert(candidate);
+                }
+            }
+        }
+        unnamed
+    }
+
+    /// The full path of an entry, from its directory and file names if both are
+    /// stored, or else from its embedded name.
+    fn full_name(&self, index: Index) -> Option<String> {
+        let (dir, file) = self.get(index);
+        if let (Some(dir_name), Some(file_name)) = (&dir.name, &file.name) {
+            return Some(format!("{}/{}", dir_name, file_name));
+        }
+        if !self.embed_file_names {
+            return None;
+        }
+        let file_block = self.file_block(file).ok()?;
+        let embedded = file_block.embedded_name()?.to_owned();
+        Some(windows_1252::decode_string(embedded))
+    }
 
-        Some(index)
+    pub fn find_file_by_hash(&self, folder_hash: Hash, file_hash: Hash) -> Option<Index> {
+        let candidates = self.index.find(&self.dirs, folder_hash, file_hash);
+        candidates.first().copied()
     }
 
     pub fn extract1<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
@@ -182,7 +254,7 @@ where
         self._extract3(dir.as_ref())
     }
 
-    fn file_block(&self, file: &File) -> Result<FileBlock> {
+    pub(crate) fn file_block(&self, file: &File) -> Result<FileBlock> {
         let mut r = self.reader.borrow_mut();
         let pos = SeekFrom::Start(file.block_offset as u64);
         r.seek(pos)?;
@@ -192,7 +264,69 @@ where
         FileBlock::from_bytes(data, file.compression.is_some(), self.embed_file_names)
     }
 
-    fn get(&self, index: Index) -> (&Dir, &File) {
+    /// The length of the contents of a file once decompressed. Only the embedded name
+    /// and the stored length before the data are read.
+    pub(crate) fn uncompressed_len(&self, file: &File) -> Result<u64> {
+        let mut r = self.reader.borrow_mut();
+        r.seek(SeekFrom::Start(file.block_offset as u64))?;
+
+        let mut header_len = 0;
+        if self.embed_file_names {
+            let mut name_len = [0];
+            r.read_exact(&mut name_len)?;
+            r.seek(SeekFrom::Current(name_len[0] as i64))?;
+            header_len += 1 + name_len[0] as u64;
+        }
+
+        if file.compression.is_some() {
+            let mut len = [0; 4];
+            r.read_exact(&mut len)?;
+            Ok(u32::from_le_bytes(len) as u64)
+        } else {
+            Ok((file.block_len as u64).saturating_sub(header_len))
+        }
+    }
+
+    /// The contents of the file at `index` as they are stored, without decompressing
+    /// them.
+    pub(crate) fn raw_block(&self, index: Index) -> Result<RawBlock> {
+        let (_, file) = self.get(index);
+        let file_block = self.file_block(file)?;
+        Ok(RawBlock {
+            compression: file.compression,
+            uncompressed_len: file_block.uncompressed_len,
+            data: file_block.raw_data().to_vec(),
+        })
+    }
+
+    /// The index of every file by its lower case path. If a path is stored more than
+    /// once, the first entry is used.
+    ///
+    /// # Errors
+    /// If the archive does not include names.
+    pub(crate) fn paths(&self) -> Result<BTreeMap<String, Index>> {
+        let mut paths = BTreeMap::new();
+        for (folder, dir) in self.dirs.iter().enumerate() {
+            for (file, entry) in dir.files.iter().enumerate() {
+                let (dir_name, file_name) = match (&dir.name, &entry.name) {
+                    (Some(dir_name), Some(file_name)) => (dir_name, file_name),
+                    _ => {
+                        let name = format!("{}/{}", dir.display_name(), entry.display_name());
+                        return Err(WriteError::MissingName(name).into());
+                    }
+                };
+                let path = format!("{}/{}", dir_name, file_name).to_lowercase();
+                let index = Index {
+                    folder: folder as u32,
+                    file: file as u32,
+                };
+                paths.entry(path).or_insert(index);
+            }
+        }
+        Ok(paths)
+    }
+
+    pub(crate) fn get(&self, index: Index) -> (&Dir, &File) {
         let dir = &self.dirs[index.folder as usize];
         let file = &dir.files[index.file as usize];
         (dir, file)
@@ -205,11 +339,11 @@ where
     /// remove this dependency.
     fn _extract1(&self, out: &Path) -> Result<()> {
         for dir in &self.dirs {
-            let folder_path = out.join(&dir.name);
+            let folder_path = out.join(dir.display_name().as_ref());
             fs::create_dir_all(&folder_path)?;
 
             for file in &dir.files {
-                let path = folder_path.join(&file.name);
+                let path = folder_path.join(file.display_name().as_ref());
                 let file_block = self.file_block(file)?;
                 save_file(file_block, &path, file.compression)?;
             }
@@ -226,13 +360,13 @@ where
         let (sender, receiver) = channel();
 
         for dir in &self.dirs {
-            let folder_path = out.join(&dir.name);
+            let folder_path = out.join(dir.display_name().as_ref());
             fs::create_dir_all(&folder_path)?;
 
             for file in &dir.files {
                 let file_block = self.file_block(file)?;
                 let compression = file.compression;
-                let path = folder_path.join(&file.name);
+                let path = folder_path.join(file.display_name().as_ref());
                 let sender = sender.clone();
 
                 pool.execute(move || {
@@ -267,12 +401,12 @@ where
         let (uncompressed_tx, uncompressed_rx) = channel();
 
         for dir in &self.dirs {
-            let folder_path = out.join(&dir.name);
+            let folder_path = out.join(dir.display_name().as_ref());
 
             for file in &dir.files {
                 let file_block = self.file_block(file)?;
                 let compression = file.compression;
-                let path = folder_path.join(&file.name);
+                let path = folder_path.join(file.display_name().as_ref());
                 let errors_tx = errors_tx.clone();
                 let uncompressed_tx = uncompressed_tx.clone();
 
@@ -325,14 +459,19 @@ impl<R: ReadAt + Sync> RawArchive<R> {
 
         self.dirs
             .par_iter()
-            .flat_map(|dir| dir.files.par_iter().map(|file| (dir.name.clone(), file)))
+            .flat_map(|dir| {
+                let dirname = dir.display_name().into_owned();
+                dir.files
+                    .par_iter()
+                    .map(move |file| (dirname.clone(), file))
+            })
             .try_for_each(|(dirname, file)| -> Result<()> {
                 let data = read_vec_at(reader, file.block_len as usize, file.block_offset as u64)?;
                 let file_block =
                     FileBlock::from_bytes(data, file.compression.is_some(), embed_filenames)?;
                 let mut path = out.join(dirname);
                 fs::create_dir_all(&path)?;
-                path.push(&file.name);
+                path.push(file.display_name().as_ref());
 
                 save_file(file_block, &path, file.compression)?;
 
@@ -349,13 +488,9 @@ where
     R: Read + Seek,
 {
     fn next(&self, mut index: Index) -> Option<Index> {
+        index.file += 1;
         loop {
-            let dirs = &self.dirs;
-            if index.folder as usize >= dirs.len() {
-                return None;
-            }
-            let dir = &dirs[index.folder as usize];
-            index.file += 1;
+            let dir = self.dirs.get(index.folder as usize)?;
             if (index.file as usize) < dir.files.len() {
                 return Some(index);
             } else {
@@ -367,9 +502,9 @@ where
 
     fn name(&self, index: Index) -> Cow<str> {
         let (dir, file) = self.get(index);
-        let mut name = dir.name.clone();
+        let mut name = dir.display_name().into_owned();
         name.push('/');
-        name.push_str(&file.name);
+        name.push_str(&file.display_name());
         name.into()
     }
 
@@ -384,6 +519,11 @@ where
         let file_block = self.file_block(file)?;
         save_file(file_block, path, file.compression)
     }
+
+    fn size(&self, index: Index) -> Result<u64> {
+        let (_, file) = self.get(index);
+        self.uncompressed_len(file)
+    }
 }
 
 fn save_file(file_block: FileBlock, path: &Path, compression: Option<Compression>) -> Result<()> {
@@ -429,9 +569,9 @@ fn save_file_to<W: ?Sized + Write>(
     Ok(())
 }
 
-struct FileBlock {
+pub(crate) struct FileBlock {
     embedded_name_len: Option<u8>,
-    uncompressed_len: Option<u32>,
+    pub(crate) uncompressed_len: Option<u32>,
     data: Vec<u8>,
 }
 
@@ -469,6 +609,12 @@ impl FileBlock {
         })
     }
 
+    /// The full path embedded before the data, if the archive embeds file names.
+    pub fn embedded_name(&self) -> Option<&[u8]> {
+        let len = self.embedded_name_len? as usize;
+        Some(&self.data[1..1 + len])
+    }
+
     pub fn raw_data(&self) -> &[u8] {
         let mut offset = 0;
         if let Some(len) = self.embedded_name_len {
@@ -497,7 +643,11 @@ impl FileBlock {
     }
 }
 
-const MAGIC: &[u8] = b"BSA\0";
+pub(crate) const MAGIC: &[u8] = b"BSA\0";
+
+/// Set in a file record's size when the file's compression is the opposite of the
+/// archive's default.
+pub(crate) const COMPRESSION_TOGGLE: u32 = 1 << 30;
 
 struct Header {
     pub version: Version,
@@ -519,13 +669,7 @@ impl Header {
         }
 
         let mut next_u32 = || u32::from_le_bytes(chunks.next().unwrap().try_into().unwrap());
-        let version = next_u32();
-        let version = match version {
-            103 => Version::V103,
-            104 => Version::V104,
-            105 => Version::V105,
-            _ => return None,
-        };
+        let version = Version::from_number(next_u32())?;
         let offset = next_u32();
         if offset != 36 {
             return None;
@@ -624,3 +768,44 @@ bitflags! {
         const MISC = 0x100;
     }
 }
+
+impl FileFlags {
+    /// The flag describing a file in an archive of `version`, from its top-level
+    /// folder, or from its extension for files outside of the folders the game uses.
+    ///
+    /// Oblivion (v103) keeps menus in `menus`, and Skyrim Special Edition (v105) keeps
+    /// them in `interface`, along with its fonts. v104 is shared by Fallout 3, New
+    /// Vegas and Skyrim, so both folders are recognized.
+    pub fn for_path(path: &str, version: Version) -> FileFlags {
+        let path = path.to_lowercase();
+        let mut components = path.split(['\\', '/']).filter(|c| !c.is_empty());
+        let top = components.next().unwrap_or("");
+        let second = components.next().unwrap_or("");
+        match top {
+            "meshes" => return FileFlags::MESHES,
+            "textures" => return FileFlags::TEXTURES,
+            "interface" if version != Version::V103 && second == "fonts" => {
+                return FileFlags::FONTS
+            }
+            "interface" if version != Version::V103 => return FileFlags::MENUS,
+            "menus" if version != Version::V105 => return FileFlags::MENUS,
+            "sound" if second == "voice" => return FileFlags::VOICES,
+            "sound" | "music" => return FileFlags::SOUNDS,
+            "shaders" => return FileFlags::SHADERS,
+            "trees" => return FileFlags::TREES,
+            "fonts" => return FileFlags::FONTS,
+            _ => {}
+        }
+
+        let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension);
+        match extension {
+            "nif" | "kf" | "hkx" | "tri" | "egm" | "btr" | "bto" => FileFlags::MESHES,
+            "dds" | "tga" => FileFlags::TEXTURES,
+            "wav" | "mp3" | "ogg" | "xwm" => FileFlags::SOUNDS,
+            "fuz" | "lip" => FileFlags::VOICES,
+            "spt" => FileFlags::TREES,
+            "fnt" => FileFlags::FONTS,
+            _ => FileFlags::MISC,
+        }
+    }
+}
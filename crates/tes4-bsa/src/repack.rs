@@ -0,0 +1,241 @@
+//! Rebuild an archive from a directory, compressing only the files that changed.
+//!
+//! Next to the archive, a cache records the size and SHA-256 digest of every source
+//! file it was built from. A source file whose size and digest match the cache is
+//! unchanged, and its stored block is copied from the existing archive verbatim. Any
+//! other file is compressed again.
+//!
+//! Without a cache entry, such as on the first update, a source file is compared
+//! against the decompressed contents of the archive instead, which is slower but
+//! still avoids compressing it.
+//!
+//! The cache is named after the archive, with `.cache` appended. It also records the
+//! length of the archive, and is ignored if the archive has been rebuilt by something
+//! else since.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use bsa_core::vfs::{normalize, LooseFiles, Source};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    raw_archive::RawArchive,
+    write::{FileData, RawWriter, StoredFile},
+    Result,
+};
+
+const CACHE_HEADER: &str = "bsa-cache 1";
+
+/// What an update did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+    /// Files copied from the existing archive without compressing them.
+    pub reused: usize,
+    /// Files that were added or changed, and compressed again.
+    pub compressed: usize,
+    /// Files of the existing archive no longer in the directory.
+    pub removed: usize,
+}
+
+/// Rebuild the archive at `archive` from the files in `dir`, keeping its version and
+/// flags.
+///
+/// The new archive is written next to the existing one, and replaces it once
+/// complete. The cache is written afterwards.
+pub fn update<P: AsRef<Path>, Q: AsRef<Path>>(archive: P, dir: Q) -> Result<UpdateSummary> {
+    update_inner(archive.as_ref(), dir.as_ref())
+}
+
+fn update_inner(archive_path: &Path, dir: &Path) -> Result<UpdateSummary> {
+    let cache_path = with_suffix(archive_path, ".cache");
+    let temp_path = with_suffix(archive_path, ".tmp");
+
+    let archive_len = fs::metadata(archive_path)?.len();
+    let cache = Cache::load(&cache_path, archive_len);
+
+    let old = RawArchive::new(BufReader::new(File::open(archive_path)?))?;
+    let old_paths = old.paths()?;
+    let old = Rc::new(old);
+
+    let mut writer = RawWriter::new(old.version);
+    writer.set_archive_flags(old.archive_flags);
+    writer.set_file_flags(old.file_flags);
+
+    let sources = LooseFiles::new(dir)?;
+    let mut summary = UpdateSummary::default();
+    let mut new_cache = Cache::default();
+
+    for i in 0..sources.len() {
+        let name = sources.name(i);
+        let path = normalize(&name);
+        let mut counted = Counted::default();
+        sources.read_to(i, &mut counted)?;
+        let entry = counted.finish();
+
+        let unchanged = match old_paths.get(&path) {
+            Some(&index) => match cache.entries.get(&path) {
+                Some(cached) => *cached == entry,
+                None => {
+                    let stored = StoredFile {
+                        archive: old.clone(),
+                        index,
+                    };
+                    CacheEntry::of(stored)? == entry
+                }
+            },
+            None => false,
+        };
+
+        if unchanged {
+            let index = old_paths[&path];
+            let compressed = old.get(index).1.compression.is_some();
+            let stored = StoredFile {
+                archive: old.clone(),
+                index,
+            };
+            writer.add_with_compression(&path, compressed, stored)?;
+            summary.reused += 1;
+        } else {
+            writer.add(&name, SourceFile(dir.join(name.as_ref())))?;
+            summary.compressed += 1;
+        }
+        new_cache.entries.insert(path, entry);
+    }
+    summary.removed = old_paths
+        .keys()
+        .filter(|path| !new_cache.entries.contains_key(*path))
+        .count();
+
+    let mut out = BufWriter::new(File::create(&temp_path)?);
+    let result = writer.write_to(&mut out).and_then(|_| Ok(out.flush()?));
+    drop(out);
+    drop(old);
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    fs::rename(&temp_path, archive_path)?;
+
+    new_cache.save(&cache_path, fs::metadata(archive_path)?.len())?;
+    Ok(summary)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+/// A source file, opened only when the archive is written.
+pub(crate) struct SourceFile(pub(crate) PathBuf);
+
+impl FileData for SourceFile {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        let mut f = File::open(&self.0)?;
+        Ok(io::copy(&mut f, w)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CacheEntry {
+    len: u64,
+    digest: [u8; 32],
+}
+
+impl CacheEntry {
+    fn of<D: FileData>(mut data: D) -> Result<CacheEntry> {
+        let mut counted = Counted::default();
+        data.write_to(&mut counted)?;
+        Ok(counted.finish())
+    }
+}
+
+/// A digest that also counts the bytes written to it.
+#[derive(Default)]
+struct Counted(Sha256, u64);
+
+impl Counted {
+    fn finish(self) -> CacheEntry {
+        CacheEntry {
+            len: self.1,
+            digest: self.0.finalize().into(),
+        }
+    }
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        self.1 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The source files an archive was built from, by normalized path.
+#[derive(Default)]
+struct Cache {
+    entries: BTreeMap<String, CacheEntry>,
+}
+
+impl Cache {
+    /// Load the cache for an archive of `archive_len` bytes. A missing, unreadable or
+    /// stale cache is treated as empty.
+    fn load(path: &Path, archive_len: u64) -> Cache {
+        let mut text = String::new();
+        let read = File::open(path).and_then(|mut f| f.read_to_string(&mut text));
+        if read.is_err() {
+            return Cache::default();
+        }
+        Cache::parse(&text, archive_len).unwrap_or_default()
+    }
+
+    fn parse(text: &str, archive_len: u64) -> Option<Cache> {
+        let mut lines = text.lines();
+        let header = lines.next()?;
+        if header != format!("{} {}", CACHE_HEADER, archive_len) {
+            return None;
+        }
+
+        let mut entries = BTreeMap::new();
+        for line in lines {
+            let mut fields = line.splitn(3, ' ');
+            let len = fields.next()?.parse().ok()?;
+            let hex = fields.next()?;
+            let path = fields.next()?;
+            if hex.len() != 64 {
+                return None;
+            }
+            let mut digest = [0; 32];
+            for (i, byte) in digest.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+            }
+            entries.insert(path.to_owned(), CacheEntry { len, digest });
+        }
+        Some(Cache { entries })
+    }
+
+    fn save(&self, path: &Path, archive_len: u64) -> Result<()> {
+        let mut text = format!("{} {}\n", CACHE_HEADER, archive_len);
+        for (path, entry) in &self.entries {
+            write!(text, "{} ", entry.len).unwrap();
+            for byte in entry.digest {
+                write!(text, "{:02x}", byte).unwrap();
+            }
+            writeln!(text, " {}", path).unwrap();
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
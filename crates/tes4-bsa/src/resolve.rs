@@ -0,0 +1,185 @@
+//! Recovery of names for archives that only store hashes.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+};
+
+use bsa_core::Archive;
+
+use crate::hash::{hash_directory_name, hash_file_name, Hash};
+
+/// A dictionary of known paths, used to recover the names of directories and files
+/// in archives that were written without them.
+///
+/// Directory and file names are hashed independently, so a file name may be
+/// recovered even when its directory is not known, and vice versa.
+///
+/// # Examples
+/// Recover names using the contents of another archive and a wordlist.
+/// ```no_run
+/// use std::{fs::File, io::BufReader};
+///
+/// use bsa_core::Result;
+/// use tes4_bsa::{NameResolver, Tes5Archive};
+///
+/// fn recover() -> Result<()> {
+///     let vanilla = Tes5Archive::new(File::open("Skyrim - Meshes.bsa")?)?;
+///     let mut resolver = NameResolver::new();
+///     resolver.add_archive(&vanilla);
+///     resolver.add_wordlist(BufReader::new(File::open("paths.txt")?))?;
+///
+///     let mut bsa = Tes5Archive::new(File::open("Optimized.bsa")?)?;
+///     let recovered = bsa.resolve_names(&resolver);
+///     println!("recovered {} names", recovered);
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct NameResolver {
+    dirs: HashMap<Hash, String>,
+    files: HashMap<Hash, String>,
+}
+
+impl NameResolver {
+    pub fn new() -> NameResolver {
+        Default::default()
+    }
+
+    /// Add a known file path. The file name, its directory and every ancestor of that
+    /// directory become candidates.
+    ///
+    /// Returns false if the path is not a valid archive path.
+    pub fn add_path(&mut self, path: &str) -> bool {
+        let path = path.trim_matches(['\\', '/']);
+        match path.rfind(['\\', '/']) {
+            Some(i) => {
+                let added_dir = self.add_directory(&path[..i]);
+                let added_file = self.add_file_name(&path[i + 1..]);
+                added_dir && added_file
+            }
+            None => self.add_file_name(path),
+        }
+    }
+
+    /// Add a known directory path, along with every ancestor of that directory.
+    ///
+    /// Returns false if the path is not a valid archive path.
+    pub fn add_directory(&mut self, dir: &str) -> bool {
+        let dir = dir.trim_matches(['\\', '/']);
+        let hash = match hash_directory_name(dir) {
+            Some(hash) => hash,
+            None => return false,
+        };
+        if self.dirs.contains_key(&hash) {
+            return true;
+        }
+        self.dirs.insert(hash, dir.replace('\\', "/"));
+
+        if let Some(i) = dir.rfind(['\\', '/']) {
+            self.add_directory(&dir[..i]);
+        }
+        true
+    }
+
+    /// Add a known file name, without a directory.
+    ///
+    /// Returns false if the name is not a valid file name.
+    pub fn add_file_name(&mut self, name: &str) -> bool {
+        match hash_file_name(name) {
+            Some(hash) => {
+                self.files.entry(hash).or_insert_with(|| name.to_owned());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add every path from a wordlist, containing one path per line. Blank lines and
+    /// lines that are not valid archive paths are ignored.
+    pub fn add_wordlist<R: BufRead>(&mut self, r: R) -> io::Result<()> {
+        for line in r.lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() {
+                self.add_path(line);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the name of every entry in another archive.
+    pub fn add_archive<A: Archive>(&mut self, archive: &A) {
+        for entry in archive.entries() {
+            self.add_path(&entry.name());
+        }
+    }
+
+    /// Add every path referenced by a plugin (.esp, .esm or .esl) file.
+    ///
+    /// Plugins refer to assets with nul-terminated paths that are relative to the
+    /// asset's top level directory (`meshes`, `textures`, ...). The file is scanned for
+    /// strings that look like such paths, and each is added both as-is and prefixed
+    /// with the directory implied by its extension. Strings inside compressed records
+    /// are not found.
+    pub fn add_plugin(&mut self, data: &[u8]) {
+        for s in data.split(|&byte| byte == b'\0') {
+            let s = match plugin_path(s) {
+                Some(s) => s,
+                None => continue,
+            };
+            self.add_path(s);
+
+            let extension = s.rsplit('.').next().unwrap_or_default();
+            if let Some(prefix) = top_level_directory(extension) {
+                let already_prefixed = s
+                    .get(..prefix.len())
+                    .is_some_and(|start| start.eq_ignore_ascii_case(prefix));
+                if !already_prefixed {
+                    self.add_path(&format!("{}\\{}", prefix, s));
+                }
+            }
+        }
+    }
+
+    /// Look up the name of a directory by its hash.
+    pub fn resolve_directory(&self, hash: Hash) -> Option<&str> {
+        self.dirs.get(&hash).map(String::as_str)
+    }
+
+    /// Look up the name of a file by its hash.
+    pub fn resolve_file_name(&self, hash: Hash) -> Option<&str> {
+        self.files.get(&hash).map(String::as_str)
+    }
+}
+
+/// Returns `bytes` as a string if it looks like a relative asset path: printable
+/// ASCII, containing a separator or extension, and ending in a short alphanumeric
+/// extension.
+fn plugin_path(bytes: &[u8]) -> Option<&str> {
+    if bytes.len() < 5 || !bytes.iter().all(|&byte| (0x20..0x7f).contains(&byte)) {
+        return None;
+    }
+    let s = std::str::from_utf8(bytes).ok()?;
+    let (stem, extension) = s.rsplit_once('.')?;
+    let valid_extension = (2..=4).contains(&extension.len())
+        && extension.bytes().all(|byte| byte.is_ascii_alphanumeric());
+    if stem.is_empty() || !valid_extension {
+        None
+    } else {
+        Some(s)
+    }
+}
+
+fn top_level_directory(extension: &str) -> Option<&'static str> {
+    let dir = match extension.to_ascii_lowercase().as_str() {
+        "nif" | "kf" | "hkx" | "tri" | "egm" | "egt" => "meshes",
+        "dds" => "textures",
+        "wav" | "xwm" | "fuz" | "lip" | "mp3" | "ogg" => "sound",
+        "pex" | "psc" => "scripts",
+        "spt" => "trees",
+        "swf" => "interface",
+        _ => return None,
+    };
+    Some(dir)
+}
@@ -94,3 +94,940 @@ pub mod hash {
         }
     }
 }
+
+/// Builds raw archives by hand, for tests needing records the writer never produces.
+pub mod builder {
+    use crate::hash::{hash_directory_name, hash_file_name};
+
+    /// The name and contents of every file of a directory.
+    pub type Files<'a> = &'a [(&'a str, &'a [u8])];
+
+    /// Builds an uncompressed v104 archive, with or without directory and file names.
+    /// Directories and files are written in the order given rather than sorted by hash.
+    pub fn archive(dirs: &[(&str, Files)], names: bool) -> Vec<u8> {
+        let file_count: usize = dirs.iter().map(|(_, files)| files.len()).sum();
+        let (dir_names_len, file_names_len) = if names {
+            let dir_names_len = dirs.iter().map(|(name, _)| name.len() + 1).sum();
+            let file_names_len = dirs
+                .iter()
+                .flat_map(|(_, files)| files.iter().map(|(name, _)| name.len() + 1))
+                .sum();
+            (dir_names_len, file_names_len)
+        } else {
+            (0, 0)
+        };
+        let dir_name_prefixes = if names { dirs.len() } else { 0 };
+        let records_len = 36 + dirs.len() * 16 + dir_name_prefixes + dir_names_len;
+        let mut data_offset = records_len + file_count * 16 + file_names_len;
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"BSA\0");
+        let header = [
+            104,
+            36,
+            if names { 0x3 } else { 0 },
+            dirs.len() as u32,
+            file_count as u32,
+            dir_names_len as u32,
+            file_names_len as u32,
+            0,
+        ];
+        for value in header {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        for (name, files) in dirs {
+            buf.extend_from_slice(&hash_directory_name(name).unwrap().to_bytes());
+            buf.extend_from_slice(&(files.len() as u32).to_le_bytes());
+            buf.extend_from_slice(&0u32.to_le_bytes());
+        }
+        for (name, files) in dirs {
+            if names {
+                buf.push(name.len() as u8 + 1);
+                buf.extend_from_slice(name.as_bytes());
+                buf.push(0);
+            }
+            for (name, data) in *files {
+                buf.extend_from_slice(&hash_file_name(name).unwrap().to_bytes());
+                buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&(data_offset as u32).to_le_bytes());
+                data_offset += data.len();
+            }
+        }
+        if names {
+            for (_, files) in dirs {
+                for (name, _) in *files {
+                    buf.extend_from_slice(name.as_bytes());
+                    buf.push(0);
+                }
+            }
+        }
+        for (_, files) in dirs {
+            for (_, data) in *files {
+                buf.extend_from_slice(data);
+            }
+        }
+        buf
+    }
+}
+
+pub mod names {
+    use std::io::Cursor;
+
+    use bsa_core::Archive;
+
+    use super::builder;
+    use crate::{
+        hash::{hash_directory_name, hash_file_path},
+        NameResolver, Tes5Archive,
+    };
+
+    #[test]
+    pub fn test_hash_only_archive() {
+        let path = "meshes/clutter/bucket01.nif";
+        let (folder_hash, file_hash) = hash_file_path(path).unwrap();
+        let files: builder::Files = &[("bucket01.nif", b"bucket")];
+        let bytes = builder::archive(&[("meshes/clutter", files)], false);
+        let mut bsa = Tes5Archive::new(Cursor::new(bytes)).unwrap();
+
+        let name = bsa.entries().next().unwrap().name().into_owned();
+        assert_eq!(
+            name,
+            format!("{:016x}/{:016x}", folder_hash.to_u64(), file_hash.to_u64())
+        );
+
+        let mut data = Vec::new();
+        let entry = bsa.by_hash(folder_hash, file_hash).unwrap();
+        entry.extract_to(&mut data).unwrap();
+        assert_eq!(data, b"bucket");
+        assert!(bsa.by_name(path).is_some());
+
+        let mut resolver = NameResolver::new();
+        resolver.add_plugin(b"MODL\x1a\0clutter\\Bucket01.nif\0EDID\0");
+        assert!(resolver
+            .resolve_directory(hash_directory_name("meshes").unwrap())
+            .is_some());
+
+        assert_eq!(bsa.resolve_names(&resolver), 2);
+        let name = bsa.entries().next().unwrap().name().into_owned();
+        assert_eq!(name, "meshes/clutter/Bucket01.nif");
+    }
+}
+
+pub mod entries {
+    use std::io::Cursor;
+
+    use bsa_core::Archive;
+
+    use super::builder;
+    use crate::{
+        hash::{hash_directory_name, hash_file_name},
+        Tes5Archive,
+    };
+
+    #[test]
+    pub fn test_iterate_multiple_folders() {
+        let mut dirs = [
+            ("meshes", vec![("a.nif", &b"a"[..]), ("b.nif", &b"b"[..])]),
+            ("textures", vec![("c.dds", &b"c"[..]), ("d.dds", &b"d"[..])]),
+        ];
+        for (_, files) in &mut dirs {
+            files.sort_by_key(|(name, _)| hash_file_name(name).unwrap().to_u64());
+        }
+        dirs.sort_by_key(|(dir, _)| hash_directory_name(dir).unwrap().to_u64());
+        let dirs: Vec<_> = dirs.iter().map(|(dir, files)| (*dir, &files[..])).collect();
+        let bytes = builder::archive(&dirs, false);
+        let bsa = Tes5Archive::new(Cursor::new(bytes)).unwrap();
+
+        // Every file of every folder is visited, including the first of each.
+        let mut contents: Vec<_> = bsa
+            .entries()
+            .map(|entry| {
+                let mut data = Vec::new();
+                entry.extract_to(&mut data).unwrap();
+                data
+            })
+            .collect();
+        contents.sort();
+        assert_eq!(contents, [b"a", b"b", b"c", b"d"]);
+    }
+}
+
+pub mod verify {
+    use std::io::Cursor;
+
+    use bsa_core::verify::IssueKind;
+
+    use crate::{hash::hash_file_name, Tes5Archive, Tes5Writer};
+
+    /// Builds an archive holding a single compressed file, and returns it with the
+    /// position of its file record.
+    fn archive() -> (Vec<u8>, usize) {
+        let mut writer = Tes5Writer::new();
+        writer.set_compressed(true);
+        writer.add("meshes/a.nif", b"mesh".repeat(100)).unwrap();
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write_to(&mut bytes).unwrap();
+        let bytes = bytes.into_inner();
+
+        let hash = hash_file_name("a.nif").unwrap().to_bytes();
+        let record = bytes.windows(8).position(|w| w == hash).unwrap();
+        (bytes, record)
+    }
+
+    fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+    }
+
+    fn issues(bytes: Vec<u8>) -> Vec<IssueKind> {
+        let bsa = Tes5Archive::new(Cursor::new(bytes)).unwrap();
+        let report = bsa.verify().unwrap();
+        assert_eq!(report.entries, 1);
+        report.issues.into_iter().map(|issue| issue.kind).collect()
+    }
+
+    #[test]
+    pub fn test_valid() {
+        let (bytes, _) = archive();
+        assert_eq!(issues(bytes), []);
+    }
+
+    #[test]
+    pub fn test_bad_size() {
+        let (mut bytes, record) = archive();
+        let archive_len = bytes.len() as u64;
+        let offset = read_u32(&bytes, record + 12) as u64;
+        let len = read_u32(&bytes, record + 8) as u64 + 100;
+        bytes[record + 8..record + 12].copy_from_slice(&(len as u32).to_le_bytes());
+
+        let kind = IssueKind::OutOfBounds {
+            offset,
+            len,
+            archive_len,
+        };
+        assert_eq!(issues(bytes), [kind]);
+    }
+
+    #[test]
+    pub fn test_bad_offset() {
+        let (mut bytes, record) = archive();
+        let archive_len = bytes.len() as u64;
+        let len = read_u32(&bytes, record + 8) as u64;
+        bytes[record + 12..record + 16].copy_from_slice(&(archive_len as u32).to_le_bytes());
+
+        let kind = IssueKind::OutOfBounds {
+            offset: archive_len,
+            len,
+            archive_len,
+        };
+        assert_eq!(issues(bytes), [kind]);
+    }
+
+    #[test]
+    pub fn test_bad_uncompressed_len() {
+        let (mut bytes, record) = archive();
+        let offset = read_u32(&bytes, record + 12) as usize;
+        bytes[offset..offset + 4].copy_from_slice(&500u32.to_le_bytes());
+
+        let kind = IssueKind::LengthMismatch {
+            expected: 500,
+            actual: 400,
+        };
+        assert_eq!(issues(bytes), [kind]);
+    }
+
+    #[test]
+    pub fn test_bad_compressed_data() {
+        let (mut bytes, record) = archive();
+        let offset = read_u32(&bytes, record + 12) as usize;
+        let len = read_u32(&bytes, record + 8) as usize;
+        for byte in &mut bytes[offset + 4..offset + len] {
+            *byte = 0xff;
+        }
+
+        let kinds = issues(bytes);
+        assert_eq!(kinds.len(), 1);
+        assert!(matches!(kinds[0], IssueKind::Decompression(_)));
+    }
+}
+
+pub mod index {
+    use std::io::Cursor;
+
+    use bsa_core::Archive;
+
+    use super::builder::{archive, Files};
+    use crate::{
+        hash::{hash_directory_name, hash_file_name},
+        Tes5Archive,
+    };
+
+    #[test]
+    pub fn test_unsorted_archive() {
+        let mut dirs: [(&str, Files); 2] = [
+            ("meshes", &[("a.nif", b"mesh")]),
+            ("textures", &[("a.dds", b"texture"), ("b.dds", b"texture")]),
+        ];
+        dirs.sort_by_key(|(name, _)| std::cmp::Reverse(hash_directory_name(name).unwrap()));
+        let bytes = archive(&dirs, true);
+        let bsa = Tes5Archive::new(Cursor::new(bytes)).unwrap();
+
+        for (path, expected) in [
+            ("meshes/a.nif", &b"mesh"[..]),
+            ("Textures\\A.dds", b"texture"),
+        ] {
+            let mut data = Vec::new();
+            bsa.by_name(path).unwrap().extract_to(&mut data).unwrap();
+            assert_eq!(data, expected);
+        }
+        assert!(bsa.duplicates().is_empty());
+    }
+
+    #[test]
+    pub fn test_duplicate_paths() {
+        let files: Files = &[("a.nif", b"first"), ("a.nif", b"second")];
+        let bytes = archive(&[("meshes", files)], true);
+        let bsa = Tes5Archive::new(Cursor::new(bytes)).unwrap();
+
+        let duplicates = bsa.duplicates();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].path, "meshes/a.nif");
+        assert_eq!(duplicates[0].entries.len(), 2);
+
+        let mut data = Vec::new();
+        bsa.by_name("meshes/a.nif")
+            .unwrap()
+            .extract_to(&mut data)
+            .unwrap();
+        assert_eq!(data, b"first");
+    }
+
+    #[test]
+    pub fn test_hash_collision() {
+        let files: Files = &[("a.nif", b"a"), ("b.nif", b"b")];
+        let mut bytes = archive(&[("meshes", files)], true);
+        // Give both files the hash of a third name.
+        let collision = hash_file_name("c.nif").unwrap().to_bytes();
+        for name in ["a.nif", "b.nif"] {
+            let hash = hash_file_name(name).unwrap().to_bytes();
+            let pos = bytes.windows(8).position(|w| w == hash).unwrap();
+            bytes[pos..pos + 8].copy_from_slice(&collision);
+        }
+        let bsa = Tes5Archive::new(Cursor::new(bytes)).unwrap();
+
+        assert!(bsa.by_name("meshes/c.nif").is_none());
+        assert!(bsa.duplicates().is_empty());
+        let names: Vec<_> = bsa.entries().map(|e| e.name().into_owned()).collect();
+        assert_eq!(names, ["meshes/a.nif", "meshes/b.nif"]);
+    }
+}
+
+pub mod write {
+    use std::io::Cursor;
+
+    use bsa_core::Archive;
+
+    use crate::{
+        Bsa, BsaArchive, BsaWriter, Compression, CompressionPolicy, FileFlags, Sse, SseArchive,
+        SseWriter, Tes4, Tes5, Tes5Archive, Tes5Writer, Version,
+    };
+
+    fn round_trip<A: Bsa>(compression: Compression) {
+        let mut writer = BsaWriter::<A>::new();
+        writer.set_compressed(true);
+        writer.set_embed_file_names(true);
+        writer
+            .add("Meshes\\Clutter/Bucket01.nif", b"bucket".repeat(100))
+            .unwrap();
+        writer
+            .add_with_compression("textures/a.dds", false, b"texture".to_vec())
+            .unwrap();
+        assert!(writer.add("meshes/../a.nif", Vec::new()).is_err());
+
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write_to(&mut bytes).unwrap();
+        bytes.set_position(0);
+        let bsa = BsaArchive::<A, _>::new(bytes).unwrap();
+        assert!(bsa.verify().unwrap().is_ok());
+
+        let bucket = bsa.by_name("meshes/clutter/bucket01.nif").unwrap();
+        assert_eq!(bucket.name(), "meshes/clutter/bucket01.nif");
+        assert_eq!(bsa.compression(bucket.index()), Some(compression));
+        let mut data = Vec::new();
+        bucket.extract_to(&mut data).unwrap();
+        assert_eq!(data, b"bucket".repeat(100));
+        assert_eq!(bucket.size().unwrap(), 600);
+
+        let texture = bsa.by_name("textures/a.dds").unwrap();
+        assert_eq!(bsa.compression(texture.index()), None);
+        assert_eq!(texture.size().unwrap(), 7);
+    }
+
+    #[test]
+    pub fn test_write_round_trip() {
+        round_trip::<Tes4>(Compression::Zlib);
+        round_trip::<Tes5>(Compression::Zlib);
+        round_trip::<Sse>(Compression::Lz4);
+    }
+
+    #[test]
+    pub fn test_raw_copy() {
+        let mut writer = Tes5Writer::new();
+        writer.set_compressed(true);
+        writer.add("meshes/a.nif", b"a".repeat(100)).unwrap();
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write_to(&mut bytes).unwrap();
+        bytes.set_position(0);
+        let source = Tes5Archive::new(bytes).unwrap();
+        let block = source
+            .raw_block(source.by_name("meshes/a.nif").unwrap().index())
+            .unwrap();
+        assert_eq!(block.compression, Some(Compression::Zlib));
+
+        // The destination does not compress by default, but the block stays compressed.
+        let mut tes5 = Tes5Writer::new();
+        tes5.add_raw("meshes/b.nif", block.clone()).unwrap();
+        let mut sse = SseWriter::new();
+        sse.add_raw("meshes/b.nif", block.clone()).unwrap();
+
+        let mut bytes = Cursor::new(Vec::new());
+        tes5.write_to(&mut bytes).unwrap();
+        bytes.set_position(0);
+        let tes5 = Tes5Archive::new(bytes).unwrap();
+        let copied = tes5
+            .raw_block(tes5.by_name("meshes/b.nif").unwrap().index())
+            .unwrap();
+        assert_eq!(copied, block);
+
+        let mut bytes = Cursor::new(Vec::new());
+        sse.write_to(&mut bytes).unwrap();
+        bytes.set_position(0);
+        let sse = SseArchive::new(bytes).unwrap();
+        let entry = sse.by_name("meshes/b.nif").unwrap();
+        assert_eq!(sse.compression(entry.index()), Some(Compression::Lz4));
+        let mut data = Vec::new();
+        entry.extract_to(&mut data).unwrap();
+        assert_eq!(data, b"a".repeat(100));
+    }
+
+    #[test]
+    pub fn test_deduplicate() {
+        let write = |embed| {
+            let mut writer = Tes5Writer::new();
+            writer.set_compressed(true);
+            writer.set_embed_file_names(embed);
+            writer.add("textures/a.dds", b"same".repeat(100)).unwrap();
+            writer.add("textures/b/a.dds", b"same".repeat(100)).unwrap();
+            writer.add("textures/c.dds", b"other".to_vec()).unwrap();
+            let mut bytes = Cursor::new(Vec::new());
+            let summary = writer.write_to(&mut bytes).unwrap();
+            bytes.set_position(0);
+            (summary, Tes5Archive::new(bytes).unwrap())
+        };
+
+        let (summary, bsa) = write(false);
+        assert_eq!(summary.files, 3);
+        assert_eq!(summary.deduplicated, 1);
+        assert!(summary.bytes_saved > 0);
+        assert!(bsa.verify().unwrap().is_ok());
+        let a = bsa.by_name("textures/a.dds").unwrap();
+        let b = bsa.by_name("textures/b/a.dds").unwrap();
+        assert_eq!(
+            bsa.raw_block(a.index()).unwrap(),
+            bsa.raw_block(b.index()).unwrap()
+        );
+        let mut data = Vec::new();
+        b.extract_to(&mut data).unwrap();
+        assert_eq!(data, b"same".repeat(100));
+
+        // Embedded names make every stored block different.
+        let (summary, _) = write(true);
+        assert_eq!(summary.deduplicated, 0);
+    }
+
+    #[test]
+    pub fn test_threads() {
+        let write = |threads| {
+            let mut writer = SseWriter::new();
+            writer.set_compressed(true);
+            writer.set_threads(threads);
+            for i in 0..20 {
+                let data = format!("file {}", i).repeat(100 * i + 1).into_bytes();
+                if i % 2 == 0 {
+                    writer.add(&format!("meshes/{}.nif", i), data).unwrap();
+                } else {
+                    writer.add_send(&format!("meshes/{}.nif", i), data).unwrap();
+                }
+            }
+            let mut bytes = Cursor::new(Vec::new());
+            writer.write_to(&mut bytes).unwrap();
+            bytes.into_inner()
+        };
+
+        let bytes = write(4);
+        assert_eq!(bytes, write(1));
+        let bsa = SseArchive::new(Cursor::new(bytes)).unwrap();
+        assert!(bsa.verify().unwrap().is_ok());
+        let mut data = Vec::new();
+        bsa.by_name("meshes/7.nif")
+            .unwrap()
+            .extract_to(&mut data)
+            .unwrap();
+        assert_eq!(data, "file 7".repeat(701).into_bytes());
+    }
+
+    #[test]
+    pub fn test_compression_policy() {
+        let write = |file_flags| {
+            let mut policy = CompressionPolicy::new();
+            policy.set_extension(".WAV", false);
+            policy.set_min_savings(Some(0.1));
+            policy.set_zlib_level(9);
+            let mut writer = Tes5Writer::new();
+            writer.set_compressed(true);
+            writer.set_file_flags(file_flags);
+            writer.set_policy(policy);
+            writer.add_file_flags_rule(FileFlags::VOICES, false);
+            writer.add("sound/a.wav", b"a".repeat(100)).unwrap();
+            writer.add("sound/b.xwm", b"b".repeat(100)).unwrap();
+            writer.add("sound/c.xwm", b"c".to_vec()).unwrap();
+            writer
+                .add_with_compression("sound/d.wav", true, b"d".repeat(100))
+                .unwrap();
+            let mut bytes = Cursor::new(Vec::new());
+            writer.write_to(&mut bytes).unwrap();
+            bytes.set_position(0);
+            let bsa = Tes5Archive::new(bytes).unwrap();
+            assert!(bsa.verify().unwrap().is_ok());
+            ["a.wav", "b.xwm", "c.xwm", "d.wav"].map(|name| {
+                let entry = bsa.by_name(format!("sound/{}", name)).unwrap();
+                bsa.compression(entry.index()).is_some()
+            })
+        };
+
+        // A single byte grows when compressed, so it is stored uncompressed.
+        assert_eq!(write(FileFlags::SOUNDS), [false, true, false, true]);
+        assert_eq!(write(FileFlags::VOICES), [false, false, false, true]);
+    }
+
+    #[test]
+    pub fn test_lz4_level() {
+        let contents = (0..10_000u32)
+            .map(|i| format!("line {}\n", i % 700))
+            .collect::<String>()
+            .into_bytes();
+        let write = |level| {
+            let mut policy = CompressionPolicy::new();
+            policy.set_lz4_level(level);
+            let mut writer = SseWriter::new();
+            writer.set_compressed(true);
+            writer.set_policy(policy);
+            writer.add("meshes/a.nif", contents.clone()).unwrap();
+            let mut bytes = Cursor::new(Vec::new());
+            writer.write_to(&mut bytes).unwrap();
+            let len = bytes.get_ref().len();
+            bytes.set_position(0);
+            let bsa = SseArchive::new(bytes).unwrap();
+            assert!(bsa.verify().unwrap().is_ok());
+            let mut data = Vec::new();
+            let entry = bsa.by_name("meshes/a.nif").unwrap();
+            assert_eq!(bsa.compression(entry.index()), Some(Compression::Lz4));
+            entry.extract_to(&mut data).unwrap();
+            assert_eq!(data, contents);
+            len
+        };
+
+        assert!(write(12) < write(0));
+    }
+
+    #[test]
+    pub fn test_incompressible_and_max_len() {
+        let write = |max_len| {
+            let mut writer = SseWriter::new();
+            writer.set_compressed(true);
+            writer.set_max_len(max_len);
+            writer.add("meshes/a.nif", b"a".to_vec()).unwrap();
+            writer.add("meshes/b.nif", b"b".repeat(1000)).unwrap();
+            let mut bytes = Cursor::new(Vec::new());
+            writer.write_to(&mut bytes).map(|_| bytes.into_inner())
+        };
+
+        // Without a minimum saving, a file that grows is still stored uncompressed.
+        let bytes = write(None).unwrap();
+        let bsa = SseArchive::new(Cursor::new(&bytes)).unwrap();
+        let entry = bsa.by_name("meshes/a.nif").unwrap();
+        assert_eq!(bsa.compression(entry.index()), None);
+
+        assert!(write(Some(bytes.len() as u64)).is_ok());
+        let err = write(Some(bytes.len() as u64 - 1)).unwrap_err();
+        assert!(err.to_string().contains("meshes\\\\b.nif"), "{}", err);
+    }
+
+    #[test]
+    pub fn test_infer_file_flags() {
+        let mut writer = SseWriter::new();
+        writer.add("meshes/a.nif", b"a".to_vec()).unwrap();
+        writer.add("sound/voice/a.fuz", b"a".to_vec()).unwrap();
+        writer.add("interface/fonts/a.swf", b"a".to_vec()).unwrap();
+        writer.add("scripts/a.pex", b"a".to_vec()).unwrap();
+        assert_eq!(
+            writer.file_flags(),
+            FileFlags::MESHES | FileFlags::VOICES | FileFlags::FONTS | FileFlags::MISC
+        );
+        let for_path = FileFlags::for_path;
+        assert_eq!(
+            for_path("Interface\\Map.swf", Version::V105),
+            FileFlags::MENUS
+        );
+        assert_eq!(
+            for_path("Interface\\Map.swf", Version::V103),
+            FileFlags::MISC
+        );
+        assert_eq!(for_path("menus/map.xml", Version::V103), FileFlags::MENUS);
+        assert_eq!(for_path("menus/map.xml", Version::V105), FileFlags::MISC);
+        assert_eq!(for_path("a.dds", Version::V104), FileFlags::TEXTURES);
+
+        writer.set_file_flags(FileFlags::MESHES);
+        assert_eq!(writer.file_flags(), FileFlags::MESHES);
+    }
+}
+
+pub mod patch {
+    use std::io::Cursor;
+
+    use bsa_core::Archive;
+
+    use crate::{
+        patch::{apply_patch, create_patch, PatchSummary},
+        SseArchive, SseWriter,
+    };
+
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = SseWriter::new();
+        writer.set_compressed(true);
+        for &(path, data) in files {
+            writer.add(path, data.to_vec()).unwrap();
+        }
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write_to(&mut bytes).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    pub fn test_patch() {
+        let texture: Vec<u8> = (0..4096u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut changed = texture.clone();
+        changed[2000..2010].copy_from_slice(b"0123456789");
+
+        let old = archive(&[
+            ("meshes/same.nif", b"same"),
+            ("meshes/removed.nif", b"removed"),
+            ("textures/a.dds", &texture),
+        ]);
+        let new = archive(&[
+            ("meshes/same.nif", b"same"),
+            ("meshes/added.nif", b"added"),
+            ("textures/a.dds", &changed),
+        ]);
+
+        let mut patch = Vec::new();
+        let summary = create_patch(Cursor::new(&old), Cursor::new(&new), &mut patch).unwrap();
+        assert_eq!(
+            summary,
+            PatchSummary {
+                added: 1,
+                removed: 1,
+                modified: 1,
+                unchanged: 1,
+            }
+        );
+        assert!(patch.len() < 200);
+
+        let mut patched = Cursor::new(Vec::new());
+        apply_patch(
+            Cursor::new(old.clone()),
+            Cursor::new(patch.clone()),
+            &mut patched,
+        )
+        .unwrap();
+        patched.set_position(0);
+        let bsa = SseArchive::new(patched).unwrap();
+        let names: Vec<_> = bsa.entries().map(|e| e.name().into_owned()).collect();
+        assert_eq!(names.len(), 3);
+        assert!(bsa.by_name("meshes/removed.nif").is_none());
+        let mut data = Vec::new();
+        bsa.by_name("textures/a.dds")
+            .unwrap()
+            .extract_to(&mut data)
+            .unwrap();
+        assert_eq!(data, changed);
+
+        let other = archive(&[("meshes/same.nif", b"other")]);
+        let result = apply_patch(
+            Cursor::new(other),
+            Cursor::new(patch),
+            Cursor::new(Vec::new()),
+        );
+        assert!(result.is_err());
+    }
+}
+
+pub mod repack {
+    use std::fs;
+
+    use bsa_core::Archive;
+
+    use crate::{
+        repack::{update, UpdateSummary},
+        Tes5Archive, Tes5Writer,
+    };
+
+    #[test]
+    pub fn test_update() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let source = dir.join("source");
+        let archive = dir.join("test.bsa");
+        for (path, contents) in [("meshes/a.nif", "a"), ("meshes/b.nif", "b")] {
+            fs::create_dir_all(source.join(path).parent().unwrap()).unwrap();
+            fs::write(source.join(path), contents).unwrap();
+        }
+
+        let mut writer = Tes5Writer::new();
+        writer.set_compressed(true);
+        writer.add("meshes/a.nif", b"a".to_vec()).unwrap();
+        writer.add("meshes/b.nif", b"old".to_vec()).unwrap();
+        writer.add("meshes/c.nif", b"c".to_vec()).unwrap();
+        writer
+            .write_to(fs::File::create(&archive).unwrap())
+            .unwrap();
+
+        // Without a cache, files are compared against the archive's contents.
+        let summary = update(&archive, &source).unwrap();
+        assert_eq!(
+            summary,
+            UpdateSummary {
+                reused: 1,
+                compressed: 1,
+                removed: 1,
+            }
+        );
+
+        fs::write(source.join("meshes/a.nif"), "changed").unwrap();
+        let summary = update(&archive, &source).unwrap();
+        assert_eq!((summary.reused, summary.compressed), (1, 1));
+
+        let bsa = Tes5Archive::new(fs::File::open(&archive).unwrap()).unwrap();
+        let mut data = Vec::new();
+        bsa.by_name("meshes/a.nif")
+            .unwrap()
+            .extract_to(&mut data)
+            .unwrap();
+        assert_eq!(data, b"changed");
+        assert!(bsa.by_name("meshes/c.nif").is_none());
+        drop(bsa);
+    }
+}
+
+pub mod layout {
+    use std::{fs, io::Cursor};
+
+    use crate::{
+        layout::{pack, unpack, BsaLayout},
+        DataOrder, FileFlags, SseWriter,
+    };
+
+    use super::builder::{self, Files};
+
+    #[test]
+    pub fn test_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let archive = dir.join("test.bsa");
+        let unpacked = dir.join("unpacked");
+
+        let mut writer = SseWriter::new();
+        writer.set_compressed(true);
+        writer.set_embed_file_names(true);
+        writer.set_file_flags(FileFlags::MESHES | FileFlags::SOUNDS);
+        writer.set_data_order(DataOrder::Added);
+        writer.add("sound/z.wav", vec![1; 100]).unwrap();
+        writer
+            .add_with_compression("meshes/b.nif", false, vec![2; 100])
+            .unwrap();
+        writer.add("meshes/a.nif", vec![3; 100]).unwrap();
+        writer
+            .write_to(fs::File::create(&archive).unwrap())
+            .unwrap();
+
+        let layout = unpack(&archive, &unpacked).unwrap();
+        let paths: Vec<_> = layout.files.iter().map(|file| &file.path[..]).collect();
+        assert_eq!(paths, ["sound\\z.wav", "meshes\\b.nif", "meshes\\a.nif"]);
+        assert!(!layout.files[1].compressed);
+
+        layout.save(dir.join("layout")).unwrap();
+        let loaded = BsaLayout::load(dir.join("layout")).unwrap();
+        assert_eq!(loaded, layout);
+
+        let mut packed = Cursor::new(Vec::new());
+        pack(&unpacked, &loaded, &mut packed).unwrap();
+        assert_eq!(packed.into_inner(), fs::read(&archive).unwrap());
+    }
+
+    #[test]
+    pub fn test_duplicate_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let archive = dir.join("test.bsa");
+
+        let files: Files = &[("a.nif", b"first"), ("a.nif", b"second")];
+        fs::write(&archive, builder::archive(&[("meshes", files)], true)).unwrap();
+        let err = unpack(&archive, dir.join("unpacked")).unwrap_err();
+        assert!(err.to_string().contains("meshes/a.nif"), "{}", err);
+    }
+}
+
+pub mod edit {
+    use std::io::Cursor;
+
+    use bsa_core::Archive;
+
+    use crate::{edit::BsaEditor, SseArchive, SseWriter};
+
+    #[test]
+    pub fn test_edit() {
+        let mut writer = SseWriter::new();
+        writer.set_compressed(true);
+        writer.add("meshes/a.nif", b"aaaa".to_vec()).unwrap();
+        writer
+            .add_with_compression("meshes/b.nif", false, b"bbbb".to_vec())
+            .unwrap();
+        writer.add("meshes/c.nif", b"cccc".to_vec()).unwrap();
+        let mut original = Cursor::new(Vec::new());
+        writer.write_to(&mut original).unwrap();
+
+        let mut editor = BsaEditor::open(Cursor::new(original.into_inner())).unwrap();
+        editor.remove("Meshes\\A.nif").unwrap();
+        editor
+            .rename("meshes/b.nif", "textures/moved/b.nif")
+            .unwrap();
+        editor.replace("meshes/c.nif", &b"new c"[..]).unwrap();
+        editor.add("meshes/d.nif", &b"dddd"[..]).unwrap();
+        assert!(editor.add("meshes/c.nif", &b""[..]).is_err());
+        assert!(editor.rename("meshes/c.nif", "meshes/d.nif").is_err());
+        assert!(editor.remove("meshes/a.nif").is_err());
+
+        let mut edited = Cursor::new(Vec::new());
+        editor.commit(&mut edited).unwrap();
+
+        let bsa = SseArchive::new(Cursor::new(edited.into_inner())).unwrap();
+        assert!(bsa.verify().unwrap().is_ok());
+        let mut contents: Vec<_> = bsa
+            .entries()
+            .map(|entry| {
+                let mut data = Vec::new();
+                entry.extract_to(&mut data).unwrap();
+                (entry.name().into_owned(), data)
+            })
+            .collect();
+        contents.sort();
+        let expected = [
+            ("meshes/c.nif", &b"new c"[..]),
+            ("meshes/d.nif", b"dddd"),
+            ("textures/moved/b.nif", b"bbbb"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(name, data)| (name.to_owned(), data.to_vec()))
+            .collect();
+        assert_eq!(contents, expected);
+
+        let moved = bsa.by_name("textures/moved/b.nif").unwrap();
+        assert_eq!(bsa.compression(moved.index()), None);
+    }
+}
+
+pub mod merge {
+    use std::io::Cursor;
+
+    use bsa_core::{
+        merge::{MergeSummary, Precedence},
+        Archive,
+    };
+
+    use crate::{
+        merge::merge, ArchiveFlags, Compression, SseArchive, Tes4Archive, Tes5Writer, Version,
+    };
+
+    fn archive(files: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        archive_with(true, false, files)
+    }
+
+    fn archive_with(compressed: bool, embed: bool, files: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut writer = Tes5Writer::new();
+        writer.set_compressed(compressed);
+        writer.set_embed_file_names(embed);
+        // Contents are repeated so that they shrink when compressed.
+        for &(path, contents) in files {
+            writer.add(path, contents.repeat(100).into_bytes()).unwrap();
+        }
+        let mut bytes = Cursor::new(Vec::new());
+        writer.write_to(&mut bytes).unwrap();
+        bytes.set_position(0);
+        bytes
+    }
+
+    #[test]
+    pub fn test_merge() {
+        for (precedence, expected) in [(Precedence::First, "1"), (Precedence::Last, "2")] {
+            let inputs = vec![
+                archive(&[("meshes/a.nif", "1"), ("meshes/b.nif", "b")]),
+                archive(&[("meshes/a.nif", "2"), ("textures/c.dds", "c")]),
+            ];
+            let mut out = Cursor::new(Vec::new());
+            let summary = merge(inputs, Some(Version::V105), precedence, &mut out).unwrap();
+            assert_eq!(
+                summary,
+                MergeSummary {
+                    files: 3,
+                    overridden: 1,
+                }
+            );
+
+            out.set_position(0);
+            let sse = SseArchive::new(out).unwrap();
+            assert!(sse.verify().unwrap().is_ok());
+            let a = sse.by_name("meshes/a.nif").unwrap();
+            assert_eq!(sse.compression(a.index()), Some(Compression::Lz4));
+            let mut data = Vec::new();
+            a.extract_to(&mut data).unwrap();
+            assert_eq!(data, expected.repeat(100).into_bytes());
+        }
+    }
+
+    #[test]
+    pub fn test_merge_flags() {
+        let names = ArchiveFlags::INCLUDE_DIRNAMES | ArchiveFlags::INCLUDE_FILENAMES;
+        let inputs = || {
+            vec![
+                archive_with(false, false, &[("meshes/a.nif", "a")]),
+                archive_with(true, true, &[("meshes/b.nif", "b")]),
+            ]
+        };
+
+        let mut out = Cursor::new(Vec::new());
+        merge(inputs(), Some(Version::V105), Precedence::Last, &mut out).unwrap();
+        out.set_position(0);
+        let sse = SseArchive::new(out).unwrap();
+        let expected = names | ArchiveFlags::COMPRESSED | ArchiveFlags::EMBED_FILENAMES;
+        assert_eq!(sse.archive_flags(), expected);
+        assert!(sse.verify().unwrap().is_ok());
+
+        // Oblivion archives cannot embed file names.
+        let mut out = Cursor::new(Vec::new());
+        merge(inputs(), Some(Version::V103), Precedence::Last, &mut out).unwrap();
+        out.set_position(0);
+        let tes4 = Tes4Archive::new(out).unwrap();
+        assert_eq!(tes4.archive_flags(), names | ArchiveFlags::COMPRESSED);
+        assert!(tes4.verify().unwrap().is_ok());
+    }
+}
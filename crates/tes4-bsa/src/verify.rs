@@ -0,0 +1,128 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use bsa_core::{
+    verify::{IssueKind, Report},
+    vfs::normalize,
+};
+use flate2::bufread::ZlibDecoder;
+use lz4_flex::frame::FrameDecoder;
+
+use crate::{
+    hash::{hash_directory_name, hash_file_name, Hash},
+    index::is_sorted,
+    raw_archive::{Dir, File, FileBlock, RawArchive},
+    Compression, Result,
+};
+
+impl<R> RawArchive<R>
+where
+    R: Read + Seek,
+{
+    /// Check every entry in the archive. See
+    /// [BsaArchive::verify](crate::BsaArchive::verify).
+    pub fn verify(&self) -> Result<Report> {
+        let mut report = Report::new();
+        let archive_len = self.reader.borrow_mut().seek(SeekFrom::End(0))?;
+
+        if !is_sorted(self.dirs.iter().map(|dir| dir.hash), true) {
+            report.push(None::<String>, IssueKind::UnsortedDirectories);
+        }
+        for duplicate in self.index.duplicates() {
+            let count = duplicate.entries.len();
+            report.push(
+                Some(duplicate.path.as_str()),
+                IssueKind::DuplicatePath { count },
+            );
+        }
+
+        for dir in &self.dirs {
+            let dir_name = dir.display_name();
+
+            if let Some(name) = &dir.name {
+                check_hash(&mut report, &dir_name, hash_directory_name(name), dir.hash);
+            }
+            if !is_sorted(dir.files.iter().map(|file| file.hash), true) {
+                report.push(Some(dir_name.as_ref()), IssueKind::UnsortedFiles);
+            }
+
+            for file in &dir.files {
+                report.entries += 1;
+                self.verify_file(&mut report, dir, file, archive_len);
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn verify_file(&self, report: &mut Report, dir: &Dir, file: &File, archive_len: u64) {
+        let path = format!("{}/{}", dir.display_name(), file.display_name());
+
+        if let Some(name) = &file.name {
+            check_hash(report, &path, hash_file_name(name), file.hash);
+        }
+
+        let offset = file.block_offset as u64;
+        let len = file.block_len as u64;
+        if archive_len < offset + len {
+            let kind = IssueKind::OutOfBounds {
+                offset,
+                len,
+                archive_len,
+            };
+            report.push(Some(path), kind);
+            return;
+        }
+
+        let file_block = match self.file_block(file) {
+            Ok(file_block) => file_block,
+            Err(e) => {
+                report.push(Some(path), IssueKind::Read(e.to_string()));
+                return;
+            }
+        };
+
+        if let (Some(embedded), Some(_), Some(_)) =
+            (file_block.embedded_name(), &dir.name, &file.name)
+        {
+            let embedded = windows_1252::decode_string(embedded.to_owned());
+            if normalize(&embedded) != normalize(&path) {
+                let kind = IssueKind::EmbeddedNameMismatch {
+                    embedded,
+                    expected: path.clone(),
+                };
+                report.push(Some(path.as_str()), kind);
+            }
+        }
+
+        if let Some(expected) = file_block.uncompressed_len {
+            match decompressed_len(file_block, file.compression) {
+                Ok(actual) if actual != expected as u64 => {
+                    let kind = IssueKind::LengthMismatch {
+                        expected: expected as u64,
+                        actual,
+                    };
+                    report.push(Some(path), kind);
+                }
+                Ok(_) => {}
+                Err(e) => report.push(Some(path), IssueKind::Decompression(e.to_string())),
+            }
+        }
+    }
+}
+
+fn check_hash(report: &mut Report, name: &str, computed: Option<Hash>, stored: Hash) {
+    match computed {
+        Some(hash) if hash == stored => {}
+        Some(_) => report.push(Some(name), IssueKind::HashMismatch),
+        None => report.push(Some(name), IssueKind::InvalidName),
+    }
+}
+
+fn decompressed_len(file_block: FileBlock, compression: Option<Compression>) -> io::Result<u64> {
+    let data = file_block.into_raw_data();
+    match compression {
+        Some(Compression::Zlib) => io::copy(&mut ZlibDecoder::new(data), &mut io::sink()),
+        Some(Compression::Lz4) => io::copy(&mut FrameDecoder::new(data), &mut io::sink()),
+        None => Ok(data.get_ref().len() as u64 - data.position()),
+    }
+}
@@ -0,0 +1,943 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    mem,
+    rc::Rc,
+    sync::Arc,
+};
+
+use bsa_core::{pipeline::Pipeline, policy::CompressionPolicy, write::WriteSummary, WriteError};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+use lz4_flex::frame::{FrameDecoder, FrameEncoder};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    archive::Index,
+    hash::{hash_directory_name, hash_file_name, hash_file_path, Hash},
+    raw_archive::{ArchiveFlags, FileFlags, RawArchive, COMPRESSION_TOGGLE, MAGIC},
+    Bsa, Compression, Result, Version,
+};
+
+/// The data of a file to be written to an archive.
+pub trait FileData {
+    /// Write the uncompressed contents of the file to `w`, returning the number of
+    /// bytes written.
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64>;
+
+    /// The contents as they are already stored in an archive, if they are available
+    /// that way. Writers copy them verbatim when the compression matches, instead of
+    /// compressing the contents again.
+    fn raw(&mut self) -> Result<Option<RawBlock>> {
+        Ok(None)
+    }
+}
+
+impl FileData for Vec<u8> {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        w.write_all(self)?;
+        Ok(self.len() as u64)
+    }
+}
+
+impl FileData for fs::File {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        self.seek(SeekFrom::Start(0))?;
+        Ok(io::copy(self, w)?)
+    }
+}
+
+/// The contents of a file, read from any reader once the archive is written.
+pub struct ReaderData<R>(pub R);
+
+impl<R: Read> FileData for ReaderData<R> {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        Ok(io::copy(&mut self.0, w)?)
+    }
+}
+
+/// The contents of a file as stored in an archive: compressed with the archive's
+/// codec or not at all, without the embedded name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawBlock {
+    pub compression: Option<Compression>,
+    /// The length of the contents once decompressed, if they are compressed.
+    pub uncompressed_len: Option<u32>,
+    pub data: Vec<u8>,
+}
+
+impl RawBlock {
+    /// Write the decompressed contents to `w`, returning the number of bytes written.
+    pub fn decompress_to(&self, w: &mut dyn Write) -> Result<u64> {
+        let n = match self.compression {
+            Some(Compression::Zlib) => io::copy(&mut ZlibDecoder::new(&self.data[..]), w)?,
+            Some(Compression::Lz4) => io::copy(&mut FrameDecoder::new(&self.data[..]), w)?,
+            None => {
+                w.write_all(&self.data)?;
+                self.data.len() as u64
+            }
+        };
+        Ok(n)
+    }
+}
+
+impl FileData for RawBlock {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        self.decompress_to(w)
+    }
+
+    fn raw(&mut self) -> Result<Option<RawBlock>> {
+        Ok(Some(self.clone()))
+    }
+}
+
+/// A file in an archive that has been read, copied without decompression when
+/// possible.
+pub(crate) struct StoredFile<R> {
+    pub archive: Rc<RawArchive<R>>,
+    pub index: Index,
+}
+
+impl<R: Read + Seek> FileData for StoredFile<R> {
+    fn write_to(&mut self, w: &mut dyn Write) -> Result<u64> {
+        self.archive.raw_block(self.index)?.decompress_to(w)
+    }
+
+    fn raw(&mut self) -> Result<Option<RawBlock>> {
+        Ok(Some(self.archive.raw_block(self.index)?))
+    }
+}
+
+struct Dir {
+    name: Vec<u8>,
+    files: BTreeMap<Hash, File>,
+}
+
+struct File {
+    name: Vec<u8>,
+    /// Whether to compress this file, if different from the archive's default.
+    compressed: Option<bool>,
+    /// The number of files added before this one.
+    added: usize,
+    data: Data,
+}
+
+enum Data {
+    /// Read on the thread writing the archive.
+    Local(Box<dyn FileData>),
+    /// Read on the thread compressing it.
+    Send(Box<dyn FileData + Send>),
+}
+
+/// The contents of a file, as handed to the thread compressing it.
+enum Input {
+    Raw(RawBlock),
+    Contents(Vec<u8>),
+    Data(Box<dyn FileData + Send>),
+}
+
+/// The order in which the data of files is written, after the records and names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DataOrder {
+    /// Sorted by hash, like the records.
+    #[default]
+    Hash,
+    /// In the order files were added, such as to reproduce the layout of an
+    /// existing archive.
+    Added,
+}
+
+/// Writes an archive of any version, chosen at runtime. See [BsaWriter] for an
+/// archive of a specific game.
+///
+/// Directories and files are written sorted by hash, the way the game expects them,
+/// so the archive does not depend on the order files are added in, unless their data
+/// is written in [DataOrder::Added]. Names are stored in lower case with `\` as the
+/// separator.
+///
+/// Files with identical stored contents are written once, and share their data.
+///
+/// The file flags are inferred from the files added, as described in
+/// [FileFlags::for_path], unless they are set.
+///
+/// Whether a file is compressed is decided, in order, by the compression it was added
+/// with, the rule of the [CompressionPolicy] for its extension, the first rule for
+/// the archive's file flags, and the archive's default. Rules for file flags look at
+/// the flags of the whole archive rather than of each file.
+///
+/// Files are compressed on a thread pool, while the archive is written on the calling
+/// thread in the same order regardless of the number of threads. At most twice as
+/// many files as threads are held in memory at once.
+pub struct RawWriter {
+    version: Version,
+    archive_flags: ArchiveFlags,
+    /// The file flags, if set instead of inferred.
+    file_flags: Option<FileFlags>,
+    policy: CompressionPolicy,
+    file_flags_rules: Vec<(FileFlags, bool)>,
+    deduplicate: bool,
+    threads: usize,
+    data_order: DataOrder,
+    max_len: Option<u64>,
+    added: usize,
+    dirs: BTreeMap<Hash, Dir>,
+}
+
+impl RawWriter {
+    pub fn new(version: Version) -> RawWriter {
+        RawWriter {
+            version,
+            archive_flags: ArchiveFlags::INCLUDE_DIRNAMES | ArchiveFlags::INCLUDE_FILENAMES,
+            file_flags: None,
+            policy: CompressionPolicy::new(),
+            file_flags_rules: Vec::new(),
+            deduplicate: true,
+            threads: num_cpus::get(),
+            data_order: DataOrder::Hash,
+            max_len: None,
+            added: 0,
+            dirs: BTreeMap::new(),
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn archive_flags(&self) -> ArchiveFlags {
+        self.archive_flags
+    }
+
+    pub fn set_archive_flags(&mut self, flags: ArchiveFlags) {
+        self.archive_flags = flags;
+    }
+
+    /// The file flags, as set or as inferred from the files added so far.
+    pub fn file_flags(&self) -> FileFlags {
+        self.file_flags
+            .unwrap_or_else(|| self.inferred_file_flags())
+    }
+
+    /// Set the file flags, instead of inferring them.
+    pub fn set_file_flags(&mut self, flags: FileFlags) {
+        self.file_flags = Some(flags);
+    }
+
+    /// The file flags describing the files added so far, as given by
+    /// [FileFlags::for_path].
+    pub fn inferred_file_flags(&self) -> FileFlags {
+        let mut flags = FileFlags::empty();
+        for dir in self.dirs.values() {
+            let dir_name = String::from_utf8_lossy(&dir.name);
+            for file in dir.files.values() {
+                let file_name = String::from_utf8_lossy(&file.name);
+                let path = format!("{}\\{}", dir_name, file_name);
+                flags |= FileFlags::for_path(&path, self.version);
+            }
+        }
+        flags
+    }
+
+    /// Set whether files are compressed by default.
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.archive_flags.set(ArchiveFlags::COMPRESSED, compressed);
+    }
+
+    pub fn policy(&self) -> &CompressionPolicy {
+        &self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: CompressionPolicy) {
+        self.policy = policy;
+    }
+
+    /// Set whether files are compressed when the archive's file flags include any of
+    /// `flags`, such as leaving sounds uncompressed. Rules added first take
+    /// precedence.
+    ///
+    /// The rule applies to every file of a matching archive, not only to the files
+    /// described by `flags`: a rule leaving sounds uncompressed leaves the meshes of an
+    /// archive holding both uncompressed too. Use extension rules of the
+    /// [CompressionPolicy] for single files.
+    pub fn add_file_flags_rule(&mut self, flags: FileFlags, compressed: bool) {
+        self.file_flags_rules.push((flags, compressed));
+    }
+
+    /// Whether a file is compressed, if it was not added with a compression of its
+    /// own.
+    fn compressed_by_rules(&self, file_name: &[u8], file_flags: FileFlags) -> bool {
+        let file_name = String::from_utf8_lossy(file_name);
+        self.policy
+            .extension_rule(&file_name)
+            .or_else(|| {
+                self.file_flags_rules
+                    .iter()
+                    .find(|(flags, _)| file_flags.intersects(*flags))
+                    .map(|&(_, compressed)| compressed)
+            })
+            .unwrap_or_else(|| self.archive_flags.contains(ArchiveFlags::COMPRESSED))
+    }
+
+    /// Set whether the full path of every file is embedded before its data. Version
+    /// 103 archives do not support this.
+    pub fn set_embed_file_names(&mut self, embed: bool) {
+        self.archive_flags.set(ArchiveFlags::EMBED_FILENAMES, embed);
+    }
+
+    /// Set whether files with identical stored contents share their data. Files
+    /// never share data when their names are embedded, since the name is stored
+    /// along with it.
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.deduplicate = deduplicate;
+    }
+
+    /// Set the number of threads reading and compressing files. Defaults to the
+    /// number of CPUs.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Set the order in which the data of files is written. Defaults to
+    /// [DataOrder::Hash].
+    pub fn set_data_order(&mut self, order: DataOrder) {
+        self.data_order = order;
+    }
+
+    /// Set the largest the archive may be, in bytes. Writing fails with
+    /// [WriteError::ExceedsLimit], naming the first file whose data ends past it.
+    pub fn set_max_len(&mut self, max_len: Option<u64>) {
+        self.max_len = max_len;
+    }
+
+    /// The codec used for compressed files.
+    pub fn compression(&self) -> Compression {
+        match self.version {
+            Version::V103 | Version::V104 => Compression::Zlib,
+            Version::V105 => Compression::Lz4,
+        }
+    }
+
+    /// Add a file, replacing any file previously added with the same path.
+    ///
+    /// # Errors
+    /// If the path cannot be stored in an archive, or if its hashes are the same as
+    /// those of a different path already added.
+    pub fn add<D: 'static + FileData>(&mut self, path: &str, data: D) -> Result<()> {
+        self.insert(path, None, Data::Local(Box::new(data)))
+    }
+
+    /// Add a file that can be read on another thread, such as a file on disk. It is
+    /// read on the thread pool along with being compressed, instead of on the thread
+    /// writing the archive. See [RawWriter::add].
+    pub fn add_send<D: 'static + FileData + Send>(&mut self, path: &str, data: D) -> Result<()> {
+        self.insert(path, None, Data::Send(Box::new(data)))
+    }
+
+    /// Add a file that is compressed, or not, regardless of the archive's default.
+    pub fn add_with_compression<D: 'static + FileData>(
+        &mut self,
+        path: &str,
+        compressed: bool,
+        data: D,
+    ) -> Result<()> {
+        self.insert(path, Some(compressed), Data::Local(Box::new(data)))
+    }
+
+    /// Add a file as it is stored in another archive, keeping its compression. The
+    /// block is copied verbatim if its codec is the one used by this archive, and
+    /// decompressed and compressed again otherwise.
+    pub fn add_raw(&mut self, path: &str, block: RawBlock) -> Result<()> {
+        let compressed = block.compression.is_some();
+        self.insert(path, Some(compressed), Data::Send(Box::new(block)))
+    }
+
+    fn insert(&mut self, path: &str, compressed: Option<bool>, data: Data) -> Result<()> {
+        let path = ParsedPath::new(path)?;
+        self.check_collision(&path)?;
+
+        let dir = self.dirs.entry(path.dir_hash).or_insert_with(|| Dir {
+            name: path.dir_name,
+            files: BTreeMap::new(),
+        });
+        let file = File {
+            name: path.file_name,
+            compressed,
+            added: self.added,
+            data,
+        };
+        self.added += 1;
+        dir.files.insert(path.file_hash, file);
+        Ok(())
+    }
+
+    /// Fail if the hashes of `path` are the same as those of a different path.
+    fn check_collision(&self, path: &ParsedPath) -> Result<()> {
+        if let Some(dir) = self.dirs.get(&path.dir_hash) {
+            if dir.name != path.dir_name {
+                return Err(collision(&dir.name, &path.dir_name).into());
+            }
+            if let Some(existing) = dir.files.get(&path.file_hash) {
+                if existing.name != path.file_name {
+                    return Err(collision(&existing.name, &path.file_name).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a file has been added at `path`.
+    pub fn contains(&self, path: &str) -> bool {
+        match hash_file_path(path) {
+            Some((dir_hash, file_hash)) => self
+                .dirs
+                .get(&dir_hash)
+                .is_some_and(|dir| dir.files.contains_key(&file_hash)),
+            None => false,
+        }
+    }
+
+    /// Move the file at `from` to `to`, which may be in a different directory,
+    /// replacing any file at `to`. Returns whether a file was present at `from`.
+    pub(crate) fn rename(&mut self, from: &str, to: &str) -> Result<bool> {
+        let to = ParsedPath::new(to)?;
+        self.check_collision(&to)?;
+        let file = match self.take(from) {
+            Some(file) => file,
+            None => return Ok(false),
+        };
+
+        let dir = self.dirs.entry(to.dir_hash).or_insert_with(|| Dir {
+            name: to.dir_name,
+            files: BTreeMap::new(),
+        });
+        let file = File {
+            name: to.file_name,
+            ..file
+        };
+        dir.files.insert(to.file_hash, file);
+        Ok(true)
+    }
+
+    fn take(&mut self, path: &str) -> Option<File> {
+        let (dir_hash, file_hash) = hash_file_path(path)?;
+        let dir = self.dirs.get_mut(&dir_hash)?;
+        let file = dir.files.remove(&file_hash);
+        if dir.files.is_empty() {
+            self.dirs.remove(&dir_hash);
+        }
+        file
+    }
+
+    /// Remove the file at `path`, returning whether it was present.
+    pub fn remove(&mut self, path: &str) -> bool {
+        self.take(path).is_some()
+    }
+
+    /// The number of files added.
+    pub fn len(&self) -> usize {
+        self.dirs.values().map(|dir| dir.files.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Write the archive.
+    ///
+    /// Record blocks are written with placeholder values first, and filled in once
+    /// the sizes of the compressed files are known.
+    pub fn write_to<W: Write + Seek>(mut self, mut w: W) -> Result<WriteSummary> {
+        let start = w.stream_position()?;
+
+        let include_dir_names = self.archive_flags.contains(ArchiveFlags::INCLUDE_DIRNAMES);
+        let include_file_names = self.archive_flags.contains(ArchiveFlags::INCLUDE_FILENAMES);
+        let embed_file_names = self.version != Version::V103
+            && self.archive_flags.contains(ArchiveFlags::EMBED_FILENAMES);
+        let default_compressed = self.archive_flags.contains(ArchiveFlags::COMPRESSED);
+        let compression = self.compression();
+        let file_flags = self.file_flags();
+        let deduplicate = self.deduplicate && !embed_file_names;
+        let max_len = self.max_len;
+
+        let folder_count = self.dirs.len() as u32;
+        let file_count = self.len() as u32;
+        let total_folder_name_len: u32 = self
+            .dirs
+            .values()
+            .map(|dir| dir.name.len() as u32 + 1)
+            .sum();
+        let total_file_name_len: u32 = self
+            .dirs
+            .values()
+            .flat_map(|dir| dir.files.values())
+            .map(|file| file.name.len() as u32 + 1)
+            .sum();
+
+        let mut header = Vec::with_capacity(36);
+        header.extend_from_slice(MAGIC);
+        for value in [
+            self.version.number(),
+            36,
+            self.archive_flags.bits(),
+            folder_count,
+            file_count,
+            total_folder_name_len,
+            total_file_name_len,
+            file_flags.bits() as u32,
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        w.write_all(&header)?;
+
+        let folder_record_len = if self.version == Version::V105 {
+            24
+        } else {
+            16
+        };
+        let records_start = 36 + folder_count as u64 * folder_record_len;
+        let mut record_offset = records_start;
+        for (&hash, dir) in &self.dirs {
+            // The offset of each file record block, plus the length of the file
+            // names block, as written by the official tools.
+            let offset = record_offset + total_file_name_len as u64;
+            let mut record = Vec::with_capacity(folder_record_len as usize);
+            record.extend_from_slice(&hash.to_bytes());
+            record.extend_from_slice(&(dir.files.len() as u32).to_le_bytes());
+            if self.version == Version::V105 {
+                record.extend_from_slice(&0u32.to_le_bytes());
+                record.extend_from_slice(&offset.to_le_bytes());
+            } else {
+                let offset: u32 = offset.try_into().map_err(|_| WriteError::TooLarge)?;
+                record.extend_from_slice(&offset.to_le_bytes());
+            }
+            w.write_all(&record)?;
+
+            if include_dir_names {
+                record_offset += dir.name.len() as u64 + 2;
+            }
+            record_offset += dir.files.len() as u64 * 16;
+        }
+
+        let file_record_blocks_pos = w.stream_position()?;
+        w.write_all(&vec![0; (record_offset - records_start) as usize])?;
+
+        if include_file_names {
+            for file in self.dirs.values().flat_map(|dir| dir.files.values()) {
+                w.write_all(&file.name)?;
+                w.write_all(b"\0")?;
+            }
+        }
+
+        let mut summary = WriteSummary {
+            files: file_count as usize,
+            ..WriteSummary::default()
+        };
+        let mut written = HashMap::new();
+        // The record of every file, in the order of the file record blocks.
+        let mut records = Vec::with_capacity(file_count as usize);
+        let mut files = Vec::with_capacity(file_count as usize);
+        for dir in self.dirs.values() {
+            for (&hash, file) in &dir.files {
+                let compressed = file
+                    .compressed
+                    .unwrap_or_else(|| self.compressed_by_rules(&file.name, file_flags));
+                let mut name = dir.name.clone();
+                name.push(b'\\');
+                name.extend_from_slice(&file.name);
+                files.push((records.len(), name, compressed, file.added));
+                records.push((hash, 0, 0));
+            }
+        }
+        let mut data: Vec<_> = self
+            .dirs
+            .values_mut()
+            .flat_map(|dir| dir.files.values_mut())
+            .map(|file| mem::replace(&mut file.data, Data::Local(Box::new(Vec::new()))))
+            .map(Some)
+            .collect();
+        if self.data_order == DataOrder::Added {
+            files.sort_by_key(|&(_, _, _, added)| added);
+        }
+
+        // Files are read and compressed on the pool, and written here in order.
+        let policy = Arc::new(self.policy.clone());
+        let mut pipeline: Pipeline<Result<_>> = Pipeline::new(self.threads, self.threads * 2);
+        let mut jobs = files.iter();
+        for (record, name, _, _) in &files {
+            while !pipeline.is_full() {
+                let &(record, _, compressed, _) = match jobs.next() {
+                    Some(job) => job,
+                    None => break,
+                };
+                let input = match data[record].take().unwrap() {
+                    Data::Local(mut data) => match data.raw()? {
+                        Some(raw) => Input::Raw(raw),
+                        None => {
+                            let mut buf = Vec::new();
+                            data.write_to(&mut buf)?;
+                            Input::Contents(buf)
+                        }
+                    },
+                    Data::Send(data) => Input::Data(data),
+                };
+                let codec = if compressed { Some(compression) } else { None };
+                let policy = policy.clone();
+                pipeline.submit(move || {
+                    let raw = encode(input, codec, &policy)?;
+                    let digest = deduplicate.then(|| digest(&raw));
+                    Ok((raw, digest))
+                });
+            }
+            let (raw, digest) = pipeline.next_result().unwrap()??;
+            let compressed = raw.compression.is_some();
+            let record = &mut records[*record];
+
+            if let Some(&(len, offset)) = digest.and_then(|digest| written.get(&digest)) {
+                summary.deduplicated += 1;
+                summary.bytes_saved += (len & !COMPRESSION_TOGGLE) as u64;
+                *record = (record.0, len, offset);
+                continue;
+            }
+
+            let offset = w.stream_position()? - start;
+            let offset: u32 = offset.try_into().map_err(|_| WriteError::TooLarge)?;
+
+            if embed_file_names {
+                let len: u8 = name.len().try_into().map_err(|_| {
+                    WriteError::InvalidPath(String::from_utf8_lossy(name).into_owned())
+                })?;
+                w.write_all(&[len])?;
+                w.write_all(name)?;
+            }
+
+            write_raw(&mut w, &raw)?;
+
+            let len = w.stream_position()? - start - offset as u64;
+            if max_len.is_some_and(|max_len| offset as u64 + len > max_len) {
+                let name = String::from_utf8_lossy(name).into_owned();
+                return Err(WriteError::ExceedsLimit(name).into());
+            }
+            if len >= COMPRESSION_TOGGLE as u64 {
+                return Err(WriteError::TooLarge.into());
+            }
+            let mut len = len as u32;
+            if compressed != default_compressed {
+                len |= COMPRESSION_TOGGLE;
+            }
+            if let Some(digest) = digest {
+                written.insert(digest, (len, offset));
+            }
+            *record = (record.0, len, offset);
+        }
+
+        let end = w.stream_position()?;
+        w.seek(SeekFrom::Start(file_record_blocks_pos))?;
+        let mut records = records.into_iter();
+        let mut block = Vec::new();
+        for dir in self.dirs.values() {
+            block.clear();
+            if include_dir_names {
+                block.push(dir.name.len() as u8 + 1);
+                block.extend_from_slice(&dir.name);
+                block.push(0);
+            }
+            for _ in 0..dir.files.len() {
+                let (hash, len, offset) = records.next().unwrap();
+                block.extend_from_slice(&hash.to_bytes());
+                block.extend_from_slice(&len.to_le_bytes());
+                block.extend_from_slice(&offset.to_le_bytes());
+            }
+            w.write_all(&block)?;
+        }
+        w.seek(SeekFrom::Start(end))?;
+        w.flush()?;
+
+        Ok(summary)
+    }
+}
+
+/// A path split into its directory and file name, as they are stored in an archive.
+struct ParsedPath {
+    dir_hash: Hash,
+    dir_name: Vec<u8>,
+    file_hash: Hash,
+    file_name: Vec<u8>,
+}
+
+impl ParsedPath {
+    fn new(path: &str) -> Result<ParsedPath> {
+        let invalid = || WriteError::InvalidPath(path.to_owned());
+
+        let path = path.to_lowercase();
+        let mut components: Vec<_> = path
+            .split(['\\', '/'])
+            .filter(|component| !component.is_empty())
+            .collect();
+        let file_name = components.pop().ok_or_else(invalid)?;
+        let dir_name = components.join("\\");
+        Ok(ParsedPath {
+            dir_hash: hash_directory_name(&dir_name).ok_or_else(invalid)?,
+            dir_name: encode_name(&dir_name).ok_or_else(invalid)?,
+            file_hash: hash_file_name(file_name).ok_or_else(invalid)?,
+            file_name: encode_name(file_name).ok_or_else(invalid)?,
+        })
+    }
+}
+
+fn collision(existing: &[u8], new: &[u8]) -> WriteError {
+    WriteError::HashCollision(
+        String::from_utf8_lossy(existing).into_owned(),
+        String::from_utf8_lossy(new).into_owned(),
+    )
+}
+
+/// Encode a name as Windows-1252, the way it is stored in an archive. Names are at
+/// most 254 bytes, so that their length fits in a byte along with a terminating nul.
+fn encode_name(name: &str) -> Option<Vec<u8>> {
+    let name = windows_1252::encode_string(name.to_owned()).ok()?;
+    if name.len() < 255 {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Compress `data` with `compression`, at the level `policy` sets for that codec,
+/// into a [RawBlock].
+pub(crate) fn compress(
+    data: &[u8],
+    compression: Option<Compression>,
+    policy: &CompressionPolicy,
+) -> Result<RawBlock> {
+    let uncompressed_len = match compression {
+        Some(_) => Some(u32::try_from(data.len()).map_err(|_| WriteError::TooLarge)?),
+        None => None,
+    };
+    let data = match compression {
+        Some(Compression::Zlib) => {
+            let level = flate2::Compression::new(policy.zlib_level());
+            let mut encoder = ZlibEncoder::new(Vec::new(), level);
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+        Some(Compression::Lz4) if policy.lz4_level() < 3 => {
+            let mut encoder = FrameEncoder::new(Vec::new());
+            encoder.write_all(data)?;
+            encoder.finish().map_err(io::Error::from)?
+        }
+        Some(Compression::Lz4) => {
+            // The same frame settings as the fast encoder, with the high compression
+            // encoder of the reference implementation.
+            let mut encoder = lz4::EncoderBuilder::new()
+                .level(policy.lz4_level())
+                .block_size(lz4::BlockSize::Max64KB)
+                .block_mode(lz4::BlockMode::Independent)
+                .checksum(lz4::ContentChecksum::NoChecksum)
+                .build(Vec::new())?;
+            encoder.write_all(data)?;
+            let (data, result) = encoder.finish();
+            result?;
+            data
+        }
+        None => data.to_vec(),
+    };
+    Ok(RawBlock {
+        compression,
+        uncompressed_len,
+        data,
+    })
+}
+
+/// Compress the contents of a file with `codec`, or store them uncompressed if
+/// compressing them does not save space, or does not save enough for `policy`. Stored
+/// blocks are reused when their codec matches.
+fn encode(
+    input: Input,
+    codec: Option<Compression>,
+    policy: &CompressionPolicy,
+) -> Result<RawBlock> {
+    let contents = match input {
+        Input::Raw(raw) if raw.compression == codec => return Ok(raw),
+        Input::Raw(raw) => {
+            let mut contents = Vec::new();
+            raw.decompress_to(&mut contents)?;
+            contents
+        }
+        Input::Contents(contents) => contents,
+        Input::Data(mut data) => match data.raw()? {
+            Some(raw) if raw.compression == codec => return Ok(raw),
+            _ => {
+                let mut contents = Vec::new();
+                data.write_to(&mut contents)?;
+                contents
+            }
+        },
+    };
+
+    let raw = compress(&contents, codec, policy)?;
+    let stored_len = raw.data.len() as u64 + 4;
+    let uncompressed_len = contents.len() as u64;
+    if codec.is_some()
+        && (stored_len >= uncompressed_len
+            || !policy.keeps_compressed(uncompressed_len, stored_len))
+    {
+        compress(&contents, None, policy)
+    } else {
+        Ok(raw)
+    }
+}
+
+/// Identifies stored contents, for deduplication.
+fn digest(raw: &RawBlock) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([raw.compression.is_some() as u8]);
+    if let Some(len) = raw.uncompressed_len {
+        hasher.update(len.to_le_bytes());
+    }
+    hasher.update(&raw.data);
+    hasher.finalize().into()
+}
+
+fn write_raw<W: Write>(mut w: W, raw: &RawBlock) -> Result<()> {
+    if let Some(len) = raw.uncompressed_len {
+        w.write_all(&len.to_le_bytes())?;
+    }
+    w.write_all(&raw.data)?;
+    Ok(())
+}
+
+/// Writes an archive for a specific game.
+pub struct BsaWriter<A: Bsa> {
+    inner: RawWriter,
+    _marker: PhantomData<A>,
+}
+
+impl<A: Bsa> BsaWriter<A> {
+    pub fn new() -> BsaWriter<A> {
+        BsaWriter {
+            inner: RawWriter::new(A::VERSION),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn archive_flags(&self) -> ArchiveFlags {
+        self.inner.archive_flags()
+    }
+
+    pub fn set_archive_flags(&mut self, flags: ArchiveFlags) {
+        self.inner.set_archive_flags(flags)
+    }
+
+    /// The file flags, as set or as inferred. See [RawWriter::file_flags].
+    pub fn file_flags(&self) -> FileFlags {
+        self.inner.file_flags()
+    }
+
+    /// Set the file flags, instead of inferring them.
+    pub fn set_file_flags(&mut self, flags: FileFlags) {
+        self.inner.set_file_flags(flags)
+    }
+
+    /// Set whether files are compressed by default.
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.inner.set_compressed(compressed)
+    }
+
+    pub fn policy(&self) -> &CompressionPolicy {
+        self.inner.policy()
+    }
+
+    pub fn set_policy(&mut self, policy: CompressionPolicy) {
+        self.inner.set_policy(policy)
+    }
+
+    /// Set whether files are compressed when the archive's file flags include any of
+    /// `flags`. See [RawWriter::add_file_flags_rule].
+    pub fn add_file_flags_rule(&mut self, flags: FileFlags, compressed: bool) {
+        self.inner.add_file_flags_rule(flags, compressed)
+    }
+
+    /// Set whether the full path of every file is embedded before its data. Version
+    /// 103 archives do not support this.
+    pub fn set_embed_file_names(&mut self, embed: bool) {
+        self.inner.set_embed_file_names(embed)
+    }
+
+    /// Set whether files with identical stored contents share their data. See
+    /// [RawWriter::set_deduplicate].
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.inner.set_deduplicate(deduplicate)
+    }
+
+    /// Set the number of threads reading and compressing files. Defaults to the
+    /// number of CPUs.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.inner.set_threads(threads)
+    }
+
+    /// Set the order in which the data of files is written. Defaults to
+    /// [DataOrder::Hash].
+    pub fn set_data_order(&mut self, order: DataOrder) {
+        self.inner.set_data_order(order)
+    }
+
+    /// Set the largest the archive may be, in bytes. See [RawWriter::set_max_len].
+    pub fn set_max_len(&mut self, max_len: Option<u64>) {
+        self.inner.set_max_len(max_len)
+    }
+
+    /// Add a file, replacing any file previously added with the same path. See
+    /// [RawWriter::add].
+    pub fn add<D: 'static + FileData>(&mut self, path: &str, data: D) -> Result<()> {
+        self.inner.add(path, data)
+    }
+
+    /// Add a file that can be read on another thread. See [RawWriter::add_send].
+    pub fn add_send<D: 'static + FileData + Send>(&mut self, path: &str, data: D) -> Result<()> {
+        self.inner.add_send(path, data)
+    }
+
+    /// Add a file that is compressed, or not, regardless of the archive's default.
+    pub fn add_with_compression<D: 'static + FileData>(
+        &mut self,
+        path: &str,
+        compressed: bool,
+        data: D,
+    ) -> Result<()> {
+        self.inner.add_with_compression(path, compressed, data)
+    }
+
+    /// Add a file as it is stored in another archive. See [RawWriter::add_raw].
+    pub fn add_raw(&mut self, path: &str, block: RawBlock) -> Result<()> {
+        self.inner.add_raw(path, block)
+    }
+
+    /// Whether a file has been added at `path`.
+    pub fn contains(&self, path: &str) -> bool {
+        self.inner.contains(path)
+    }
+
+    /// Remove the file at `path`, returning whether it was present.
+    pub fn remove(&mut self, path: &str) -> bool {
+        self.inner.remove(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn write_to<W: Write + Seek>(self, w: W) -> Result<WriteSummary> {
+        self.inner.write_to(w)
+    }
+}
+
+impl<A: Bsa> Default for BsaWriter<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}